@@ -1,6 +1,7 @@
 package club.doki7.sennaar.cpl
 
 import club.doki7.sennaar.Identifier
+import club.doki7.sennaar.registry.Type
 import kotlinx.serialization.SerialName
 import kotlinx.serialization.Serializable
 
@@ -67,6 +68,17 @@ data class CUnaryExpr(var expr: CExpr, var op: CUnaryOp) : CExpr
 @SerialName("Cast")
 data class CCastExpr(var expr: CExpr, var type: CExpr) : CExpr
 
+@Serializable
+enum class CTypeOperator { SizeOf, AlignOf }
+
+@Serializable
+@SerialName("TypeOperator")
+data class CTypeOperatorExpr(var op: CTypeOperator, var ty: CExpr) : CExpr
+
+@Serializable
+@SerialName("OffsetOf")
+data class COffsetOfExpr(var ty: CExpr, var member: CExpr) : CExpr
+
 @Serializable
 enum class CBinaryOp {
     Mul, Div, Mod,
@@ -95,3 +107,7 @@ data class CConditionalExpr(var cond: CExpr, var then: CExpr, var otherwise: CEx
 @Serializable
 @SerialName("Paren")
 data class CParenExpr(var expr: CExpr) : CExpr
+
+@Serializable
+@SerialName("Type")
+data class CTypeExpr(var ty: Type) : CExpr
@@ -0,0 +1,33 @@
+package club.doki7.sennaar.registry
+
+import club.doki7.sennaar.Identifier
+import kotlinx.serialization.SerialName
+import kotlinx.serialization.Serializable
+
+@Serializable
+data class Doc(
+    var brief: MutableList<DocSpan> = mutableListOf(),
+    var description: MutableList<MutableList<DocSpan>> = mutableListOf(),
+    var params: MutableList<DocParam> = mutableListOf(),
+    var returns: MutableList<DocSpan> = mutableListOf(),
+    var see: MutableList<Identifier> = mutableListOf(),
+    var deprecated: MutableList<DocSpan>? = null
+)
+
+@Serializable
+data class DocParam(var name: Identifier, var text: MutableList<DocSpan>)
+
+@Serializable
+sealed interface DocSpan {
+    @Serializable
+    @SerialName("Text")
+    data class Text(var value: String) : DocSpan
+
+    @Serializable
+    @SerialName("Code")
+    data class Code(var value: String) : DocSpan
+
+    @Serializable
+    @SerialName("Link")
+    data class Link(var target: Identifier) : DocSpan
+}
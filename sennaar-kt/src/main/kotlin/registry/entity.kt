@@ -9,7 +9,7 @@ sealed class Entity {
     abstract var name: Identifier
 
     var metadata: MutableMap<String, Metadata> = mutableMapOf()
-    var doc: MutableList<String> = mutableListOf()
+    var doc: Doc = Doc()
     var platform: Platform? = null
 
     final override fun equals(other: Any?): Boolean {
@@ -70,7 +70,8 @@ sealed class Entity {
 @Serializable
 data class Typedef(
     override var name: Identifier,
-    var target: Type
+    var target: Type,
+    var platformVariants: MutableList<Typedef> = mutableListOf()
 ) : Entity() {
     constructor(
         name: String,
@@ -85,7 +86,8 @@ enum class Bitwidth { Bit32, Bit64 }
 data class Bitmask(
     override var name: Identifier,
     var bitwidth: Bitwidth,
-    var bitflags: MutableList<Bitflag>
+    var bitflags: MutableList<Bitflag>,
+    var platformVariants: MutableList<Bitmask> = mutableListOf()
 ) : Entity() {
     constructor(
         name: String,
@@ -105,6 +107,11 @@ data class Bitflag(
     ) : this(name.interned(), value)
 }
 
+@Serializable
+enum class CallingConvention {
+    StdCall, FastCall, ThisCall, VectorCall, RegCall, Pascal, SysV, Win64, Aapcs, AapcsVfp, Swift
+}
+
 @Serializable
 data class Command(
     override var name: Identifier,
@@ -112,7 +119,10 @@ data class Command(
     var result: Type,
     var successCodes: MutableList<CExpr>,
     var errorCodes: MutableList<CExpr>,
-    var aliasTo: Identifier?
+    var aliasTo: Identifier?,
+    var variadic: Boolean = false,
+    var callingConvention: CallingConvention? = null,
+    var platformVariants: MutableList<Command> = mutableListOf()
 ) : Entity() {
     constructor(
         name: String,
@@ -165,7 +175,8 @@ data class Param(
 data class Constant(
     override var name: Identifier,
     var ty: Type,
-    var expr: CExpr
+    var expr: CExpr,
+    var platformVariants: MutableList<Constant> = mutableListOf()
 ) : Entity() {
     constructor(
         name: String,
@@ -177,7 +188,8 @@ data class Constant(
 @Serializable
 data class Enumeration(
     override var name: Identifier,
-    var variants: MutableList<EnumVariant>
+    var variants: MutableList<EnumVariant>,
+    var platformVariants: MutableList<Enumeration> = mutableListOf()
 ) : Entity() {
     constructor(
         name: String,
@@ -202,7 +214,10 @@ data class FunctionTypedef(
     var params: MutableList<Param>,
     var result: Type,
     var isPointer: Boolean,
-    var isNativeAPI: Boolean
+    var isNativeAPI: Boolean,
+    var variadic: Boolean = false,
+    var callingConvention: CallingConvention? = null,
+    var platformVariants: MutableList<FunctionTypedef> = mutableListOf()
 ) : Entity() {
     constructor(
         name: String,
@@ -222,19 +237,42 @@ data class FunctionTypedef(
 }
 
 @Serializable
-data class OpaqueTypedef(override var name: Identifier) : Entity() {
+data class Macro(
+    override var name: Identifier,
+    var params: MutableList<Identifier>,
+    var variadic: Boolean,
+    var body: CExpr,
+    var platformVariants: MutableList<Macro> = mutableListOf()
+) : Entity() {
+    constructor(
+        name: String,
+        params: MutableList<Identifier>,
+        variadic: Boolean,
+        body: CExpr
+    ) : this(name.interned(), params, variadic, body)
+}
+
+@Serializable
+data class OpaqueTypedef(
+    override var name: Identifier,
+    var platformVariants: MutableList<OpaqueTypedef> = mutableListOf()
+) : Entity() {
     constructor(name: String) : this(name.interned())
 }
 
 @Serializable
-data class OpaqueHandleTypedef(override var name: Identifier) : Entity() {
+data class OpaqueHandleTypedef(
+    override var name: Identifier,
+    var platformVariants: MutableList<OpaqueHandleTypedef> = mutableListOf()
+) : Entity() {
     constructor(name: String) : this(name.interned())
 }
 
 @Serializable
 data class Structure(
     override var name: Identifier,
-    var members: MutableList<Member>
+    var members: MutableList<Member>,
+    var platformVariants: MutableList<Structure> = mutableListOf()
 ) : Entity() {
     constructor(
         name: String,
@@ -19,6 +19,12 @@ data class ArrayType(var element: Type, var length: CExpr?) : Type
 data class PointerType(
     var pointee: Type,
     var isConst: Boolean,
+    var isVolatile: Boolean = false,
+    var isAtomic: Boolean = false,
+    var isRestrict: Boolean = false,
+    var isPointerConst: Boolean = false,
+    var isPointerVolatile: Boolean = false,
+    var isPointerAtomic: Boolean = false,
     var pointerToOne: Boolean,
     var nullable: Boolean
 ) : Type
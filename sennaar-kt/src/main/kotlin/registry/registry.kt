@@ -15,6 +15,7 @@ interface IRegistry {
     var constants: MutableMap<Identifier, Constant>
     var enumerations: MutableMap<Identifier, Enumeration>
     var functionTypedefs: MutableMap<Identifier, FunctionTypedef>
+    var macros: MutableMap<Identifier, Macro>
     var opaqueTypedefs: MutableMap<Identifier, OpaqueTypedef>
     var opaqueHandleTypedefs: MutableMap<Identifier, OpaqueHandleTypedef>
     var structs: MutableMap<Identifier, Structure>
@@ -39,6 +40,7 @@ interface IRegistry {
         constants.putAll(other.constants)
         enumerations.putAll(other.enumerations)
         functionTypedefs.putAll(other.functionTypedefs)
+        macros.putAll(other.macros)
         opaqueTypedefs.putAll(other.opaqueTypedefs)
         opaqueHandleTypedefs.putAll(other.opaqueHandleTypedefs)
         structs.putAll(other.structs)
@@ -57,6 +59,7 @@ data class Registry(
     override var constants: MutableMap<Identifier, Constant>,
     override var enumerations: MutableMap<Identifier, Enumeration>,
     override var functionTypedefs: MutableMap<Identifier, FunctionTypedef>,
+    override var macros: MutableMap<Identifier, Macro> = mutableMapOf(),
     override var opaqueTypedefs: MutableMap<Identifier, OpaqueTypedef>,
     override var opaqueHandleTypedefs: MutableMap<Identifier, OpaqueHandleTypedef>,
     override var structs: MutableMap<Identifier, Structure>,
@@ -73,6 +76,7 @@ data class Registry(
         constants = mutableMapOf(),
         enumerations = mutableMapOf(),
         functionTypedefs = mutableMapOf(),
+        macros = mutableMapOf(),
         opaqueTypedefs = mutableMapOf(),
         opaqueHandleTypedefs = mutableMapOf(),
         structs = mutableMapOf(),
@@ -91,6 +95,7 @@ data class RegistryTE<EXT>(
     override var constants: MutableMap<Identifier, Constant>,
     override var enumerations: MutableMap<Identifier, Enumeration>,
     override var functionTypedefs: MutableMap<Identifier, FunctionTypedef>,
+    override var macros: MutableMap<Identifier, Macro> = mutableMapOf(),
     override var opaqueTypedefs: MutableMap<Identifier, OpaqueTypedef>,
     override var opaqueHandleTypedefs: MutableMap<Identifier, OpaqueHandleTypedef>,
     override var structs: MutableMap<Identifier, Structure>,
@@ -107,6 +112,7 @@ data class RegistryTE<EXT>(
         constants = mutableMapOf(),
         enumerations = mutableMapOf(),
         functionTypedefs = mutableMapOf(),
+        macros = mutableMapOf(),
         opaqueTypedefs = mutableMapOf(),
         opaqueHandleTypedefs = mutableMapOf(),
         structs = mutableMapOf(),
@@ -0,0 +1,276 @@
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use schemars::schema_for;
+use sennaar::Diagnostic;
+use sennaar::registry::{
+    parse_registry, parse_registry_xml, validate_registry, write_registry, write_registry_xml,
+    Registry,
+};
+use sennaar::rossetta::clang_config::{Filter, ParseConfig};
+use sennaar::rossetta::clang_extract::{extract_registry, ExtractMode};
+
+#[derive(Parser)]
+#[command(name = "sennaar", version, about = "C API metadata registry tool")]
+struct Cli {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Subcommand)]
+enum Action {
+    /// Convert a registry from one format to another
+    Convert {
+        input: PathBuf,
+        /// Output file, standard output if omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Input format, guessed from the file extension if omitted
+        #[arg(long)]
+        from: Option<Format>,
+        /// Output format, guessed from the output file extension if omitted
+        #[arg(long)]
+        to: Option<Format>,
+    },
+    /// Check registries for inconsistencies and references to undeclared entities
+    Validate {
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Registries whose entities may be referred to, but are not checked themselves
+        #[arg(long)]
+        import: Vec<PathBuf>,
+    },
+    /// Print the JSON schema of registries
+    Schema {
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Extract a registry from a C header
+    Extract {
+        header: PathBuf,
+        /// Registry name, the header file stem if omitted
+        #[arg(long)]
+        name: Option<String>,
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Output format, guessed from the output file extension if omitted, JSON by default
+        #[arg(long)]
+        to: Option<Format>,
+        /// Leave out declarations that cannot be mapped, with a warning, instead of failing
+        #[arg(long)]
+        skip_unsupported: bool,
+        #[command(flatten)]
+        parse: Box<ParseArgs>,
+    },
+}
+
+#[derive(Args)]
+struct ParseArgs {
+    /// Directory searched for `#include`
+    #[arg(short = 'I', long = "include-dir", value_name = "DIR")]
+    include_dirs: Vec<String>,
+    /// Macro defined before the header
+    #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]")]
+    defines: Vec<String>,
+    /// Target triple, the host if omitted
+    #[arg(long)]
+    target: Option<String>,
+    #[arg(long)]
+    sysroot: Option<String>,
+    /// Language standard, like c11
+    #[arg(long)]
+    std: Option<String>,
+    /// Regex of the entity names to extract, all if omitted
+    #[arg(long, value_name = "REGEX")]
+    allow: Vec<String>,
+    /// Regex of the entity names not to extract
+    #[arg(long, value_name = "REGEX")]
+    block: Vec<String>,
+    /// Regex of the included files whose declarations are extracted too
+    #[arg(long, value_name = "REGEX")]
+    allow_file: Vec<String>,
+    /// Regex of the files whose declarations are not extracted
+    #[arg(long, value_name = "REGEX")]
+    block_file: Vec<String>,
+    /// Arguments passed to clang, after `--`
+    #[arg(last = true)]
+    clang_args: Vec<String>,
+}
+
+impl ParseArgs {
+    fn config(self) -> Result<ParseConfig, String> {
+        let filter = |allow, block| Filter::new(allow, block).map_err(|e| format!("invalid regex: {}", e));
+        Ok(ParseConfig {
+            names: filter(&self.allow, &self.block)?,
+            files: filter(&self.allow_file, &self.block_file)?,
+            include_dirs: self.include_dirs,
+            defines: self
+                .defines
+                .into_iter()
+                .map(|define| match define.split_once('=') {
+                    Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                    None => (define, None),
+                })
+                .collect(),
+            target: self.target,
+            sysroot: self.sysroot,
+            std: self.std,
+            clang_args: self.clang_args,
+        })
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Json,
+    Dsl,
+    Xml,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "sennaar" | "dsl" => Some(Format::Dsl),
+            "xml" => Some(Format::Xml),
+            _ => None,
+        }
+    }
+
+    fn resolve(explicit: Option<Format>, path: Option<&Path>) -> Result<Format, String> {
+        explicit.or_else(|| path.and_then(Format::from_path)).ok_or_else(|| match path {
+            Some(path) => format!("cannot tell the format of '{}', use --from/--to", path.display()),
+            None => "no output file to tell the format from, use --to".to_string(),
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match cli.action {
+        Action::Convert { input, output, from, to } => convert(&input, output.as_deref(), from, to),
+        Action::Validate { inputs, import } => validate(&inputs, &import),
+        Action::Schema { output } => {
+            let schema = schema_for!(Registry);
+            emit(output.as_deref(), serde_json::to_string_pretty(&schema).unwrap())
+        }
+        Action::Extract { header, name, output, to, skip_unsupported, parse } => {
+            let mode = if skip_unsupported { ExtractMode::SkipUnsupported } else { ExtractMode::Strict };
+            parse.config().and_then(|config| extract(&header, name, output.as_deref(), to, mode, &config))
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    std::fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {}", path.display(), e))
+}
+
+fn emit(output: Option<&Path>, content: String) -> Result<(), String> {
+    match output {
+        Some(path) => std::fs::write(path, content)
+            .map_err(|e| format!("cannot write '{}': {}", path.display(), e)),
+        None => {
+            print!("{}", content);
+            Ok(())
+        }
+    }
+}
+
+fn load<'a>(path: &Path, source: &'a str, format: Format) -> Result<Registry<'a>, String> {
+    let file = path.display().to_string();
+    let result = match format {
+        Format::Json => Registry::from_json(&file, source),
+        Format::Dsl => parse_registry(&file, source),
+        Format::Xml => parse_registry_xml(&file, source),
+    };
+
+    result.map_err(|diagnostics: Vec<Diagnostic>| {
+        for diagnostic in &diagnostics {
+            eprintln!("{}\n", diagnostic);
+        }
+        format!("could not load '{}' due to {} previous error(s)", file, diagnostics.len())
+    })
+}
+
+fn store(registry: &Registry<'_>, format: Format) -> String {
+    match format {
+        Format::Json => serde_json::to_string_pretty(registry).unwrap() + "\n",
+        Format::Dsl => write_registry(registry),
+        Format::Xml => write_registry_xml(registry),
+    }
+}
+
+fn convert(
+    input: &Path,
+    output: Option<&Path>,
+    from: Option<Format>,
+    to: Option<Format>,
+) -> Result<(), String> {
+    let from = Format::resolve(from, Some(input))?;
+    let to = Format::resolve(to, output)?;
+
+    let source = read(input)?;
+    let registry = load(input, &source, from)?;
+    emit(output, store(&registry, to))
+}
+
+fn validate(inputs: &[PathBuf], imports: &[PathBuf]) -> Result<(), String> {
+    let paths = inputs.iter().chain(imports).collect::<Vec<_>>();
+    let sources = paths.iter().map(|path| read(path)).collect::<Result<Vec<_>, _>>()?;
+    let registries = paths
+        .iter()
+        .zip(&sources)
+        .map(|(path, source)| load(path, source, Format::resolve(None, Some(path))?))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut error_count = 0;
+    for (i, (path, registry)) in paths.iter().zip(&registries).enumerate().take(inputs.len()) {
+        // Inputs may refer to each other as well as to imports
+        let others = registries
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .map(|(_, other)| other.as_base())
+            .collect::<Vec<_>>();
+
+        for error in validate_registry(registry.as_base(), &others) {
+            eprintln!("{}: {}", path.display(), error);
+            error_count += 1;
+        }
+    }
+
+    if error_count == 0 {
+        Ok(())
+    } else {
+        Err(format!("validation failed with {} error(s)", error_count))
+    }
+}
+
+fn extract(
+    header: &Path,
+    name: Option<String>,
+    output: Option<&Path>,
+    to: Option<Format>,
+    mode: ExtractMode,
+    config: &ParseConfig,
+) -> Result<(), String> {
+    let to = to.or_else(|| output.and_then(Format::from_path)).unwrap_or(Format::Json);
+    let name = name.unwrap_or_else(|| {
+        header.file_stem().map_or("registry".to_string(), |stem| stem.to_string_lossy().into_owned())
+    });
+
+    let extraction = extract_registry(&header.to_string_lossy(), &name, config, mode)?;
+    for warning in &extraction.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    emit(output, store(&extraction.registry, to))
+}
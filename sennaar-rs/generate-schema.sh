@@ -1,3 +1,3 @@
 #!/usr/bin/env bash
 
-cargo run --bin generate_schema > ../sennaar.schema.json
\ No newline at end of file
+cargo run --bin sennaar -- schema > ../sennaar.schema.json
\ No newline at end of file
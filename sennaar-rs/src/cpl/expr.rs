@@ -4,6 +4,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::Identifier;
+use crate::registry::Type;
 
 
 include!("../macross.rs");
@@ -24,15 +25,70 @@ pub enum CExpr<'a> {
     PostfixIncDec(Box<CPostfixIncDecExpr<'a>>),
     Unary(Box<CUnaryExpr<'a>>),
     Cast(Box<CCastExpr<'a>>),
+    TypeOperator(Box<CTypeOperatorExpr<'a>>),
+    OffsetOf(Box<COffsetOfExpr<'a>>),
     Binary(Box<CBinaryExpr<'a>>),
     Conditional(Box<CConditionalExpr<'a>>),
     Paren(Box<CParenExpr<'a>>),
+    Type(Box<CTypeExpr<'a>>),
 }
 
 impl <'a> CExpr<'a> {
     pub fn identifier(ident: Identifier) -> CExpr<'a> {
         CExpr::Identifier(Box::new(CIdentifierExpr { ident }))
     }
+
+    /// Copy every borrowed literal, so that the expression outlives its source.
+    pub fn into_owned(self) -> CExpr<'static> {
+        let owned = |s: Cow<'a, str>| Cow::Owned(s.into_owned());
+        match self {
+            CExpr::IntLiteral(e) => CExpr::IntLiteral(Box::new(CIntLiteralExpr {
+                value: owned(e.value),
+                suffix: owned(e.suffix),
+            })),
+            CExpr::FloatLiteral(e) => CExpr::FloatLiteral(Box::new(CFloatLiteralExpr {
+                value: owned(e.value),
+                suffix: owned(e.suffix),
+            })),
+            CExpr::CharLiteral(e) => CExpr::CharLiteral(Box::new(CCharLiteralExpr { value: owned(e.value) })),
+            CExpr::StringLiteral(e) => CExpr::StringLiteral(Box::new(CStringLiteralExpr { value: owned(e.value) })),
+            CExpr::Identifier(e) => CExpr::Identifier(e),
+            CExpr::Index(e) => CExpr::Index(Box::new(CIndexExpr {
+                base: e.base.into_owned(),
+                index: e.index.into_owned(),
+            })),
+            CExpr::Call(e) => CExpr::Call(Box::new(CCallExpr {
+                callee: e.callee.into_owned(),
+                args: e.args.into_iter().map(CExpr::into_owned).collect(),
+            })),
+            CExpr::Member(e) => CExpr::Member(Box::new(CMemberExpr { obj: e.obj.into_owned(), member: e.member })),
+            CExpr::PtrMember(e) => {
+                CExpr::PtrMember(Box::new(CPtrMemberExpr { obj: e.obj.into_owned(), member: e.member }))
+            }
+            CExpr::PostfixIncDec(e) => {
+                CExpr::PostfixIncDec(Box::new(CPostfixIncDecExpr { expr: e.expr.into_owned(), op: e.op }))
+            }
+            CExpr::Unary(e) => CExpr::Unary(Box::new(CUnaryExpr { expr: e.expr.into_owned(), op: e.op })),
+            CExpr::Cast(e) => CExpr::Cast(Box::new(CCastExpr { expr: e.expr.into_owned(), ty: e.ty.into_owned() })),
+            CExpr::TypeOperator(e) => CExpr::TypeOperator(Box::new(CTypeOperatorExpr { op: e.op, ty: e.ty.into_owned() })),
+            CExpr::OffsetOf(e) => CExpr::OffsetOf(Box::new(COffsetOfExpr {
+                ty: e.ty.into_owned(),
+                member: e.member.into_owned(),
+            })),
+            CExpr::Binary(e) => CExpr::Binary(Box::new(CBinaryExpr {
+                op: e.op,
+                lhs: e.lhs.into_owned(),
+                rhs: e.rhs.into_owned(),
+            })),
+            CExpr::Conditional(e) => CExpr::Conditional(Box::new(CConditionalExpr {
+                cond: e.cond.into_owned(),
+                then: e.then.into_owned(),
+                otherwise: e.otherwise.into_owned(),
+            })),
+            CExpr::Paren(e) => CExpr::Paren(Box::new(CParenExpr { expr: e.expr.into_owned() })),
+            CExpr::Type(e) => CExpr::Type(Box::new(CTypeExpr { ty: e.ty.into_owned() })),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -134,6 +190,7 @@ pub struct CUnaryExpr<'a> {
     pub op: CUnaryOp,
 }
 
+/// Cast to a type, given as a [`CExpr::Type`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[derive(JsonSchema)]
 pub struct CCastExpr<'a> {
@@ -141,6 +198,25 @@ pub struct CCastExpr<'a> {
     pub ty: CExpr<'a>,
 }
 
+ss_enum!{CTypeOperator, SizeOf, AlignOf}
+
+/// `sizeof` or `alignof` of a type, given as a [`CExpr::Type`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(JsonSchema)]
+pub struct CTypeOperatorExpr<'a> {
+    pub op: CTypeOperator,
+    pub ty: CExpr<'a>,
+}
+
+/// `offsetof(ty, member)`, where `ty` is a [`CExpr::Type`] and `member` is a designator such as
+/// `a.b[2]`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(JsonSchema)]
+pub struct COffsetOfExpr<'a> {
+    pub ty: CExpr<'a>,
+    pub member: CExpr<'a>,
+}
+
 ss_enum!{
     CBinaryOp,
     Mul, Div, Mod,
@@ -179,6 +255,13 @@ pub struct CParenExpr<'a> {
     pub expr: CExpr<'a>,
 }
 
+/// Type name, as the operand of a cast, `sizeof`, `alignof` or `offsetof`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(JsonSchema)]
+pub struct CTypeExpr<'a> {
+    pub ty: Type<'a>,
+}
+
 impl <'a> Display for CExpr<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -211,9 +294,15 @@ impl <'a> Display for CExpr<'a> {
                 _ => write!(f, "{}{}", pre_op_describe(e.op), e.expr),
             },
             CExpr::Cast(c) => write!(f, "({}) {}", c.ty, c.expr),
+            CExpr::TypeOperator(e) => match e.op {
+                CTypeOperator::SizeOf => write!(f, "sizeof({})", e.ty),
+                CTypeOperator::AlignOf => write!(f, "alignof({})", e.ty),
+            },
+            CExpr::OffsetOf(e) => write!(f, "offsetof({}, {})", e.ty, e.member),
             CExpr::Binary(b) => write!(f, "{} {} {}", b.lhs, bin_op_describe(b.op), b.rhs),
             CExpr::Conditional(c) => write!(f, "{} ? {} : {}", c.cond, c.then, c.otherwise),
             CExpr::Paren(p) => write!(f, "({})", p.expr),
+            CExpr::Type(t) => write!(f, "{}", t.ty),
         }
     }
 }
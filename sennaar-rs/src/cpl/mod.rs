@@ -1,2 +1,4 @@
 mod expr;
-pub use expr::*;
\ No newline at end of file
+mod rawdecl;
+pub use expr::*;
+pub use rawdecl::*;
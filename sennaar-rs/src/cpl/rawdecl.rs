@@ -0,0 +1,505 @@
+use std::fmt::Display;
+
+use crate::{Identifier, Internalize};
+use crate::cpl::CExpr;
+use crate::registry::{ArrayType, IdentifierType, Platform, PointerType, Type};
+
+
+/// A C declaration as written, before the declarators are resolved into types.
+#[derive(Debug, Clone)]
+pub struct RawDeclaration<'a> {
+    pub specifiers: RawDeclSpecifiers<'a>,
+    pub declarators: Vec<RawInitDeclarator<'a>>,
+    /// Lines of the documentation comment of the declaration.
+    pub doc: Vec<String>,
+    /// Platform the declaration is visible on, if not all of them.
+    pub platform: Option<Platform>,
+}
+
+impl<'a> RawDeclaration<'a> {
+    /// Make a declaration without declarators, taking a typedef name that follows a complete type
+    /// as the declared name instead, as in a typedef repeated on another platform.
+    pub fn from_specifiers(mut specifiers: Vec<RawSpecifier<'a>>) -> Result<Self, String> {
+        let has_type = |specifiers: &[RawSpecifier<'a>]| specifiers.iter().any(|specifier| matches!(
+            specifier,
+            RawSpecifier::Keyword(_) | RawSpecifier::TypeName(_) | RawSpecifier::Record(_) | RawSpecifier::Enum(_)
+        ));
+        let mut declarators = Vec::new();
+        if let Some(RawSpecifier::TypeName(name)) = specifiers.last()
+            && has_type(&specifiers[..specifiers.len() - 1])
+        {
+            let declarator = RawDeclarator::Name(Some(name.clone()));
+            declarators.push(RawInitDeclarator { declarator, init: None });
+            specifiers.pop();
+        }
+        let specifiers = RawDeclSpecifiers::from_specifiers(specifiers)?;
+        Ok(Self { specifiers, declarators, doc: Vec::new(), platform: None })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RawInitDeclarator<'a> {
+    pub declarator: RawDeclarator<'a>,
+    pub init: Option<CExpr<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RawStorageClass {
+    Typedef,
+    Extern,
+    Static,
+    Auto,
+    Register,
+    ThreadLocal,
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct RawQualifiers {
+    pub is_const: bool,
+    pub is_volatile: bool,
+    pub is_restrict: bool,
+    pub is_atomic: bool,
+}
+
+impl RawQualifiers {
+    pub fn add(&mut self, qualifier: &str) {
+        match qualifier {
+            "const" => self.is_const = true,
+            "volatile" => self.is_volatile = true,
+            "restrict" => self.is_restrict = true,
+            "_Atomic" => self.is_atomic = true,
+            _ => unreachable!("not a type qualifier: {}", qualifier),
+        }
+    }
+
+    pub fn spelling(&self) -> Vec<&'static str> {
+        [
+            (self.is_const, "const"),
+            (self.is_volatile, "volatile"),
+            (self.is_restrict, "restrict"),
+            (self.is_atomic, "_Atomic"),
+        ]
+        .into_iter()
+        .filter_map(|(present, spelling)| present.then_some(spelling))
+        .collect()
+    }
+}
+
+/// Annotation macro use, such as `NULLABLE` or `_In_reads_(count)`.
+#[derive(Debug, Clone)]
+pub struct RawAnnotation<'a> {
+    pub name: Identifier,
+    pub args: Vec<CExpr<'a>>,
+}
+
+/// One item of a declaration specifier list, as parsed.
+#[derive(Debug, Clone)]
+pub enum RawSpecifier<'a> {
+    Storage(RawStorageClass),
+    Qualifier(&'static str),
+    Inline,
+    Noreturn,
+    Keyword(&'static str),
+    TypeName(Identifier),
+    Record(Box<RawRecord<'a>>),
+    Enum(Box<RawEnum<'a>>),
+    Annotation(RawAnnotation<'a>),
+}
+
+#[derive(Debug, Clone)]
+pub struct RawDeclSpecifiers<'a> {
+    pub storage: Option<RawStorageClass>,
+    pub qualifiers: RawQualifiers,
+    pub annotations: Vec<RawAnnotation<'a>>,
+    pub is_inline: bool,
+    pub is_noreturn: bool,
+    pub ty: RawTypeSpecifier<'a>,
+}
+
+impl<'a> RawDeclSpecifiers<'a> {
+    /// Combine a specifier list, rejecting lists that do not name exactly one type.
+    pub fn from_specifiers(specifiers: Vec<RawSpecifier<'a>>) -> Result<Self, String> {
+        let mut storage = None;
+        let mut qualifiers = RawQualifiers::default();
+        let mut annotations = Vec::new();
+        let mut is_inline = false;
+        let mut is_noreturn = false;
+        let mut keywords = Vec::new();
+        let mut ty = None;
+
+        for specifier in specifiers {
+            match specifier {
+                RawSpecifier::Storage(class) => {
+                    if storage.replace(class).is_some() {
+                        return Err("multiple storage classes in declaration specifiers".to_string());
+                    }
+                }
+                RawSpecifier::Qualifier(qualifier) => qualifiers.add(qualifier),
+                RawSpecifier::Annotation(annotation) => annotations.push(annotation),
+                RawSpecifier::Inline => is_inline = true,
+                RawSpecifier::Noreturn => is_noreturn = true,
+                RawSpecifier::Keyword(keyword) => keywords.push(keyword),
+                RawSpecifier::TypeName(ident) => {
+                    if ty.replace(RawTypeSpecifier::TypeName(ident)).is_some() {
+                        return Err("two or more data types in declaration specifiers".to_string());
+                    }
+                }
+                RawSpecifier::Record(record) => {
+                    if ty.replace(RawTypeSpecifier::Record(record)).is_some() {
+                        return Err("two or more data types in declaration specifiers".to_string());
+                    }
+                }
+                RawSpecifier::Enum(enumeration) => {
+                    if ty.replace(RawTypeSpecifier::Enum(enumeration)).is_some() {
+                        return Err("two or more data types in declaration specifiers".to_string());
+                    }
+                }
+            }
+        }
+
+        let ty = match (ty, keywords.is_empty()) {
+            (Some(ty), true) => ty,
+            (None, false) => RawTypeSpecifier::Builtin(builtin_spelling(&keywords)?),
+            (Some(_), false) => {
+                return Err("two or more data types in declaration specifiers".to_string());
+            }
+            (None, true) => return Err("declaration specifiers name no type".to_string()),
+        };
+
+        Ok(Self { storage, qualifiers, annotations, is_inline, is_noreturn, ty })
+    }
+}
+
+/// Spell a combination of type keywords in the canonical order, so that `long unsigned int`
+/// and `unsigned long` are the same type.
+pub(crate) fn builtin_spelling(keywords: &[&'static str]) -> Result<String, String> {
+    let count = |keyword: &str| keywords.iter().filter(|k| **k == keyword).count();
+    let (signed, unsigned, short, long, int) =
+        (count("signed"), count("unsigned"), count("short"), count("long"), count("int"));
+    let base = ["void", "char", "float", "double", "_Bool"]
+        .into_iter()
+        .filter(|k| count(k) > 0)
+        .collect::<Vec<_>>();
+    let invalid = || format!("invalid combination of type specifiers '{}'", keywords.join(" "));
+
+    if signed + unsigned > 1 || short + long.min(1) > 1 || long > 2 || int > 1 || base.len() > 1
+        || base.iter().any(|k| count(k) > 1)
+    {
+        return Err(invalid());
+    }
+
+    let complex = count("_Complex") > 0;
+    let mut spelling = Vec::new();
+    match base.first().copied() {
+        Some("char") if short + long + int == 0 => {
+            if signed > 0 {
+                spelling.push("signed");
+            } else if unsigned > 0 {
+                spelling.push("unsigned");
+            }
+            spelling.push("char");
+        }
+        Some("double") if signed + unsigned + short + int == 0 && long <= 1 => {
+            if long > 0 {
+                spelling.push("long");
+            }
+            spelling.push("double");
+        }
+        Some(base @ ("void" | "float" | "_Bool")) if signed + unsigned + short + long + int == 0 => {
+            spelling.push(base);
+        }
+        Some(_) => return Err(invalid()),
+        None if complex => return Err(invalid()),
+        None => {
+            if unsigned > 0 {
+                spelling.push("unsigned");
+            }
+            if short > 0 {
+                spelling.push("short");
+            }
+            spelling.extend(std::iter::repeat_n("long", long));
+            if short + long == 0 {
+                spelling.push("int");
+            }
+        }
+    }
+    if complex {
+        spelling.push("_Complex");
+    }
+
+    Ok(spelling.join(" "))
+}
+
+#[derive(Debug, Clone)]
+pub enum RawTypeSpecifier<'a> {
+    /// Combination of type keywords, spelled canonically as in `unsigned long`.
+    Builtin(String),
+    TypeName(Identifier),
+    Record(Box<RawRecord<'a>>),
+    Enum(Box<RawEnum<'a>>),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RawRecordKind {
+    Struct,
+    Union,
+}
+
+/// `struct` or `union` specifier. `members` is `None` when there is no body.
+#[derive(Debug, Clone)]
+pub struct RawRecord<'a> {
+    pub kind: RawRecordKind,
+    pub name: Option<Identifier>,
+    pub members: Option<Vec<RawMember<'a>>>,
+}
+
+/// Member declarator of a record. The declarator is absent for unnamed bitfields and nested
+/// anonymous records.
+#[derive(Debug, Clone)]
+pub struct RawMember<'a> {
+    pub specifiers: RawDeclSpecifiers<'a>,
+    pub declarator: Option<RawDeclarator<'a>>,
+    pub bits: Option<CExpr<'a>>,
+    pub doc: Vec<String>,
+}
+
+/// `enum` specifier. `variants` is `None` when there is no body.
+#[derive(Debug, Clone)]
+pub struct RawEnum<'a> {
+    pub name: Option<Identifier>,
+    pub variants: Option<Vec<RawEnumerator<'a>>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RawEnumerator<'a> {
+    pub name: Identifier,
+    pub value: Option<CExpr<'a>>,
+    pub doc: Vec<String>,
+}
+
+/// Declarator, nested the way C writes it: in `*a[3]`, the pointer applies to the result of
+/// `a[3]`, so `a` is an array of pointers.
+#[derive(Debug, Clone)]
+pub enum RawDeclarator<'a> {
+    /// Declared name, absent in abstract declarators.
+    Name(Option<Identifier>),
+    /// Pointer, with the annotations written among its qualifiers.
+    Pointer(Box<RawDeclarator<'a>>, RawQualifiers, Vec<RawAnnotation<'a>>),
+    Array(Box<RawDeclarator<'a>>, Option<CExpr<'a>>),
+    Function(Box<RawDeclarator<'a>>, RawParams<'a>),
+}
+
+#[derive(Debug, Clone)]
+pub struct RawParams<'a> {
+    pub params: Vec<RawParam<'a>>,
+    pub variadic: bool,
+}
+
+impl<'a> RawParams<'a> {
+    /// Whether this is the `(void)` parameter list.
+    pub fn is_void(&self) -> bool {
+        matches!(
+            self.params.as_slice(),
+            [RawParam {
+                specifiers: RawDeclSpecifiers { ty: RawTypeSpecifier::Builtin(ty), qualifiers, .. },
+                declarator: RawDeclarator::Name(None),
+                ..
+            }] if ty == "void" && *qualifiers == RawQualifiers::default()
+        ) && !self.variadic
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RawParam<'a> {
+    pub specifiers: RawDeclSpecifiers<'a>,
+    pub declarator: RawDeclarator<'a>,
+    pub doc: Vec<String>,
+}
+
+impl<'a> RawDeclarator<'a> {
+    pub fn name(&self) -> Option<&Identifier> {
+        match self {
+            RawDeclarator::Name(name) => name.as_ref(),
+            RawDeclarator::Pointer(inner, ..)
+            | RawDeclarator::Array(inner, _)
+            | RawDeclarator::Function(inner, _) => inner.name(),
+        }
+    }
+
+    /// Annotations written around the `*`s of this declarator, outermost pointer first.
+    pub fn annotations(&self) -> Vec<&RawAnnotation<'a>> {
+        match self {
+            RawDeclarator::Name(_) => Vec::new(),
+            RawDeclarator::Pointer(inner, _, annotations) => {
+                let mut ret = inner.annotations();
+                ret.extend(annotations);
+                ret
+            }
+            RawDeclarator::Array(inner, _) | RawDeclarator::Function(inner, _) => inner.annotations(),
+        }
+    }
+
+    /// Apply this declarator to `base`, giving the declared name and its type.
+    pub fn resolve(&self, base: RawType<'a>) -> (Option<Identifier>, RawType<'a>) {
+        match self {
+            RawDeclarator::Name(name) => (name.clone(), base),
+            RawDeclarator::Pointer(inner, qualifiers, _) => {
+                inner.resolve(RawType::Pointer(Box::new(base), *qualifiers))
+            }
+            RawDeclarator::Array(inner, length) => {
+                inner.resolve(RawType::Array(Box::new(base), length.clone()))
+            }
+            RawDeclarator::Function(inner, params) => {
+                inner.resolve(RawType::Function(Box::new(base), params.clone()))
+            }
+        }
+    }
+}
+
+/// Resolved C type, with the declared name taken out.
+#[derive(Debug, Clone)]
+pub enum RawType<'a> {
+    Specified(RawTypeSpecifier<'a>, RawQualifiers),
+    Pointer(Box<RawType<'a>>, RawQualifiers),
+    Array(Box<RawType<'a>>, Option<CExpr<'a>>),
+    Function(Box<RawType<'a>>, RawParams<'a>),
+}
+
+impl<'a> RawType<'a> {
+    pub fn from_specifiers(specifiers: &RawDeclSpecifiers<'a>) -> Self {
+        RawType::Specified(specifiers.ty.clone(), specifiers.qualifiers)
+    }
+
+    /// Registry type of this type, mapping pointers and arrays structurally. Fails with the
+    /// specifier of an anonymous record or enum, which has no name to refer to it by.
+    ///
+    /// Function prototypes have no registry type, so a function or pointer to one is referred to by
+    /// the name `prototype` gives it, from its result, its parameters and whether it is a pointer.
+    pub fn to_type<F>(&self, prototype: &mut F) -> Result<Type<'a>, &RawTypeSpecifier<'a>>
+    where
+        F: FnMut(&RawType<'a>, &RawParams<'a>, bool) -> Identifier,
+    {
+        let ident = |ident: Identifier| Type::IdentifierType(Box::new(IdentifierType { ident }));
+        Ok(match self {
+            RawType::Specified(specifier, _) => {
+                let name = match specifier {
+                    RawTypeSpecifier::Builtin(spelling) => spelling.interned(),
+                    RawTypeSpecifier::TypeName(ident) => ident.clone(),
+                    RawTypeSpecifier::Record(record) if record.name.is_some() => record.name.clone().unwrap(),
+                    RawTypeSpecifier::Enum(enumeration) if enumeration.name.is_some() => {
+                        enumeration.name.clone().unwrap()
+                    }
+                    _ => return Err(specifier),
+                };
+                ident(name)
+            }
+            RawType::Function(result, params) => ident(prototype(result, params, false)),
+            RawType::Pointer(pointee, _) if let RawType::Function(result, params) = &**pointee => {
+                ident(prototype(result, params, true))
+            }
+            RawType::Pointer(pointee, qualifiers) => {
+                let pointee_qualifiers = match &**pointee {
+                    RawType::Specified(_, qualifiers) | RawType::Pointer(_, qualifiers) => *qualifiers,
+                    _ => RawQualifiers::default(),
+                };
+                Type::PointerType(Box::new(PointerType {
+                    pointee: pointee.to_type(prototype)?.into_pointee(),
+                    is_const: pointee_qualifiers.is_const,
+                    is_volatile: pointee_qualifiers.is_volatile,
+                    is_atomic: pointee_qualifiers.is_atomic,
+                    is_restrict: qualifiers.is_restrict,
+                    is_pointer_const: qualifiers.is_const,
+                    is_pointer_volatile: qualifiers.is_volatile,
+                    is_pointer_atomic: qualifiers.is_atomic,
+                    pointer_to_one: false,
+                    nullable: false,
+                }))
+            }
+            RawType::Array(element, length) => Type::ArrayType(Box::new(ArrayType {
+                element: element.to_type(prototype)?,
+                length: length.clone(),
+            })),
+        })
+    }
+
+    /// C spelling of a declaration of `name` with this type, `name` may be empty.
+    pub fn declare(&self, name: &str) -> String {
+        match self {
+            RawType::Specified(ty, qualifiers) => {
+                let mut ret = qualifiers.spelling().join(" ");
+                if !ret.is_empty() {
+                    ret.push(' ');
+                }
+                ret.push_str(&ty.to_string());
+                if !name.is_empty() && !name.starts_with('*') {
+                    ret.push(' ');
+                }
+                ret.push_str(name);
+                ret
+            }
+            RawType::Pointer(pointee, qualifiers) => {
+                let qualifiers = qualifiers.spelling();
+                let mut inner = "*".to_string();
+                for qualifier in &qualifiers {
+                    inner.push(' ');
+                    inner.push_str(qualifier);
+                }
+                match **pointee {
+                    RawType::Array(..) | RawType::Function(..) => {
+                        if !qualifiers.is_empty() && !name.is_empty() {
+                            inner.push(' ');
+                        }
+                        inner.push_str(name);
+                        pointee.declare(&format!("({})", inner))
+                    }
+                    _ => {
+                        if !name.is_empty() && (!qualifiers.is_empty() || !name.starts_with('*')) {
+                            inner.push(' ');
+                        }
+                        inner.push_str(name);
+                        pointee.declare(&inner)
+                    }
+                }
+            }
+            RawType::Array(element, length) => match length {
+                Some(length) => element.declare(&format!("{}[{}]", name, length)),
+                None => element.declare(&format!("{}[]", name)),
+            },
+            RawType::Function(result, params) => {
+                let mut list = params.params.iter().map(|param| {
+                    let (name, ty) = param.declarator.resolve(RawType::from_specifiers(&param.specifiers));
+                    ty.declare(name.as_ref().map_or("", |name| name.original()))
+                }).collect::<Vec<_>>();
+                if params.variadic {
+                    list.push("...".to_string());
+                }
+                result.declare(&format!("{}({})", name, list.join(", ")))
+            }
+        }
+    }
+}
+
+impl Display for RawTypeSpecifier<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let tag = |f: &mut std::fmt::Formatter<'_>, keyword: &str, name: &Option<Identifier>| match name {
+            Some(name) => write!(f, "{} {}", keyword, name.original()),
+            None => write!(f, "{} <anonymous>", keyword),
+        };
+
+        match self {
+            RawTypeSpecifier::Builtin(spelling) => write!(f, "{}", spelling),
+            RawTypeSpecifier::TypeName(ident) => write!(f, "{}", ident.original()),
+            RawTypeSpecifier::Record(record) => match record.kind {
+                RawRecordKind::Struct => tag(f, "struct", &record.name),
+                RawRecordKind::Union => tag(f, "union", &record.name),
+            },
+            RawTypeSpecifier::Enum(enumeration) => tag(f, "enum", &enumeration.name),
+        }
+    }
+}
+
+impl Display for RawType<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.declare(""))
+    }
+}
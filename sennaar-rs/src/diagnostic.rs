@@ -0,0 +1,113 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+
+/// A position in a source file, with the line it points into kept for rendering.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SourceLocation {
+    /// 1-based line number.
+    pub line: usize,
+    /// 1-based column number, counted in characters.
+    pub column: usize,
+    /// Number of characters to underline, at least 1.
+    pub length: usize,
+    pub source_line: String,
+}
+
+impl SourceLocation {
+    /// Locate the byte range `start..end` of `source`.
+    ///
+    /// Ranges spanning several lines are clipped to the end of the first line.
+    pub fn from_span(source: &str, start: usize, end: usize) -> Self {
+        let start = start.min(source.len());
+        let end = end.clamp(start, source.len());
+
+        let line_start = source[..start].rfind('\n').map_or(0, |idx| idx + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |idx| start + idx);
+        let source_line = source[line_start..line_end].trim_end_matches('\r');
+
+        let line = source[..start].matches('\n').count() + 1;
+        let column = source[line_start..start].chars().count() + 1;
+        let length = source[start..end.min(line_end)].chars().count().max(1);
+
+        Self {
+            line,
+            column,
+            length,
+            source_line: source_line.to_string(),
+        }
+    }
+
+    /// Locate a 1-based line and column, as reported by `serde_json`.
+    pub fn from_line_column(source: &str, line: usize, column: usize) -> Self {
+        let source_line = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+
+        Self {
+            line,
+            column: column.max(1),
+            length: 1,
+            source_line: source_line.to_string(),
+        }
+    }
+}
+
+/// An error found in a registry source, rendered rustc-style with a caret snippet.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub location: Option<SourceLocation>,
+    pub message: String,
+    /// Human-readable names of the tokens the parser would have accepted instead.
+    pub expected: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(file: impl ToString, message: impl ToString) -> Self {
+        Self {
+            file: file.to_string(),
+            location: None,
+            message: message.to_string(),
+            expected: Vec::new(),
+        }
+    }
+
+    pub fn with_location(mut self, location: SourceLocation) -> Self {
+        self.location = Some(location);
+        self
+    }
+
+    pub fn with_expected(mut self, expected: Vec<String>) -> Self {
+        self.expected = expected;
+        self
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        writeln!(f, "error: {}", self.message)?;
+
+        let Some(location) = &self.location else {
+            return write!(f, " --> {}", self.file);
+        };
+
+        let gutter = " ".repeat(location.line.to_string().len());
+        writeln!(f, "{}--> {}:{}:{}", gutter, self.file, location.line, location.column)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", location.line, location.source_line)?;
+
+        // Keep tabs so the caret lines up with the source line in the terminal
+        let padding = location.source_line
+            .chars()
+            .take(location.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        write!(f, "{} | {}{}", gutter, padding, "^".repeat(location.length))?;
+
+        if !self.expected.is_empty() {
+            write!(f, "\n{} = expected one of {}", gutter, self.expected.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostic {}
@@ -20,6 +20,8 @@ struct IdentInternal {
 thread_local! {
     static IDENTIFIERS: RefCell<HashMap<String, Rc<IdentInternal>>> =
         RefCell::new(HashMap::new());
+
+    static RENAME_ERRORS: RefCell<Option<Vec<RenameError>>> = const { RefCell::new(None) };
 }
 
 /// Clear internal identifier renames state.
@@ -33,6 +35,25 @@ pub fn reset_identifier_renames() {
     });
 }
 
+/// A rename conflict found while deserializing an identifier.
+#[derive(Debug, Clone)]
+pub struct RenameError {
+    /// The `original:renamed` spelling that could not be applied.
+    pub spelling: String,
+    pub message: String,
+}
+
+/// Run `f`, collecting identifier rename failures during deserialization instead of failing.
+///
+/// Within `f`, an identifier that cannot be renamed deserializes to its original name and the
+/// failure is recorded, so that all conflicting renames in a document can be reported at once.
+pub fn collect_rename_errors<R>(f: impl FnOnce() -> R) -> (R, Vec<RenameError>) {
+    let outer = RENAME_ERRORS.with(|errors| errors.borrow_mut().replace(Vec::new()));
+    let ret = f();
+    let errors = RENAME_ERRORS.with(|errors| std::mem::replace(&mut *errors.borrow_mut(), outer));
+    (ret, errors.unwrap_or_default())
+}
+
 #[derive(Clone)]
 pub struct Identifier(Rc<IdentInternal>);
 
@@ -158,10 +179,17 @@ impl<'de> Deserialize<'de> for Identifier {
             match ident.try_rename(renamed) {
                 Ok(_) => Ok(ident),
                 Err(e) => {
-                    Err(DeserializeError::custom(format!(
+                    let message = format!(
                         "Failed renaming identifier '{}' to '{}': {}",
                         original, renamed, e
-                    )))
+                    );
+                    RENAME_ERRORS.with(|errors| match errors.borrow_mut().as_mut() {
+                        Some(errors) => {
+                            errors.push(RenameError { spelling: s.clone(), message });
+                            Ok(ident)
+                        }
+                        None => Err(DeserializeError::custom(message)),
+                    })
                 }
             }
         } else {
@@ -1,5 +1,7 @@
 mod ident;
 pub use ident::*;
+mod diagnostic;
+pub use diagnostic::*;
 pub mod cpl;
 pub mod registry;
 pub mod panspace;
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+use crate::registry::CallingConvention;
+
+
+/// What an annotation macro says about the parameter or member it is attached to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AnnotationEffect {
+    /// Sets `optional`, and makes the outermost pointer nullable to match.
+    Optional,
+    /// Makes the outermost pointer nullable.
+    Nullable,
+    /// Marks the outermost pointer as pointing to a single element.
+    PointerToOne,
+    /// Sets `len` to the annotation argument at this index.
+    Len(usize),
+    /// Puts this metadata key, with the annotation arguments as a string value if there are any.
+    Metadata(String),
+    /// Sets the calling convention of the command it is attached to.
+    CallingConvention(CallingConvention),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Annotation {
+    /// Whether the macro is followed by parenthesized arguments, as in `_In_reads_(count)`.
+    pub takes_args: bool,
+    pub effects: Vec<AnnotationEffect>,
+}
+
+/// Annotation macros known to panspace, which are lexed as annotations instead of identifiers.
+#[derive(Debug, Clone, Default)]
+pub struct AnnotationTable {
+    pub entries: HashMap<String, Annotation>,
+}
+
+impl AnnotationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an annotation macro, a name ending in `(...)` takes arguments.
+    pub fn with(mut self, name: &str, effects: &[AnnotationEffect]) -> Self {
+        let (name, takes_args) = match name.strip_suffix("(...)") {
+            Some(name) => (name, true),
+            None => (name, false),
+        };
+        self.entries.insert(name.to_string(), Annotation { takes_args, effects: effects.to_vec() });
+        self
+    }
+
+    pub fn extend(&mut self, other: AnnotationTable) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Annotation> {
+        self.entries.get(name)
+    }
+
+    /// Nullability macros found in many C libraries.
+    pub fn common() -> Self {
+        use AnnotationEffect::*;
+        Self::new()
+            .with("NULLABLE", &[Optional])
+            .with("_Opt_", &[Optional])
+            .with("NOTNULL", &[])
+            .with("NONNULL", &[])
+    }
+
+    /// The calling convention keywords of MSVC, which other compilers take as macros too.
+    pub fn calling_conventions() -> Self {
+        use AnnotationEffect::CallingConvention as Convention;
+        use CallingConvention::*;
+        Self::new()
+            .with("__cdecl", &[])
+            .with("__stdcall", &[Convention(StdCall)])
+            .with("__fastcall", &[Convention(FastCall)])
+            .with("__thiscall", &[Convention(ThisCall)])
+            .with("__vectorcall", &[Convention(VectorCall)])
+    }
+
+    /// The calling convention macros of Vulkan, which are `__stdcall` on 32-bit Windows and the
+    /// platform default elsewhere.
+    pub fn vulkan() -> Self {
+        Self::platform_stdcall(&["VKAPI_CALL", "VKAPI_PTR"])
+    }
+
+    /// The calling convention macros of OpenGL, `__stdcall` on 32-bit Windows like Vulkan's.
+    pub fn opengl() -> Self {
+        Self::platform_stdcall(&["APIENTRY", "GLAPIENTRY"])
+    }
+
+    /// The calling convention macros of the Windows headers, `__stdcall` on 32-bit Windows too.
+    pub fn win32() -> Self {
+        Self::platform_stdcall(&["WINAPI", "CALLBACK", "APIENTRY"])
+    }
+
+    /// Macros that only mean stdcall where it differs from the default, that is on 32-bit Windows.
+    fn platform_stdcall(names: &[&str]) -> Self {
+        names.iter().fold(Self::new(), |table, name| {
+            table.with(name, &[AnnotationEffect::CallingConvention(CallingConvention::StdCall)])
+        })
+    }
+
+    /// The parameter annotations of Microsoft's source annotation language.
+    pub fn sal() -> Self {
+        use AnnotationEffect::*;
+        let out = || Metadata("out".to_string());
+        let inout = || Metadata("inout".to_string());
+        Self::new()
+            .with("_In_", &[PointerToOne])
+            .with("_In_opt_", &[Optional, PointerToOne])
+            .with("_Out_", &[PointerToOne, out()])
+            .with("_Out_opt_", &[Optional, PointerToOne, out()])
+            .with("_Inout_", &[PointerToOne, inout()])
+            .with("_Inout_opt_", &[Optional, PointerToOne, inout()])
+            .with("_Outptr_", &[PointerToOne, out()])
+            .with("_Outptr_opt_", &[Optional, PointerToOne, out()])
+            .with("_In_reads_(...)", &[Len(0)])
+            .with("_In_reads_opt_(...)", &[Optional, Len(0)])
+            .with("_Out_writes_(...)", &[Len(0), out()])
+            .with("_Out_writes_opt_(...)", &[Optional, Len(0), out()])
+            .with("_Inout_updates_(...)", &[Len(0), inout()])
+            .with("_Inout_updates_opt_(...)", &[Optional, Len(0), inout()])
+            .with("_Reserved_", &[Metadata("reserved".to_string())])
+    }
+}
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use crate::cpl::*;
+use crate::panspace::parse_expression;
+use crate::registry::*;
+
+
+/// The platform a predefined macro tells about when it is defined.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PlatformMacro {
+    Arch(Arch),
+    Endian(Endian),
+    OS(OS),
+    LibC(LibC),
+    Custom(String),
+}
+
+/// Macros whose conditionals are kept apart as platforms instead of being evaluated.
+#[derive(Debug, Clone, Default)]
+pub struct PlatformMacroTable {
+    pub entries: HashMap<String, PlatformMacro>,
+}
+
+impl PlatformMacroTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str, platform: PlatformMacro) -> Self {
+        self.entries.insert(name.to_string(), platform);
+        self
+    }
+
+    pub fn extend(&mut self, other: PlatformMacroTable) {
+        self.entries.extend(other.entries);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PlatformMacro> {
+        self.entries.get(name)
+    }
+
+    /// Operating system, architecture, byte order and C library macros predefined by common
+    /// compilers.
+    pub fn standard() -> Self {
+        use PlatformMacro as P;
+        Self::new()
+            .with("_WIN32", P::OS(OS::windows))
+            .with("_WIN64", P::OS(OS::windows))
+            .with("__linux__", P::OS(OS::linux))
+            .with("__APPLE__", P::OS(OS::macos))
+            .with("__FreeBSD__", P::OS(OS::freebsd))
+            .with("__i386__", P::Arch(Arch::i386))
+            .with("_M_IX86", P::Arch(Arch::i386))
+            .with("__x86_64__", P::Arch(Arch::x86_64))
+            .with("_M_X64", P::Arch(Arch::x86_64))
+            .with("__aarch64__", P::Arch(Arch::aarch64))
+            .with("_M_ARM64", P::Arch(Arch::aarch64))
+            .with("__LITTLE_ENDIAN__", P::Endian(Endian::little))
+            .with("__BIG_ENDIAN__", P::Endian(Endian::big))
+            .with("_MSC_VER", P::LibC(LibC::msft))
+            .with("__GLIBC__", P::LibC(LibC::glibc))
+    }
+}
+
+/// A preprocessor condition, as the platform macros it requires to be defined or undefined.
+///
+/// Only conjunctions are kept: a condition that would need a disjunction of platforms cannot be
+/// told by one [`Platform`] and fails to evaluate. Macros missing from the table do not take
+/// part, so that every branch testing them alone is visible.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PlatformCondition {
+    pub terms: Vec<(PlatformMacro, bool)>,
+}
+
+impl PlatformCondition {
+    /// Evaluate the condition of an `#if` or `#elif` directive.
+    pub fn parse(source: &str, table: &PlatformMacroTable) -> Result<Self, String> {
+        let source = parenthesize_defined(source);
+        let expr = parse_expression(&source).map_err(|e| format!("cannot parse condition: {e:?}"))?;
+        Self::evaluate(&expr, false, table)
+    }
+
+    /// The condition of `#ifdef name`, or of `#ifndef name` when `defined` is false.
+    pub fn defined(name: &str, defined: bool, table: &PlatformMacroTable) -> Self {
+        match table.get(name) {
+            Some(platform) => Self { terms: vec![(platform.clone(), defined)] },
+            None => Self::default(),
+        }
+    }
+
+    pub fn and(&self, other: &PlatformCondition) -> Self {
+        let mut terms = self.terms.clone();
+        terms.extend(other.terms.iter().cloned());
+        Self { terms }
+    }
+
+    pub fn negate(&self) -> Result<Self, String> {
+        match self.terms.as_slice() {
+            [] => Ok(Self::default()),
+            [(platform, defined)] => Ok(Self { terms: vec![(platform.clone(), !defined)] }),
+            _ => Err("cannot negate a condition on several platform macros".to_string()),
+        }
+    }
+
+    /// The platform the condition holds on, `Ok(None)` meaning every platform, or `Err(())`
+    /// if it holds on none.
+    ///
+    /// A macro required to be undefined leaves its dimension as [`PlatformSpecifierState::Other`].
+    #[allow(clippy::result_unit_err)]
+    pub fn platform(&self) -> Result<Option<Platform>, ()> {
+        if self.terms.is_empty() {
+            return Ok(None);
+        }
+
+        let arch = dimension(self.terms.iter().filter_map(|(platform, defined)| match platform {
+            PlatformMacro::Arch(arch) => Some((arch, *defined)),
+            _ => None,
+        }))?;
+        let os = dimension(self.terms.iter().filter_map(|(platform, defined)| match platform {
+            PlatformMacro::OS(os) => Some((os, *defined)),
+            _ => None,
+        }))?;
+        let libc = dimension(self.terms.iter().filter_map(|(platform, defined)| match platform {
+            PlatformMacro::LibC(libc) => Some((libc, *defined)),
+            _ => None,
+        }))?;
+        let custom = dimension(self.terms.iter().filter_map(|(platform, defined)| match platform {
+            PlatformMacro::Custom(custom) => Some((custom, *defined)),
+            _ => None,
+        }))?;
+
+        // With only two byte orders, ruling one out picks the other
+        let endian = match dimension(self.terms.iter().filter_map(|(platform, defined)| match platform {
+            PlatformMacro::Endian(endian) => Some((endian, *defined)),
+            _ => None,
+        }))? {
+            PlatformSpecifierState::Exact { value } => Some(value),
+            PlatformSpecifierState::Other => {
+                let ruled_out = |endian| self.terms.contains(&(PlatformMacro::Endian(endian), false));
+                match (ruled_out(Endian::little), ruled_out(Endian::big)) {
+                    (true, true) => return Err(()),
+                    (true, false) => Some(Endian::big),
+                    _ => Some(Endian::little),
+                }
+            }
+            PlatformSpecifierState::Any => None,
+        };
+
+        Ok(Some(Platform { arch, endian, os, libc, custom }))
+    }
+
+    fn evaluate(expr: &CExpr<'_>, negated: bool, table: &PlatformMacroTable) -> Result<Self, String> {
+        match expr {
+            CExpr::Paren(paren) => Self::evaluate(&paren.expr, negated, table),
+            CExpr::Unary(unary) if unary.op == CUnaryOp::Not => Self::evaluate(&unary.expr, !negated, table),
+            CExpr::Identifier(ident) => Ok(Self::defined(ident.ident.original(), !negated, table)),
+            CExpr::Call(call) if is_defined_call(call) => match call.args.as_slice() {
+                [CExpr::Identifier(ident)] => Ok(Self::defined(ident.ident.original(), !negated, table)),
+                _ => Err(format!("malformed '{expr}'")),
+            },
+            // `a && b`, or `a || b` negated as `!a && !b`
+            CExpr::Binary(binary)
+                if (binary.op == CBinaryOp::And && !negated) || (binary.op == CBinaryOp::Or && negated) =>
+            {
+                let lhs = Self::evaluate(&binary.lhs, negated, table)?;
+                let rhs = Self::evaluate(&binary.rhs, negated, table)?;
+                Ok(lhs.and(&rhs))
+            }
+            CExpr::Binary(binary) if matches!(binary.op, CBinaryOp::And | CBinaryOp::Or) => {
+                let lhs = Self::evaluate(&binary.lhs, negated, table)?;
+                let rhs = Self::evaluate(&binary.rhs, negated, table)?;
+                match (lhs.terms.is_empty(), rhs.terms.is_empty()) {
+                    (true, _) | (_, true) => Ok(Self::default()),
+                    _ => Err(format!("'{expr}' tells several platforms")),
+                }
+            }
+            _ if mentions_platform_macro(expr, table) => Err(format!("'{expr}' is not a platform condition")),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+/// Fold the terms of one dimension, `Err(())` if they contradict each other.
+fn dimension<'t, T: Clone + Eq + 't>(
+    terms: impl Iterator<Item = (&'t T, bool)>,
+) -> Result<PlatformSpecifierState<T>, ()> {
+    let mut required: Option<&T> = None;
+    let mut ruled_out = Vec::new();
+    for (value, defined) in terms {
+        if !defined {
+            ruled_out.push(value);
+        } else if required.is_some_and(|required| required != value) {
+            return Err(());
+        } else {
+            required = Some(value);
+        }
+    }
+
+    match required {
+        Some(value) if ruled_out.contains(&value) => Err(()),
+        Some(value) => Ok(PlatformSpecifierState::Exact { value: value.clone() }),
+        None if !ruled_out.is_empty() => Ok(PlatformSpecifierState::Other),
+        None => Ok(PlatformSpecifierState::Any),
+    }
+}
+
+fn is_defined_call(call: &CCallExpr<'_>) -> bool {
+    matches!(&call.callee, CExpr::Identifier(ident) if ident.ident.original() == "defined")
+}
+
+fn mentions_platform_macro(expr: &CExpr<'_>, table: &PlatformMacroTable) -> bool {
+    match expr {
+        CExpr::Identifier(ident) => table.get(ident.ident.original()).is_some(),
+        CExpr::Paren(paren) => mentions_platform_macro(&paren.expr, table),
+        CExpr::Unary(unary) => mentions_platform_macro(&unary.expr, table),
+        CExpr::Cast(cast) => mentions_platform_macro(&cast.expr, table),
+        CExpr::Call(call) => call.args.iter().any(|arg| mentions_platform_macro(arg, table)),
+        CExpr::Binary(binary) => {
+            mentions_platform_macro(&binary.lhs, table) || mentions_platform_macro(&binary.rhs, table)
+        }
+        CExpr::Conditional(conditional) => {
+            mentions_platform_macro(&conditional.cond, table)
+                || mentions_platform_macro(&conditional.then, table)
+                || mentions_platform_macro(&conditional.otherwise, table)
+        }
+        _ => false,
+    }
+}
+
+/// Rewrite `defined NAME` as `defined(NAME)`, which parses as a call.
+fn parenthesize_defined(source: &str) -> String {
+    let mut ret = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(found) = rest.find("defined") {
+        let (before, after) = rest.split_at(found + "defined".len());
+        ret.push_str(before);
+        rest = after;
+
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        if before[..found].ends_with(is_word) || rest.starts_with(is_word) {
+            continue;
+        }
+        let name_start = rest.len() - rest.trim_start().len();
+        let name_len = rest[name_start..].find(|c: char| !is_word(c)).unwrap_or(rest.len() - name_start);
+        if name_len > 0 {
+            ret.push('(');
+            ret.push_str(&rest[name_start..name_start + name_len]);
+            ret.push(')');
+            rest = &rest[name_start + name_len..];
+        }
+    }
+    ret.push_str(rest);
+    ret
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::collections::hash_map::Entry;
+
+use crate::{Identifier, Internalize};
+use crate::cpl::*;
+use crate::panspace::{parse_expression_with_types, HparseDefine};
+use crate::registry::*;
+
+
+/// `#define` left out of the registry, and why.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SkippedDefine {
+    /// 1-based line number.
+    pub line: usize,
+    pub name: String,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SkipReason {
+    IncludeGuard,
+    EmptyDefinition,
+    NotAnExpression,
+    /// GNU named variadic parameter such as `args...`, given as written.
+    NamedVariadicParameter(String),
+    /// The type of an object-like macro cannot be told, with the reason.
+    UnknownType(String),
+    DuplicateMacro,
+    DuplicateConstant,
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::IncludeGuard => write!(f, "include guard"),
+            SkipReason::EmptyDefinition => write!(f, "empty definition"),
+            SkipReason::NotAnExpression => write!(f, "not an expression"),
+            SkipReason::NamedVariadicParameter(param) => {
+                write!(f, "named variadic parameter '{}' is not supported", param)
+            }
+            SkipReason::UnknownType(reason) => write!(f, "{}", reason),
+            SkipReason::DuplicateMacro => write!(f, "duplicate macro"),
+            SkipReason::DuplicateConstant => write!(f, "duplicate constant"),
+        }
+    }
+}
+
+/// Turn macros whose bodies are C expressions into constants, or into macro entities when they
+/// are function-like.
+///
+/// The type of a constant follows from literal suffixes, casts and the constants and
+/// enumerators it refers to, with the usual arithmetic conversions between builtin types.
+/// Identifiers accepted by `type_names` are taken as typedef names, so that casts to them parse.
+///
+/// Macros defined again under other platform conditionals are kept as platform variants.
+///
+/// Returns every macro left out: include guards, empty definitions, bodies that are not
+/// expressions or whose type cannot be told, such as attributes, and duplicate definitions.
+pub fn lower_defines<'a>(
+    registry: &mut RegistryBase<'a>,
+    defines: &[HparseDefine],
+    type_names: &dyn Fn(&str) -> bool,
+) -> Vec<SkippedDefine> {
+    let mut skipped = Vec::new();
+    for define in defines {
+        let mut skip = |reason: SkipReason| skipped.push(SkippedDefine {
+            line: define.line,
+            name: define.name.clone(),
+            reason,
+        });
+
+        if define.is_include_guard {
+            skip(SkipReason::IncludeGuard);
+            continue;
+        }
+        if define.body.is_empty() {
+            skip(SkipReason::EmptyDefinition);
+            continue;
+        }
+        let Ok(expr) = parse_expression_with_types(&define.body, type_names).map(CExpr::into_owned) else {
+            skip(SkipReason::NotAnExpression);
+            continue;
+        };
+
+        let name = define.name.interned();
+        if let Some(params) = &define.params {
+            let (params, variadic) = match params.split_last() {
+                Some((last, params)) if last == "..." => (params, true),
+                _ => (params.as_slice(), false),
+            };
+            if let Some(param) = params.iter().find(|param| param.ends_with("...")) {
+                skip(SkipReason::NamedVariadicParameter(param.clone()));
+                continue;
+            }
+
+            let macro_ = Macro {
+                name: name.clone(),
+                metadata: HashMap::new(),
+                doc: Doc::parse(&define.doc),
+                platform: define.platform.clone(),
+                params: params.iter().map(|param| param.interned()).collect(),
+                variadic,
+                body: expr,
+                platform_variants: Vec::new(),
+            };
+            match registry.macros.entry(name) {
+                Entry::Occupied(mut entry) => {
+                    if entry.get_mut().add_platform_variant(macro_).is_err() {
+                        skip(SkipReason::DuplicateMacro);
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(macro_);
+                }
+            }
+            continue;
+        }
+
+        let ty = match infer_type(registry, &expr) {
+            Ok(ty) => ty,
+            Err(e) => {
+                skip(SkipReason::UnknownType(e));
+                continue;
+            }
+        };
+        let constant = Constant {
+            name: name.clone(),
+            metadata: HashMap::new(),
+            doc: Doc::parse(&define.doc),
+            platform: define.platform.clone(),
+            ty,
+            expr,
+            platform_variants: Vec::new(),
+        };
+        match registry.constants.entry(name) {
+            Entry::Occupied(mut entry) => {
+                if entry.get_mut().add_platform_variant(constant).is_err() {
+                    skip(SkipReason::DuplicateConstant);
+                }
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(constant);
+            }
+        }
+    }
+    skipped
+}
+
+/// Builtin arithmetic types, from the highest conversion rank down.
+const ARITHMETIC_TYPES: &[&str] = &[
+    "long double", "double", "float",
+    "unsigned long long", "long long", "unsigned long", "long", "unsigned int", "int",
+];
+
+fn infer_type<'a>(registry: &RegistryBase<'a>, expr: &CExpr<'a>) -> Result<Type<'a>, String> {
+    Ok(match expr {
+        CExpr::IntLiteral(literal) => identifier_type(int_literal_type(&literal.value, &literal.suffix)),
+        CExpr::FloatLiteral(literal) => identifier_type(match literal.suffix.to_ascii_lowercase().as_str() {
+            "f" => "float",
+            "l" => "long double",
+            _ => "double",
+        }),
+        CExpr::CharLiteral(_) => identifier_type("int"),
+        CExpr::StringLiteral(_) => Type::PointerType(Box::new(PointerType {
+            pointee: identifier_type("char"),
+            is_const: true,
+            is_volatile: false,
+            is_atomic: false,
+            is_restrict: false,
+            is_pointer_const: false,
+            is_pointer_volatile: false,
+            is_pointer_atomic: false,
+            pointer_to_one: false,
+            nullable: false,
+        })),
+        CExpr::Identifier(ident) => identifier_value_type(registry, &ident.ident)?,
+        CExpr::Paren(paren) => infer_type(registry, &paren.expr)?,
+        CExpr::Cast(cast) => match &cast.ty {
+            CExpr::Type(ty) => ty.ty.clone(),
+            _ => return Err(format!("cannot tell the type of '{expr}'")),
+        },
+        CExpr::TypeOperator(_) | CExpr::OffsetOf(_) => identifier_type("size_t"),
+        CExpr::Unary(unary) => match unary.op {
+            CUnaryOp::Plus | CUnaryOp::Minus | CUnaryOp::BitNot => infer_type(registry, &unary.expr)?,
+            CUnaryOp::Not => identifier_type("int"),
+            CUnaryOp::SizeOf | CUnaryOp::AlignOf => identifier_type("size_t"),
+            _ => return Err(format!("cannot tell the type of '{expr}'")),
+        },
+        CExpr::Binary(binary) => match binary.op {
+            CBinaryOp::Less | CBinaryOp::Greater | CBinaryOp::LessEq | CBinaryOp::GreaterEq
+            | CBinaryOp::Eq | CBinaryOp::NotEq | CBinaryOp::And | CBinaryOp::Or => identifier_type("int"),
+            CBinaryOp::Shl | CBinaryOp::Shr => infer_type(registry, &binary.lhs)?,
+            CBinaryOp::Mul | CBinaryOp::Div | CBinaryOp::Mod | CBinaryOp::Add | CBinaryOp::Sub
+            | CBinaryOp::BitAnd | CBinaryOp::BitXor | CBinaryOp::BitOr => {
+                let lhs = infer_type(registry, &binary.lhs)?;
+                let rhs = infer_type(registry, &binary.rhs)?;
+                arithmetic_conversion(lhs, rhs)
+            }
+            _ => return Err(format!("cannot tell the type of '{expr}'")),
+        },
+        CExpr::Conditional(conditional) => infer_type(registry, &conditional.then)?,
+        _ => return Err(format!("cannot tell the type of '{expr}'")),
+    })
+}
+
+fn identifier_type<'a>(ident: &str) -> Type<'a> {
+    Type::IdentifierType(Box::new(IdentifierType { ident: ident.interned() }))
+}
+
+/// Type of an integer literal, which grows from `int` when the value does not fit as C does.
+fn int_literal_type(value: &str, suffix: &str) -> &'static str {
+    let suffix = suffix.to_ascii_lowercase();
+    let unsigned = suffix.contains('u');
+    let long = suffix.matches('l').count();
+    let decimal = !value.starts_with('0') || value == "0";
+    let value = int_literal_value(value).unwrap_or(u64::MAX);
+
+    let candidates: &[(&str, u64)] = &[
+        ("int", i32::MAX as u64),
+        ("unsigned int", u32::MAX as u64),
+        ("long long", i64::MAX as u64),
+        ("unsigned long long", u64::MAX),
+    ];
+    candidates
+        .iter()
+        .filter(|(name, _)| unsigned == name.starts_with("unsigned") || (!unsigned && !decimal))
+        .filter(|(name, _)| long < 2 || name.ends_with("long long"))
+        .find(|(_, max)| value <= *max)
+        .map(|(name, _)| match (*name, long) {
+            ("int", 1) => "long",
+            ("unsigned int", 1) => "unsigned long",
+            (name, _) => name,
+        })
+        .unwrap_or("unsigned long long")
+}
+
+/// Value of the digits of an integer literal, in the radix given by its prefix, or `None` when it
+/// does not fit in 64 bits.
+pub(crate) fn int_literal_value(value: &str) -> Option<u64> {
+    let parsed = if let Some(hex) = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16)
+    } else if let Some(binary) = value.strip_prefix("0b").or_else(|| value.strip_prefix("0B")) {
+        u64::from_str_radix(binary, 2)
+    } else if value.len() > 1 && value.starts_with('0') {
+        u64::from_str_radix(&value[1..], 8)
+    } else {
+        value.parse()
+    };
+    parsed.ok()
+}
+
+fn identifier_value_type<'a>(registry: &RegistryBase<'a>, ident: &Identifier) -> Result<Type<'a>, String> {
+    if let Some(constant) = registry.constants.get(ident) {
+        return Ok(constant.ty.clone());
+    }
+    for enumeration in registry.enumerations.values() {
+        if enumeration.variants.iter().any(|variant| &variant.name == ident) {
+            return Ok(Type::IdentifierType(Box::new(IdentifierType { ident: enumeration.name.clone() })));
+        }
+    }
+    Err(format!("refers to unknown '{}'", ident.original()))
+}
+
+/// Common type of two operands, see [`ARITHMETIC_TYPES`]. A typedef name wins over `int`, since
+/// a cast to it is the way to give a macro its type.
+fn arithmetic_conversion<'a>(lhs: Type<'a>, rhs: Type<'a>) -> Type<'a> {
+    let rank = |ty: &Type<'_>| match ty {
+        Type::IdentifierType(ident) => ARITHMETIC_TYPES.iter().position(|name| *name == ident.ident.original()),
+        _ => None,
+    };
+    match (rank(&lhs), rank(&rhs)) {
+        (Some(l), Some(r)) if r < l => rhs,
+        (Some(_), Some(_)) => lhs,
+        // `int` and below promote to the other operand
+        (Some(_), None) if rank(&lhs) == Some(ARITHMETIC_TYPES.len() - 1) => rhs,
+        (Some(_), None) => lhs,
+        (None, _) => lhs,
+    }
+}
@@ -0,0 +1,152 @@
+/// Documentation comment: `/** */`, `/*! */`, `///` or `//!`, or one of their trailing forms
+/// `/**< */` and `///<` documenting what comes before.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DocComment {
+    /// Byte range of the comment in the source.
+    pub start: usize,
+    pub end: usize,
+    pub lines: Vec<String>,
+    pub trailing: bool,
+}
+
+/// Find the documentation comments of `source`, skipping those in literals.
+pub fn doc_comments(source: &str) -> Vec<DocComment> {
+    let bytes = source.as_bytes();
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                let end = source[i..].find('\n').map_or(source.len(), |end| i + end);
+                push_comment(&mut ret, source, i, end);
+                i = end;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = source[i + 2..].find("*/").map_or(source.len(), |end| i + end + 4);
+                push_comment(&mut ret, source, i, end);
+                i = end;
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    ret
+}
+
+fn push_comment(comments: &mut Vec<DocComment>, source: &str, start: usize, end: usize) {
+    let text = &source[start..end];
+    let Some((lines, trailing)) = parse_doc_comment(text) else {
+        return;
+    };
+
+    // Consecutive `///` lines make up one comment
+    if text.starts_with("//")
+        && let Some(last) = comments.last_mut()
+        && last.trailing == trailing
+        && source[last.start..].starts_with("//")
+        && source[last.end..start].trim().is_empty()
+        && source[last.end..start].matches('\n').count() == 1
+    {
+        last.end = end;
+        last.lines.extend(lines);
+        return;
+    }
+    comments.push(DocComment { start, end, lines, trailing });
+}
+
+/// Split a documentation comment into its lines, without comment markers and leading `*`s.
+/// Returns `None` for any other comment, and whether the comment is a trailing one.
+pub fn parse_doc_comment(text: &str) -> Option<(Vec<String>, bool)> {
+    let text = text.trim();
+    let (body, trailing) = if let Some(body) = text.strip_prefix("///").or_else(|| text.strip_prefix("//!")) {
+        if body.starts_with('/') {
+            return None;
+        }
+        match body.strip_prefix('<') {
+            Some(body) => (body, true),
+            None => (body, false),
+        }
+    } else if let Some(body) = text.strip_prefix("/**").or_else(|| text.strip_prefix("/*!")) {
+        let body = body.strip_suffix("*/").unwrap_or(body);
+        if body.starts_with('*') || body.is_empty() {
+            return None;
+        }
+        match body.strip_prefix('<') {
+            Some(body) => (body, true),
+            None => (body, false),
+        }
+    } else {
+        return None;
+    };
+
+    let mut lines = body
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.trim().to_string()
+        })
+        .collect::<Vec<_>>();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let leading_blank = lines.iter().take_while(|line| line.is_empty()).count();
+    lines.drain(..leading_blank);
+    Some((lines, trailing))
+}
+
+/// Documentation comments of a source, as the declaration grammar looks them up for the piece of
+/// it being parsed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DocScope<'d> {
+    pub source: &'d str,
+    pub comments: &'d [DocComment],
+    /// Offset of the piece being parsed in `source`.
+    pub offset: usize,
+}
+
+impl<'d> DocScope<'d> {
+    pub fn at(self, offset: usize) -> Self {
+        Self { offset, ..self }
+    }
+
+    /// Documentation of the item spanning `start..end` of the parsed piece: the comment right
+    /// before it, or else a trailing one right after it and its `,` or `;`.
+    pub fn around(&self, start: usize, end: usize) -> Vec<String> {
+        let before = self.before(start);
+        if before.is_empty() { self.after(end) } else { before }
+    }
+
+    /// Leading comment with nothing but whitespace between it and `start`.
+    pub fn before(&self, start: usize) -> Vec<String> {
+        let start = start + self.offset;
+        let leading = self.comments.iter().rev().find(|comment| comment.end <= start);
+        match leading {
+            Some(comment) if !comment.trailing && self.source[comment.end..start].trim().is_empty() => {
+                comment.lines.clone()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Trailing comment following `end`, possibly after a `,` or `;`.
+    pub fn after(&self, end: usize) -> Vec<String> {
+        let end = end + self.offset;
+        let trailing = self.comments.iter().find(|comment| comment.start >= end);
+        if let Some(comment) = trailing
+            && comment.trailing
+        {
+            let between = self.source[end..comment.start].trim();
+            if between.is_empty() || between == "," || between == ";" {
+                return comment.lines.clone();
+            }
+        }
+        Vec::new()
+    }
+}
@@ -0,0 +1,574 @@
+use crate::Diagnostic;
+use crate::cpl::RawDeclaration;
+use crate::panspace::{
+    doc_comments, parse_error_diagnostic, DeclarationReader, DocScope, PlatformCondition, PlatformMacroTable,
+};
+use crate::registry::Platform;
+
+
+/// Tells `hparse` where declarations start and which API macros to expand inside them.
+#[derive(Debug, Clone)]
+pub struct HparseRule {
+    pub name: String,
+    /// Words marking the first line of a declaration, matched as whole words.
+    pub triggers: Vec<String>,
+    /// Macros expanded in every declaration before it is parsed, as name and replacement.
+    ///
+    /// A name ending in `(...)` also takes the parenthesized arguments following the macro. The
+    /// replacement is padded to the length of the expansion so that source positions still hold,
+    /// and thus should not be longer than it.
+    pub macros: Vec<(String, String)>,
+}
+
+impl HparseRule {
+    pub fn new(name: impl ToString, triggers: &[&str], macros: &[(&str, &str)]) -> Self {
+        Self {
+            name: name.to_string(),
+            triggers: triggers.iter().map(|s| s.to_string()).collect(),
+            macros: macros.iter().map(|(name, replacement)| (name.to_string(), replacement.to_string())).collect(),
+        }
+    }
+
+    /// Type definitions and `extern` declarations, written in plain C.
+    pub fn declarations() -> Self {
+        Self::new("declarations", &["typedef", "struct", "union", "enum", "extern"], &[])
+    }
+
+    /// Vulkan commands, whose calling convention macros are left as annotations, see
+    /// [`crate::panspace::AnnotationTable::vulkan`].
+    pub fn vulkan() -> Self {
+        Self::new("vulkan", &["VKAPI_ATTR"], &[("VKAPI_ATTR", "")])
+    }
+
+    /// OpenGL commands, whose calling convention macros are left as annotations, see
+    /// [`crate::panspace::AnnotationTable::opengl`].
+    pub fn opengl() -> Self {
+        Self::new("opengl", &["GLAPI"], &[("GLAPI", ""), ("APIENTRYP", "APIENTRY*")])
+    }
+
+    /// Win32 functions, whose calling convention macros are left as annotations, see
+    /// [`crate::panspace::AnnotationTable::win32`].
+    pub fn win32() -> Self {
+        Self::new(
+            "win32",
+            &["WINAPI", "WINBASEAPI", "WINUSERAPI"],
+            &[("WINBASEAPI", ""), ("WINUSERAPI", ""), ("__declspec(...)", "")],
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HparseConfig {
+    pub rules: Vec<HparseRule>,
+    /// Trimmed lines known to declare nothing, such as the braces of `extern "C"`.
+    pub ignored_lines: Vec<String>,
+    /// Macros whose conditionals tell the platform of the declarations they enclose.
+    pub platform_macros: PlatformMacroTable,
+}
+
+impl Default for HparseConfig {
+    fn default() -> Self {
+        Self {
+            rules: vec![HparseRule::declarations()],
+            ignored_lines: vec!["extern \"C\" {".to_string(), "}".to_string()],
+            platform_macros: PlatformMacroTable::standard(),
+        }
+    }
+}
+
+/// Declaration region picked out by a rule, with macros expanded in place.
+#[derive(Debug, Clone)]
+pub struct HparseRegion {
+    pub rule: String,
+    /// 1-based line number of the first line.
+    pub line: usize,
+    /// Byte offset of the region in the header.
+    pub start: usize,
+    pub text: String,
+    /// Platform the conditionals enclosing the first line restrict the region to.
+    pub platform: Option<Platform>,
+}
+
+/// Line that neither a rule nor the built-in handling of comments and directives recognized.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HparseLine {
+    /// 1-based line number.
+    pub line: usize,
+    pub text: String,
+}
+
+/// `#define` directive, in a branch taken on some platform.
+#[derive(Debug, Clone)]
+pub struct HparseDefine {
+    /// 1-based line number.
+    pub line: usize,
+    pub name: String,
+    /// Parameters of a function-like macro, `...` included.
+    pub params: Option<Vec<String>>,
+    /// Replacement list, with continuations joined and comments dropped.
+    pub body: String,
+    pub platform: Option<Platform>,
+    /// Documentation comment before the directive, or trailing it on its last line.
+    pub doc: Vec<String>,
+    /// Whether the macro is tested by an `#ifndef` right before, and thus guards the header.
+    pub is_include_guard: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HparseOutput {
+    pub regions: Vec<HparseRegion>,
+    pub defines: Vec<HparseDefine>,
+    pub unrecognized: Vec<HparseLine>,
+    /// Conditional directives that could not be evaluated, their branches are taken as visible
+    /// on every platform.
+    pub unsupported_conditions: Vec<HparseLine>,
+}
+
+/// Scan a header line by line, picking out the declarations that `config` recognizes.
+///
+/// Comments, blank lines and preprocessor directives are skipped, apart from `#define`s which
+/// are collected; directives inside a declaration are blanked out. Every other line has to start
+/// a declaration region, which runs up to the first `;` outside of brackets, or it is reported as
+/// unrecognized.
+///
+/// Conditionals are not evaluated but followed symbolically: every branch is scanned, and a
+/// region gets the platform under which the branches enclosing it are taken. Regions in branches
+/// that no platform takes are left out.
+pub fn hparse(source: &str, config: &HparseConfig) -> HparseOutput {
+    let lines = line_spans(source);
+    let mut output = HparseOutput::default();
+    let mut conditionals = Conditionals::default();
+    // Offset of the start of the block comment open at the current line, if any
+    let mut open_comment = None;
+    let comments = doc_comments(source);
+    let docs = DocScope { source, comments: &comments, offset: 0 };
+
+    let mut i = 0;
+    while i < lines.len() {
+        let (mut start, end) = lines[i];
+        let text = &source[start..end];
+        // A declaration may follow the end of a comment on the same line
+        if open_comment.is_some() {
+            start += text.find("*/").map_or(0, |end| end + 2);
+        }
+        let code = strip_comments(text, lines[i].0, &mut open_comment);
+        let trimmed = code.trim();
+        i += 1;
+
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            let line = i;
+            i -= 1;
+            let directive = read_directive(source, &lines, &mut i);
+            let doc = directive_doc(&docs, start, lines[i - 1].1);
+            follow_directive(line, &directive, doc, &mut conditionals, config, &mut output);
+            follow_comments(source, &lines[line..i], &mut open_comment);
+            continue;
+        }
+        if config.ignored_lines.iter().any(|line| line == trimmed) {
+            continue;
+        }
+
+        let platform = conditionals.platform();
+        let Some(rule) = config.rules.iter().find(|rule| {
+            words(trimmed).any(|word| rule.triggers.iter().any(|trigger| trigger == word))
+        }) else {
+            if platform.is_ok() {
+                output.unrecognized.push(HparseLine { line: i, text: trimmed.to_string() });
+            }
+            continue;
+        };
+
+        let line = i;
+        let mut region_end = end;
+        while declaration_end(&source[start..region_end]).is_none() && i < lines.len() {
+            region_end = lines[i].1;
+            i += 1;
+        }
+        follow_comments(source, &lines[line..i], &mut open_comment);
+        // Leave out a comment opened after the declaration and closed on later lines
+        if let Some(open) = open_comment
+            && open >= start
+        {
+            region_end = region_end.min(open);
+        }
+
+        // Keep following the conditionals opened or closed inside the region
+        let mut j = line;
+        while j < i {
+            let line = j + 1;
+            let start = lines[j].0;
+            if source[start..lines[j].1].trim_start().starts_with('#') {
+                let directive = read_directive(source, &lines, &mut j);
+                let doc = directive_doc(&docs, start, lines[j - 1].1);
+                follow_directive(line, &directive, doc, &mut conditionals, config, &mut output);
+            } else {
+                j += 1;
+            }
+        }
+
+        let Ok(platform) = platform else {
+            continue;
+        };
+        let mut text = blank_directives(&source[start..region_end]);
+        for rule in &config.rules {
+            for (name, replacement) in &rule.macros {
+                text = expand_macro(&text, name, replacement);
+            }
+        }
+        output.regions.push(HparseRegion { rule: rule.name.clone(), line, start, text, platform });
+    }
+
+    output
+}
+
+impl HparseOutput {
+    /// Parse every region, in order, with `reader` carrying typedef names from one region to
+    /// the next. Declarations get the platform of their region, and the first one also the
+    /// documentation comment around the region when it has none of its own.
+    ///
+    /// Regions that fail to parse are reported as diagnostics located in `source`, the header
+    /// the regions were picked from.
+    pub fn parse_declarations(
+        &self,
+        file: &str,
+        source: &str,
+        reader: &DeclarationReader<'_>,
+    ) -> (Vec<RawDeclaration<'_>>, Vec<Diagnostic>) {
+        let comments = doc_comments(source);
+        let docs = DocScope { source, comments: &comments, offset: 0 };
+        let mut declarations = Vec::new();
+        let mut diagnostics = Vec::new();
+        for region in &self.regions {
+            match reader.read(&region.text) {
+                Ok(mut parsed) => {
+                    if let Some(first) = parsed.first_mut()
+                        && first.doc.is_empty()
+                    {
+                        first.doc = docs.around(region.start, region.start + region.text.len());
+                    }
+                    declarations.extend(parsed.into_iter().map(|mut declaration| {
+                        declaration.platform = region.platform.clone();
+                        declaration
+                    }))
+                }
+                Err(e) => diagnostics.push(parse_error_diagnostic(file, source, region.start, e)),
+            }
+        }
+        (declarations, diagnostics)
+    }
+}
+
+/// The branch taken so far in an `#if` group.
+#[derive(Debug, Clone)]
+struct Conditional {
+    /// Condition of the branch enclosing the group.
+    enclosing: PlatformCondition,
+    /// Conditions of the branches before this one, in order.
+    previous: Vec<PlatformCondition>,
+    condition: PlatformCondition,
+    branch: PlatformCondition,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Conditionals {
+    stack: Vec<Conditional>,
+    /// Macro tested by the `#ifndef` just followed, if nothing else came since.
+    guard: Option<String>,
+}
+
+impl Conditionals {
+    fn current(&self) -> PlatformCondition {
+        self.stack.last().map(|conditional| conditional.branch.clone()).unwrap_or_default()
+    }
+
+    fn platform(&self) -> Result<Option<Platform>, ()> {
+        self.current().platform()
+    }
+
+    /// Follow a directive, given without its `#`. Errors leave the branch visible on every
+    /// platform the enclosing branch is.
+    fn apply(&mut self, directive: &str, table: &PlatformMacroTable) -> Result<(), String> {
+        let (keyword, rest) = directive.split_once(|c: char| c.is_whitespace()).unwrap_or((directive, ""));
+        let rest = rest.trim();
+        let condition = match keyword {
+            "if" | "elif" => PlatformCondition::parse(rest, table),
+            "ifdef" | "elifdef" => Ok(PlatformCondition::defined(rest, true, table)),
+            "ifndef" | "elifndef" => Ok(PlatformCondition::defined(rest, false, table)),
+            "else" => Ok(PlatformCondition::default()),
+            "endif" => {
+                self.stack.pop();
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let mut error = condition.as_ref().err().cloned();
+        let condition = condition.unwrap_or_default();
+
+        if keyword == "ifndef" {
+            self.guard = Some(rest.to_string());
+        }
+        if keyword.starts_with("if") {
+            let enclosing = self.current();
+            let branch = enclosing.and(&condition);
+            self.stack.push(Conditional { enclosing, previous: Vec::new(), condition, branch });
+        } else {
+            let Some(conditional) = self.stack.last_mut() else {
+                return Err(format!("'#{keyword}' without '#if'"));
+            };
+            conditional.previous.push(std::mem::replace(&mut conditional.condition, condition));
+            // None of the branches before may be taken
+            let mut branch = conditional.enclosing.and(&conditional.condition);
+            for previous in &conditional.previous {
+                match previous.negate() {
+                    Ok(negated) => branch = branch.and(&negated),
+                    Err(e) => {
+                        error.get_or_insert(e);
+                    }
+                }
+            }
+            conditional.branch = branch;
+        }
+
+        match error {
+            Some(e) => Err(format!("#{directive}: {e}")),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Follow a conditional or collect a definition.
+fn follow_directive(
+    line: usize,
+    directive: &str,
+    doc: Vec<String>,
+    conditionals: &mut Conditionals,
+    config: &HparseConfig,
+    output: &mut HparseOutput,
+) {
+    let guard = conditionals.guard.take();
+    let Some(definition) = directive.strip_prefix("define").filter(|rest| rest.starts_with(char::is_whitespace)) else {
+        if let Err(e) = conditionals.apply(directive, &config.platform_macros) {
+            output.unsupported_conditions.push(HparseLine { line, text: e });
+        }
+        return;
+    };
+    let Ok(platform) = conditionals.platform() else {
+        return;
+    };
+
+    let definition = definition.trim_start();
+    let name_len = definition.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(definition.len());
+    let (name, rest) = definition.split_at(name_len);
+    let (params, body) = match rest.strip_prefix('(').and_then(|rest| rest.split_once(')')) {
+        Some((params, body)) => {
+            let params = params.split(',').map(|param| param.trim().to_string()).filter(|param| !param.is_empty());
+            (Some(params.collect()), body)
+        }
+        None => (None, rest),
+    };
+    output.defines.push(HparseDefine {
+        line,
+        name: name.to_string(),
+        params,
+        body: body.trim().to_string(),
+        platform,
+        doc,
+        is_include_guard: guard.as_deref() == Some(name),
+    });
+}
+
+/// Documentation of the directive spanning `start..end` of the header.
+fn directive_doc(docs: &DocScope<'_>, start: usize, end: usize) -> Vec<String> {
+    let doc = docs.before(start);
+    if !doc.is_empty() {
+        return doc;
+    }
+    docs.comments
+        .iter()
+        .find(|comment| comment.trailing && comment.start >= start && comment.end <= end)
+        .map(|comment| comment.lines.clone())
+        .unwrap_or_default()
+}
+
+/// Read the directive starting at line `i`, joining continuation lines and dropping the `#` and
+/// comments. Leaves `i` after its last line.
+fn read_directive(source: &str, lines: &[(usize, usize)], i: &mut usize) -> String {
+    let mut ret = String::new();
+    while *i < lines.len() {
+        let text = &source[lines[*i].0..lines[*i].1];
+        *i += 1;
+        match text.strip_suffix('\\') {
+            Some(text) => ret.push_str(text),
+            None => {
+                ret.push_str(text);
+                break;
+            }
+        }
+        ret.push(' ');
+    }
+
+    let mut ret = ret.trim().trim_start_matches('#').trim_start().to_string();
+    while let Some(start) = ret.find("/*") {
+        let end = ret[start..].find("*/").map_or(ret.len(), |end| start + end + 2);
+        ret.replace_range(start..end, " ");
+    }
+    if let Some(start) = ret.find("//") {
+        ret.truncate(start);
+    }
+    ret.trim_end().to_string()
+}
+
+/// `text`, found at `offset` in the source, with its comments replaced by a space. `open_comment`
+/// is the offset of the block comment open at the start of `text` if any, and is updated to that
+/// of the one open at its end.
+fn strip_comments(text: &str, offset: usize, open_comment: &mut Option<usize>) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if open_comment.is_some() {
+            if c == '*' && chars.next_if(|(_, c)| *c == '/').is_some() {
+                *open_comment = None;
+                ret.push(' ');
+            }
+            continue;
+        }
+        match c {
+            '/' if chars.peek().is_some_and(|(_, c)| *c == '/') => break,
+            '/' if chars.next_if(|(_, c)| *c == '*').is_some() => *open_comment = Some(offset + i),
+            quote @ ('"' | '\'') => {
+                ret.push(quote);
+                while let Some((_, c)) = chars.next() {
+                    ret.push(c);
+                    if c == '\\' {
+                        ret.extend(chars.next().map(|(_, c)| c));
+                    } else if c == quote {
+                        break;
+                    }
+                }
+            }
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Follow the block comments opened and closed on `lines`, see [`strip_comments`].
+fn follow_comments(source: &str, lines: &[(usize, usize)], open_comment: &mut Option<usize>) {
+    for (start, end) in lines {
+        strip_comments(&source[*start..*end], *start, open_comment);
+    }
+}
+
+/// Byte ranges of the lines of `source`, without line terminators.
+fn line_spans(source: &str) -> Vec<(usize, usize)> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    for line in source.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        ret.push((start, start + content.len()));
+        start += line.len();
+    }
+    ret
+}
+
+fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).filter(|word| !word.is_empty())
+}
+
+/// Find the end of the first `;` outside of brackets, comments and literals.
+fn declaration_end(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let mut depth = 0usize;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i += text[i..].find('\n').unwrap_or(text.len() - i);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i += text[i + 2..].find("*/").map_or(text.len() - i, |end| end + 4);
+                continue;
+            }
+            quote @ (b'"' | b'\'') => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != quote && bytes[i] != b'\n' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth = depth.saturating_sub(1),
+            b';' if depth == 0 => return Some(i + 1),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Replace preprocessor directive lines, including their continuations, with spaces.
+fn blank_directives(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut in_directive = false;
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\n', '\r']);
+        if in_directive || content.trim_start().starts_with('#') {
+            in_directive = content.ends_with('\\');
+            ret.extend(content.chars().map(|_| ' '));
+            ret.push_str(&line[content.len()..]);
+        } else {
+            ret.push_str(line);
+        }
+    }
+    ret
+}
+
+/// Replace whole-word uses of the macro `name` in `text`, see [`HparseRule::macros`].
+fn expand_macro(text: &str, name: &str, replacement: &str) -> String {
+    let (name, takes_args) = match name.strip_suffix("(...)") {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let is_word_char = |c: u8| c.is_ascii_alphanumeric() || c == b'_';
+
+    let bytes = text.as_bytes();
+    let mut ret = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut search = 0;
+    while let Some(found) = text[search..].find(name) {
+        let start = search + found;
+        let mut end = start + name.len();
+        search = end;
+        if (start > 0 && is_word_char(bytes[start - 1])) || bytes.get(end).is_some_and(|c| is_word_char(*c)) {
+            continue;
+        }
+
+        if takes_args {
+            let args = end + text[end..].len() - text[end..].trim_start().len();
+            if bytes.get(args) == Some(&b'(') {
+                let mut depth = 0;
+                for (offset, c) in text[args..].char_indices() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        end = args + offset + 1;
+                        break;
+                    }
+                }
+            }
+        }
+
+        ret.push_str(&text[copied..start]);
+        ret.push_str(replacement);
+        ret.extend(std::iter::repeat_n(' ', (end - start).saturating_sub(replacement.len())));
+        copied = end;
+        search = end;
+    }
+    ret.push_str(&text[copied..]);
+    ret
+}
@@ -0,0 +1,559 @@
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use crate::{Identifier, Internalize};
+use crate::cpl::*;
+use crate::registry::*;
+use crate::panspace::{int_literal_value, AnnotationEffect, AnnotationTable};
+
+
+/// Turn parsed declarations into registry entities.
+///
+/// Structures, unions and enumerations are named by their tag, or by the typedef declaring them
+/// when they have none. A typedef of an undefined record becomes an opaque typedef, and one of a
+/// pointer to an undefined record an opaque handle.
+///
+/// Annotations on parameters, members and function results take the effects `annotations`
+/// gives them.
+///
+/// Returns one message for every declaration, or part of one, that has no registry equivalent.
+pub fn lower_declarations<'a>(
+    registry: &mut RegistryBase<'a>,
+    declarations: &[RawDeclaration<'a>],
+    annotations: &AnnotationTable,
+) -> Vec<String> {
+    let mut lowering = Lowering {
+        registry,
+        annotations,
+        defined_records: HashSet::new(),
+        doc: Doc::default(),
+        platform: None,
+        messages: Vec::new(),
+    };
+    for declaration in declarations {
+        collect_defined_records(&declaration.specifiers.ty, &mut lowering.defined_records);
+    }
+    for declaration in declarations {
+        lowering.doc = Doc::parse(&declaration.doc);
+        lowering.platform = declaration.platform.clone();
+        lowering.lower_declaration(declaration);
+    }
+    lowering.messages
+}
+
+fn collect_defined_records(ty: &RawTypeSpecifier<'_>, defined: &mut HashSet<String>) {
+    if let RawTypeSpecifier::Record(record) = ty
+        && let Some(members) = &record.members
+    {
+        if let Some(name) = &record.name {
+            defined.insert(name.original().to_string());
+        }
+        for member in members {
+            collect_defined_records(&member.specifiers.ty, defined);
+        }
+    }
+}
+
+struct Lowering<'r, 'a> {
+    registry: &'r mut RegistryBase<'a>,
+    annotations: &'r AnnotationTable,
+    defined_records: HashSet<String>,
+    /// Documentation and platform of the declaration being lowered, given to the entities it
+    /// declares.
+    doc: Doc,
+    platform: Option<Platform>,
+    messages: Vec<String>,
+}
+
+impl<'a> Lowering<'_, 'a> {
+    fn lower_declaration(&mut self, declaration: &RawDeclaration<'a>) {
+        let specifiers = &declaration.specifiers;
+        let is_typedef = specifiers.storage == Some(RawStorageClass::Typedef);
+
+        // `typedef struct { ... } Name;` names the structure after the typedef
+        let typedef_name = match declaration.declarators.as_slice() {
+            [RawInitDeclarator { declarator: RawDeclarator::Name(Some(name)), .. }] if is_typedef => {
+                Some(name.clone())
+            }
+            _ => None,
+        };
+        let base_name = self.lower_definition(&specifiers.ty, typedef_name, &declaration.doc);
+
+        if declaration.declarators.is_empty() {
+            if let RawTypeSpecifier::Record(record) = &specifiers.ty
+                && record.members.is_none()
+                && let Some(name) = &record.name
+                && !self.defined_records.contains(name.original())
+            {
+                self.add_opaque_typedef(name.clone());
+            }
+            return;
+        }
+
+        let base = RawType::Specified(
+            match &base_name {
+                Some(name) => RawTypeSpecifier::TypeName(name.clone()),
+                None => specifiers.ty.clone(),
+            },
+            specifiers.qualifiers,
+        );
+        for init in &declaration.declarators {
+            let (name, ty) = init.declarator.resolve(base.clone());
+            let Some(name) = name else {
+                continue;
+            };
+
+            let mut annotations = specifiers.annotations.iter().collect::<Vec<_>>();
+            annotations.extend(init.declarator.annotations());
+            if is_typedef {
+                self.lower_typedef(name, ty, &annotations);
+            } else if let RawType::Function(result, params) = ty {
+                self.lower_command(name, &result, &params, &annotations);
+            } else {
+                self.messages.push(format!("variable '{}' is not supported", name.original()));
+            }
+        }
+    }
+
+    /// Add the entity defined by a record or enum specifier with a body, returning its name.
+    fn lower_definition(
+        &mut self,
+        ty: &RawTypeSpecifier<'a>,
+        typedef_name: Option<Identifier>,
+        doc: &[String],
+    ) -> Option<Identifier> {
+        match ty {
+            RawTypeSpecifier::Record(record) => {
+                let members = record.members.as_ref()?;
+                let Some(name) = record.name.clone().or(typedef_name) else {
+                    self.messages.push(format!("anonymous {} is not supported", ty));
+                    return None;
+                };
+
+                let structure = self.lower_record(&name, members, doc);
+                let (kind, entities) = match record.kind {
+                    RawRecordKind::Struct => ("struct", &mut self.registry.structs),
+                    RawRecordKind::Union => ("union", &mut self.registry.unions),
+                };
+                insert_entity(entities.entry(name.clone()), kind, structure, &mut self.messages);
+                Some(name)
+            }
+            RawTypeSpecifier::Enum(enumeration) => {
+                let variants = enumeration.variants.as_ref()?;
+                let Some(name) = enumeration.name.clone().or(typedef_name) else {
+                    self.messages.push(format!("anonymous {} is not supported", ty));
+                    return None;
+                };
+
+                let enumeration = Enumeration {
+                    name: name.clone(),
+                    metadata: HashMap::new(),
+                    doc: Doc::parse(doc),
+                    platform: self.platform.clone(),
+                    variants: lower_enumerators(variants),
+                    platform_variants: Vec::new(),
+                };
+                let entry = self.registry.enumerations.entry(name.clone());
+                insert_entity(entry, "enum", enumeration, &mut self.messages);
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn lower_record(&mut self, name: &Identifier, members: &[RawMember<'a>], doc: &[String]) -> Structure<'a> {
+        let mut lowered = Vec::new();
+        for member in members {
+            let nested = self.lower_definition(&member.specifiers.ty, None, &member.doc);
+            let Some(declarator) = &member.declarator else {
+                if member.bits.is_none() {
+                    self.messages.push(format!(
+                        "struct '{}': anonymous member {} is not supported",
+                        name.original(), member.specifiers.ty
+                    ));
+                }
+                continue;
+            };
+
+            let base = RawType::Specified(
+                match nested {
+                    Some(nested) => RawTypeSpecifier::TypeName(nested),
+                    None => member.specifiers.ty.clone(),
+                },
+                member.specifiers.qualifiers,
+            );
+            let (Some(member_name), ty) = declarator.resolve(base) else {
+                continue;
+            };
+
+            let bits = match &member.bits {
+                None => None,
+                Some(CExpr::IntLiteral(bits)) if bits.value.parse::<usize>().is_ok() => {
+                    bits.value.parse().ok()
+                }
+                Some(bits) => {
+                    self.messages.push(format!(
+                        "struct '{}' member '{}': bitfield width '{}' is not an integer literal",
+                        name.original(), member_name.original(), bits
+                    ));
+                    None
+                }
+            };
+
+            let context = format!("struct '{}' member '{}'", name.original(), member_name.original());
+            let prototype = format!("{}_{}", name.original(), member_name.original());
+            let Some(mut ty) = self.lower_type(&context, &prototype, &ty) else {
+                continue;
+            };
+            let mut annotations = member.specifiers.annotations.iter().collect::<Vec<_>>();
+            annotations.extend(declarator.annotations());
+            let annotated = self.apply_annotations(&context, &annotations, &mut ty);
+            lowered.push(Member {
+                name: member_name,
+                metadata: annotated.metadata,
+                doc: Doc::parse(&member.doc),
+                platform: None,
+                ty,
+                bits,
+                init: None,
+                optional: annotated.optional,
+                len: annotated.len,
+            });
+        }
+
+        Structure {
+            name: name.clone(),
+            metadata: HashMap::new(),
+            doc: Doc::parse(doc),
+            platform: self.platform.clone(),
+            members: lowered,
+            platform_variants: Vec::new(),
+        }
+    }
+
+    /// Lower a typedef, whose `annotations` only apply to function types.
+    fn lower_typedef(&mut self, name: Identifier, ty: RawType<'a>, annotations: &[&RawAnnotation<'a>]) {
+        let context = format!("typedef '{}'", name.original());
+        match &ty {
+            RawType::Function(result, params) => {
+                self.lower_function_typedef(name, result, params, false, annotations, self.doc.clone());
+            }
+            RawType::Pointer(pointee, _) if matches!(**pointee, RawType::Function(..)) => {
+                let RawType::Function(result, params) = &**pointee else { unreachable!() };
+                self.lower_function_typedef(name, result, params, true, annotations, self.doc.clone());
+            }
+            RawType::Specified(RawTypeSpecifier::TypeName(target), _) if *target == name => {
+                // The typedef names a record or enum defined along with it
+            }
+            RawType::Specified(RawTypeSpecifier::Record(record), _) if self.is_undefined(record) => {
+                self.add_opaque_typedef(name);
+            }
+            RawType::Pointer(pointee, _) if matches!(
+                &**pointee,
+                RawType::Specified(RawTypeSpecifier::Record(record), _) if self.is_undefined(record)
+            ) => {
+                let handle = OpaqueHandleTypedef {
+                    name: name.clone(),
+                    metadata: HashMap::new(),
+                    doc: self.doc.clone(),
+                    platform: self.platform.clone(),
+                    platform_variants: Vec::new(),
+                };
+                let entry = self.registry.opaque_handle_typedefs.entry(name);
+                insert_entity(entry, "handle", handle, &mut self.messages);
+            }
+            _ => {
+                let Some(target) = self.lower_type(&context, &format!("{}_target", name.original()), &ty) else {
+                    return;
+                };
+                // `typedef struct Foo Foo;` after `struct Foo { ... };` adds nothing
+                if let Type::IdentifierType(ident_type) = &target
+                    && ident_type.ident == name
+                {
+                    return;
+                }
+
+                let typedef = Typedef {
+                    name: name.clone(),
+                    metadata: HashMap::new(),
+                    doc: self.doc.clone(),
+                    platform: self.platform.clone(),
+                    target,
+                    platform_variants: Vec::new(),
+                };
+                insert_entity(self.registry.aliases.entry(name), "alias", typedef, &mut self.messages);
+            }
+        }
+    }
+
+    fn is_undefined(&self, record: &RawRecord<'_>) -> bool {
+        record.members.is_none()
+            && record.name.as_ref().is_some_and(|name| !self.defined_records.contains(name.original()))
+    }
+
+    fn add_opaque_typedef(&mut self, name: Identifier) {
+        if self.registry.opaque_typedefs.contains_key(&name) {
+            return;
+        }
+        let opaque = OpaqueTypedef {
+            name: name.clone(),
+            metadata: HashMap::new(),
+            doc: self.doc.clone(),
+            platform: self.platform.clone(),
+            platform_variants: Vec::new(),
+        };
+        insert_entity(self.registry.opaque_typedefs.entry(name), "opaque", opaque, &mut self.messages);
+    }
+
+    fn lower_function_typedef(
+        &mut self,
+        name: Identifier,
+        result: &RawType<'a>,
+        params: &RawParams<'a>,
+        is_pointer: bool,
+        annotations: &[&RawAnnotation<'a>],
+        doc: Doc,
+    ) {
+        let context = format!("functype '{}'", name.original());
+        let variadic = params.variadic;
+        let Some((params, mut result)) = self.lower_signature(&context, name.original(), result, params) else {
+            return;
+        };
+
+        // As for commands, annotations describe the result or else the function type itself
+        let annotated = self.apply_annotations(&format!("{} result", context), annotations, &mut result);
+        if annotated.len.is_some() {
+            self.messages.push(format!("{} result: length annotations are not supported", context));
+        }
+        let typedef = FunctionTypedef {
+            name: name.clone(),
+            metadata: annotated.metadata,
+            doc,
+            platform: self.platform.clone(),
+            params,
+            variadic,
+            calling_convention: annotated.calling_convention,
+            result,
+            is_pointer,
+            is_native_api: false,
+            platform_variants: Vec::new(),
+        };
+        insert_entity(self.registry.function_typedefs.entry(name), "functype", typedef, &mut self.messages);
+    }
+
+    fn lower_command(
+        &mut self,
+        name: Identifier,
+        result: &RawType<'a>,
+        params: &RawParams<'a>,
+        annotations: &[&RawAnnotation<'a>],
+    ) {
+        let context = format!("command '{}'", name.original());
+        let variadic = params.variadic;
+        let Some((params, mut result)) = self.lower_signature(&context, name.original(), result, params) else {
+            return;
+        };
+
+        // Annotations of the declaration describe the result, or else the command itself
+        let annotated = self.apply_annotations(&format!("{} result", context), annotations, &mut result);
+        if annotated.len.is_some() {
+            self.messages.push(format!("{} result: length annotations are not supported", context));
+        }
+        let command = Command {
+            name: name.clone(),
+            metadata: annotated.metadata,
+            doc: self.doc.clone(),
+            platform: self.platform.clone(),
+            params,
+            variadic,
+            calling_convention: annotated.calling_convention,
+            result,
+            success_codes: Vec::new(),
+            error_codes: Vec::new(),
+            alias_to: None,
+            platform_variants: Vec::new(),
+        };
+        insert_entity(self.registry.commands.entry(name), "command", command, &mut self.messages);
+    }
+
+    /// Lower the parameters and result of the function `owner`, after which the prototypes they
+    /// use are named.
+    fn lower_signature(
+        &mut self,
+        context: &str,
+        owner: &str,
+        result: &RawType<'a>,
+        params: &RawParams<'a>,
+    ) -> Option<(Vec<Param<'a>>, Type<'a>)> {
+        let mut lowered = Vec::new();
+        if !params.is_void() {
+            for (i, param) in params.params.iter().enumerate() {
+                let (name, ty) = param.declarator.resolve(RawType::from_specifiers(&param.specifiers));
+                let name = name.unwrap_or_else(|| format!("param{}", i).interned());
+                let context = format!("{} parameter '{}'", context, name.original());
+                let mut ty = self.lower_type(&context, &format!("{}_{}", owner, name.original()), &ty)?;
+                let mut annotations = param.specifiers.annotations.iter().collect::<Vec<_>>();
+                annotations.extend(param.declarator.annotations());
+                let annotated = self.apply_annotations(&context, &annotations, &mut ty);
+
+                // Parameters keep `optional` and the nullability of their pointer in sync
+                let optional = annotated.optional
+                    || matches!(&ty, Type::PointerType(ptr_type) if ptr_type.nullable);
+                if let Type::PointerType(ptr_type) = &mut ty {
+                    ptr_type.nullable = optional;
+                }
+                lowered.push(Param {
+                    name,
+                    metadata: annotated.metadata,
+                    doc: Doc::parse(&param.doc),
+                    platform: None,
+                    ty,
+                    optional,
+                    len: annotated.len,
+                });
+            }
+        }
+
+        let result = self.lower_type(&format!("{} result", context), &format!("{}_result", owner), result)?;
+        Some((lowered, result))
+    }
+
+    /// Apply the effects of `annotations` to `ty`, returning the effects on the annotated entity.
+    fn apply_annotations(
+        &mut self,
+        context: &str,
+        annotations: &[&RawAnnotation<'a>],
+        ty: &mut Type<'a>,
+    ) -> Annotated<'a> {
+        let mut ret = Annotated { optional: false, len: None, metadata: HashMap::new(), calling_convention: None };
+        for annotation in annotations {
+            let Some(entry) = self.annotations.get(annotation.name.original()) else {
+                continue;
+            };
+
+            for effect in &entry.effects {
+                let pointer = match ty {
+                    Type::PointerType(ptr_type) => Some(ptr_type),
+                    _ => None,
+                };
+                match (effect, pointer) {
+                    (AnnotationEffect::Optional, pointer) => {
+                        ret.optional = true;
+                        if let Some(pointer) = pointer {
+                            pointer.nullable = true;
+                        }
+                    }
+                    (AnnotationEffect::Nullable, Some(pointer)) => pointer.nullable = true,
+                    (AnnotationEffect::PointerToOne, Some(pointer)) => pointer.pointer_to_one = true,
+                    (AnnotationEffect::Nullable | AnnotationEffect::PointerToOne, None) => {
+                        self.messages.push(format!(
+                            "{}: annotation '{}' applies to pointers only",
+                            context, annotation.name.original()
+                        ));
+                    }
+                    (AnnotationEffect::Len(index), _) => match annotation.args.get(*index) {
+                        Some(len) => ret.len = Some(len.clone()),
+                        None => self.messages.push(format!(
+                            "{}: annotation '{}' has no argument {}",
+                            context, annotation.name.original(), index
+                        )),
+                    },
+                    (AnnotationEffect::Metadata(key), _) => {
+                        let value = if annotation.args.is_empty() {
+                            Metadata::None
+                        } else {
+                            let args = annotation.args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+                            Metadata::String { value: args.join(", ") }
+                        };
+                        ret.metadata.insert(key.clone(), value);
+                    }
+                    (AnnotationEffect::CallingConvention(calling_convention), _) => {
+                        ret.calling_convention = Some(*calling_convention);
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// Registry type of `ty`, reporting anonymous records and enums, which cannot be referred to.
+    ///
+    /// A function prototype in `ty` becomes a function typedef named `prototype`, the way rossetta
+    /// names them after their use, like `command_param` or `command_result`.
+    fn lower_type(&mut self, context: &str, prototype: &str, ty: &RawType<'a>) -> Option<Type<'a>> {
+        let lowered = ty.to_type(&mut |result, params, is_pointer| {
+            let name = prototype.interned();
+            self.lower_function_typedef(name.clone(), result, params, is_pointer, &[], Doc::default());
+            name
+        });
+        let error = |specifier| format!("{}: anonymous {} is not supported", context, specifier);
+        lowered.map_err(|specifier| self.messages.push(error(specifier))).ok()
+    }
+}
+
+struct Annotated<'a> {
+    optional: bool,
+    len: Option<CExpr<'a>>,
+    metadata: HashMap<String, Metadata>,
+    calling_convention: Option<CallingConvention>,
+}
+
+/// Give implicit enumerator values as literals while counting up from zero or a literal, and as
+/// the previous enumerator plus one otherwise.
+fn lower_enumerators<'a>(enumerators: &[RawEnumerator<'a>]) -> Vec<EnumVariant<'a>> {
+    let mut ret = Vec::new();
+    let mut next = int_literal(0);
+    for enumerator in enumerators {
+        let value = enumerator.value.clone().unwrap_or(next);
+        next = match &value {
+            CExpr::IntLiteral(literal) if literal.suffix.is_empty() => {
+                match int_literal_value(&literal.value).and_then(|n| n.checked_add(1)) {
+                    Some(n) => int_literal(n),
+                    None => successor(&enumerator.name),
+                }
+            }
+            _ => successor(&enumerator.name),
+        };
+        ret.push(EnumVariant {
+            name: enumerator.name.clone(),
+            metadata: HashMap::new(),
+            doc: Doc::parse(&enumerator.doc),
+            platform: None,
+            value,
+        });
+    }
+    ret
+}
+
+fn int_literal<'a>(n: u64) -> CExpr<'a> {
+    CExpr::IntLiteral(Box::new(CIntLiteralExpr::new(Cow::Owned(n.to_string()))))
+}
+
+fn successor<'a>(name: &Identifier) -> CExpr<'a> {
+    CExpr::Binary(Box::new(CBinaryExpr {
+        op: CBinaryOp::Add,
+        lhs: CExpr::identifier(name.clone()),
+        rhs: int_literal(1),
+    }))
+}
+
+/// Insert `entity`, or keep it as a platform variant of the entity declared for other platforms
+/// under the same name.
+fn insert_entity<T: PlatformVariants>(
+    entry: Entry<'_, Identifier, T>,
+    kind: &str,
+    entity: T,
+    messages: &mut Vec<String>,
+) {
+    match entry {
+        Entry::Occupied(mut entry) => {
+            if entry.get_mut().add_platform_variant(entity).is_err() {
+                messages.push(format!("duplicate {} '{}'", kind, entry.key().original()));
+            }
+        }
+        Entry::Vacant(entry) => {
+            entry.insert(entity);
+        }
+    }
+}
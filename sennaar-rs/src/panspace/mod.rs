@@ -1,4 +1,184 @@
 mod tok;
+mod annotation;
+mod cond;
+mod define;
+mod doc;
+mod lower;
+mod hparse;
 
-// use lalrpop_util::lalrpop_mod;
-// lalrpop_mod!(lalr, "/panspace/lalr.rs");
+pub use tok::*;
+pub use annotation::*;
+pub use cond::*;
+pub use define::*;
+pub use doc::*;
+pub use lower::*;
+pub use hparse::*;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+use lalrpop_util::{lalrpop_mod, ParseError};
+lalrpop_mod!(#[allow(clippy::all)] lalr, "/panspace/lalr.rs");
+
+use crate::{Diagnostic, SourceLocation};
+use crate::cpl::{CExpr, RawDeclaration, RawStorageClass};
+
+pub type PanspaceParseError<'input> = ParseError<usize, Token<'input>, LexError>;
+
+/// Parse a C expression, taking [`STANDARD_TYPE_NAMES`] as the only typedef names.
+pub fn parse_expression(source: &str) -> Result<CExpr<'_>, PanspaceParseError<'_>> {
+    lalr::ExpressionParser::new().parse(&DocScope::default(), Lexer::new(source))
+}
+
+/// Parse a C expression, taking the identifiers accepted by `type_names` as typedef names, so
+/// that casts to them can be told apart from parenthesized expressions.
+pub fn parse_expression_with_types<'input>(
+    source: &'input str,
+    type_names: &dyn Fn(&str) -> bool,
+) -> Result<CExpr<'input>, PanspaceParseError<'input>> {
+    lalr::ExpressionParser::new().parse(&DocScope::default(), Lexer::with_type_names(source, type_names))
+}
+
+/// Parse a sequence of C declarations, such as a preprocessed header without function bodies.
+///
+/// Names introduced by `typedef` are type names in the declarations that follow, in addition to
+/// the identifiers accepted by `type_names`.
+pub fn parse_declarations<'input>(
+    source: &'input str,
+    type_names: &dyn Fn(&str) -> bool,
+) -> Result<Vec<RawDeclaration<'input>>, PanspaceParseError<'input>> {
+    DeclarationReader::new(type_names).read(source)
+}
+
+/// Declaration parser remembering the typedef names seen so far, for sources that come in
+/// several pieces.
+pub struct DeclarationReader<'t> {
+    parser: lalr::DeclarationParser,
+    type_names: &'t dyn Fn(&str) -> bool,
+    annotations: Option<&'t AnnotationTable>,
+    typedefs: RefCell<HashSet<String>>,
+}
+
+impl<'t> DeclarationReader<'t> {
+    pub fn new(type_names: &'t dyn Fn(&str) -> bool) -> Self {
+        Self {
+            parser: lalr::DeclarationParser::new(),
+            type_names,
+            annotations: None,
+            typedefs: RefCell::new(HashSet::new()),
+        }
+    }
+
+    /// Recognize the macros of `annotations`, see [`Lexer::with_annotations`].
+    pub fn with_annotations(mut self, annotations: &'t AnnotationTable) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    pub fn is_type_name(&self, word: &str) -> bool {
+        (self.type_names)(word) || self.typedefs.borrow().contains(word)
+    }
+
+    pub fn read<'input>(
+        &self,
+        source: &'input str,
+    ) -> Result<Vec<RawDeclaration<'input>>, PanspaceParseError<'input>> {
+        let is_type_name = |word: &str| self.is_type_name(word);
+        let comments = doc_comments(source);
+        let docs = DocScope { source, comments: &comments, offset: 0 };
+
+        let mut declarations = Vec::new();
+        for (start, end) in split_declarations(source)? {
+            let mut lexer = Lexer::with_type_names(&source[start..end], &is_type_name);
+            if let Some(annotations) = self.annotations {
+                lexer = lexer.with_annotations(annotations);
+            }
+            let declaration = self.parser
+                .parse(&docs.at(start), lexer)
+                .map_err(|e| {
+                    e.map_location(|location| location + start).map_error(|e| LexError {
+                        start: e.start + start,
+                        end: e.end + start,
+                        message: e.message,
+                    })
+                })?;
+
+            if declaration.specifiers.storage == Some(RawStorageClass::Typedef) {
+                let mut typedefs = self.typedefs.borrow_mut();
+                for init in &declaration.declarators {
+                    if let Some(name) = init.declarator.name() {
+                        typedefs.insert(name.original().to_string());
+                    }
+                }
+            }
+            declarations.push(declaration);
+        }
+
+        Ok(declarations)
+    }
+}
+
+/// Render a parse error of the text at byte `offset` of `source`.
+pub fn parse_error_diagnostic(
+    file: &str,
+    source: &str,
+    offset: usize,
+    error: PanspaceParseError<'_>,
+) -> Diagnostic {
+    let (start, end, message, expected) = match error {
+        ParseError::InvalidToken { location } => (location, location + 1, "invalid token".to_string(), vec![]),
+        ParseError::UnrecognizedEof { location, expected } => {
+            (location, location, "unexpected end of input".to_string(), expected)
+        }
+        ParseError::UnrecognizedToken { token: (start, token, end), expected } => {
+            (start, end, format!("unexpected token `{}`", token), expected)
+        }
+        ParseError::ExtraToken { token: (start, token, end) } => {
+            (start, end, format!("extra token `{}`", token), vec![])
+        }
+        ParseError::User { error } => (error.start, error.end, error.message, vec![]),
+    };
+
+    let expected = expected
+        .iter()
+        .map(|terminal| match terminal.trim_matches('"') {
+            "ident" => "identifier".to_string(),
+            "type-name" => "type name".to_string(),
+            "int-literal" => "integer literal".to_string(),
+            "float-literal" => "float literal".to_string(),
+            "char-literal" => "character literal".to_string(),
+            "string-literal" => "string literal".to_string(),
+            fixed => format!("`{}`", fixed),
+        })
+        .collect();
+    Diagnostic::new(file, message)
+        .with_location(SourceLocation::from_span(source, offset + start, offset + end))
+        .with_expected(expected)
+}
+
+/// Split `source` after every `;` outside of braces and parentheses, so that each declaration
+/// can be lexed knowing the typedef names declared before it.
+fn split_declarations(source: &str) -> Result<Vec<(usize, usize)>, PanspaceParseError<'_>> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    let mut has_tokens = false;
+    let mut depth = 0usize;
+    for token in Lexer::new(source) {
+        let (_, token, end) = token.map_err(|error| ParseError::User { error })?;
+        has_tokens = true;
+        match token {
+            Token::Punct("{" | "(" | "[") => depth += 1,
+            Token::Punct("}" | ")" | "]") => depth = depth.saturating_sub(1),
+            Token::Punct(";") if depth == 0 => {
+                ret.push((start, end));
+                start = end;
+                has_tokens = false;
+            }
+            _ => {}
+        }
+    }
+    if has_tokens {
+        ret.push((start, source.len()));
+    }
+    Ok(ret)
+}
@@ -0,0 +1,416 @@
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use crate::{Identifier, Internalize};
+use crate::panspace::AnnotationTable;
+
+
+/// C11 keywords, which are never lexed as identifiers.
+pub const KEYWORDS: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while", "_Alignas", "_Alignof", "_Atomic", "_Bool",
+    "_Complex", "_Generic", "_Imaginary", "_Noreturn", "_Static_assert", "_Thread_local",
+];
+
+/// Typedef names from the C standard library, lexed as type names by default.
+pub const STANDARD_TYPE_NAMES: &[&str] = &[
+    "size_t", "ptrdiff_t", "wchar_t", "char16_t", "char32_t", "max_align_t",
+    "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+    "intptr_t", "uintptr_t", "intmax_t", "uintmax_t",
+];
+
+pub fn is_standard_type_name(word: &str) -> bool {
+    STANDARD_TYPE_NAMES.contains(&word)
+}
+
+/// C punctuators, longest first so that lexing can take the first match.
+const PUNCTUATORS: &[&str] = &[
+    "%:%:", "...", "<<=", ">>=",
+    "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=", "+=",
+    "-=", "&=", "^=", "|=", "##", "<:", ":>", "<%", "%>", "%:",
+    "[", "]", "(", ")", "{", "}", ".", "&", "*", "+", "-", "~", "!", "/", "%", "<", ">", "^",
+    "|", "?", ":", ";", "=", ",", "#",
+];
+
+/// Numeric literal split into its value and suffix, both as spelled in the source.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NumberLiteral<'input> {
+    pub value: Cow<'input, str>,
+    pub suffix: Cow<'input, str>,
+}
+
+/// Character or string literal, without quotes and with escapes kept as spelled.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextLiteral<'input> {
+    /// Encoding prefix, one of `""`, `"L"`, `"u"`, `"U"` and `"u8"`.
+    pub prefix: &'input str,
+    pub value: Cow<'input, str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token<'input> {
+    Ident(Identifier),
+    /// Identifier naming a type, which C cannot tell apart from other identifiers by syntax
+    /// alone.
+    TypeName(Identifier),
+    /// Annotation macro, see [`AnnotationTable`].
+    Annotation(Identifier),
+    /// Annotation macro followed by arguments.
+    AnnotationCall(Identifier),
+    Keyword(&'static str),
+    IntLiteral(NumberLiteral<'input>),
+    FloatLiteral(NumberLiteral<'input>),
+    CharLiteral(TextLiteral<'input>),
+    StringLiteral(TextLiteral<'input>),
+    Punct(&'static str),
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Token::Ident(ident)
+            | Token::TypeName(ident)
+            | Token::Annotation(ident)
+            | Token::AnnotationCall(ident) => write!(f, "{}", ident.original()),
+            Token::Keyword(s) | Token::Punct(s) => write!(f, "{}", s),
+            Token::IntLiteral(n) | Token::FloatLiteral(n) => write!(f, "{}{}", n.value, n.suffix),
+            Token::CharLiteral(c) => write!(f, "{}'{}'", c.prefix, c.value),
+            Token::StringLiteral(s) => write!(f, "{}\"{}\"", s.prefix, s.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LexError {
+    /// Byte offsets of the offending text.
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} at {}..{}", self.message, self.start, self.end)
+    }
+}
+
+impl std::error::Error for LexError {}
+
+pub type Spanned<'input> = (usize, Token<'input>, usize);
+
+/// Tokenizer for C source, as consumed by the panspace grammar.
+///
+/// Whitespace, comments and line continuations are skipped. Preprocessing is not performed, so
+/// `#` and `##` come out as punctuators.
+pub struct Lexer<'input, 'names> {
+    source: &'input str,
+    pos: usize,
+    type_names: &'names dyn Fn(&str) -> bool,
+    annotations: Option<&'names AnnotationTable>,
+}
+
+impl<'input, 'names> Lexer<'input, 'names> {
+    /// Create a lexer recognizing [`STANDARD_TYPE_NAMES`] as type names.
+    pub fn new(source: &'input str) -> Self {
+        Self::with_type_names(source, &is_standard_type_name)
+    }
+
+    /// Create a lexer recognizing the identifiers accepted by `type_names` as type names.
+    pub fn with_type_names(source: &'input str, type_names: &'names dyn Fn(&str) -> bool) -> Self {
+        Self { source, pos: 0, type_names, annotations: None }
+    }
+
+    /// Recognize the macros of `annotations` as annotations instead of identifiers.
+    pub fn with_annotations(mut self, annotations: &'names AnnotationTable) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    fn rest(&self) -> &'input str {
+        &self.source[self.pos..]
+    }
+
+    fn error<T>(&mut self, start: usize, end: usize, message: impl ToString) -> Option<Result<T, LexError>> {
+        // Give up on the rest of the input, the parser stops at the first error anyway
+        self.pos = self.source.len();
+        Some(Err(LexError { start, end, message: message.to_string() }))
+    }
+
+    /// Skip whitespace and comments, failing on unterminated block comments.
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
+        loop {
+            let rest = self.rest();
+            if let Some(c) = rest.chars().next().filter(|c| c.is_whitespace()) {
+                self.pos += c.len_utf8();
+            } else if rest.starts_with("\\\n") {
+                self.pos += 2;
+            } else if rest.starts_with("\\\r\n") {
+                self.pos += 3;
+            } else if rest.starts_with("//") {
+                self.pos += rest.find('\n').unwrap_or(rest.len());
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                match comment.find("*/") {
+                    Some(end) => self.pos += end + 4,
+                    None => {
+                        let start = self.pos;
+                        self.pos = self.source.len();
+                        return Err(LexError {
+                            start,
+                            end: start + 2,
+                            message: "unterminated block comment".to_string(),
+                        });
+                    }
+                }
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn lex_word(&mut self) -> Option<Result<Spanned<'input>, LexError>> {
+        let start = self.pos;
+        let len = self.rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        let word = &self.source[start..start + len];
+        self.pos += len;
+
+        if matches!(word, "L" | "u" | "U" | "u8") {
+            match self.rest().chars().next() {
+                Some('\'') => return self.lex_text(start, word, '\''),
+                Some('"') => return self.lex_text(start, word, '"'),
+                _ => {}
+            }
+        }
+
+        let token = match KEYWORDS.iter().find(|k| **k == word) {
+            Some(keyword) => Token::Keyword(keyword),
+            None if (self.type_names)(word) => Token::TypeName(word.interned()),
+            None => match self.annotations.and_then(|annotations| annotations.get(word)) {
+                Some(annotation) if annotation.takes_args => Token::AnnotationCall(word.interned()),
+                Some(_) => Token::Annotation(word.interned()),
+                None => Token::Ident(word.interned()),
+            },
+        };
+        Some(Ok((start, token, self.pos)))
+    }
+
+    /// Lex a character or string literal whose opening quote is at the current position.
+    fn lex_text(&mut self, start: usize, prefix: &'input str, quote: char) -> Option<Result<Spanned<'input>, LexError>> {
+        let content_start = self.pos + 1;
+        let mut chars = self.source[content_start..].char_indices();
+
+        let content_end = loop {
+            let Some((i, c)) = chars.next() else {
+                return self.error(start, self.source.len(), "unterminated literal");
+            };
+
+            match c {
+                '\n' => return self.error(start, content_start + i, "unterminated literal"),
+                '\\' => {
+                    let escape_start = content_start + i;
+                    if let Err(message) = check_escape(&mut chars) {
+                        let escape_end = chars.clone().next().map_or(self.source.len(), |(j, _)| content_start + j);
+                        return self.error(escape_start, escape_end, message);
+                    }
+                }
+                c if c == quote => break content_start + i,
+                _ => {}
+            }
+        };
+
+        self.pos = content_end + 1;
+        let literal = TextLiteral {
+            prefix,
+            value: Cow::Borrowed(&self.source[content_start..content_end]),
+        };
+
+        let token = if quote == '\'' {
+            if literal.value.is_empty() {
+                return self.error(start, self.pos, "empty character literal");
+            }
+            if prefix == "u8" {
+                return self.error(start, self.pos, "'u8' prefix is not allowed on character literals");
+            }
+            Token::CharLiteral(literal)
+        } else {
+            Token::StringLiteral(literal)
+        };
+        Some(Ok((start, token, self.pos)))
+    }
+
+    fn lex_number(&mut self) -> Option<Result<Spanned<'input>, LexError>> {
+        let start = self.pos;
+
+        // Take a whole preprocessing number first, then make sense of it
+        let bytes = self.source.as_bytes();
+        let mut end = start;
+        while end < bytes.len() {
+            let b = bytes[end];
+            let exponent_sign = (b == b'+' || b == b'-')
+                && matches!(bytes[end - 1], b'e' | b'E' | b'p' | b'P');
+            if b.is_ascii_alphanumeric() || b == b'_' || b == b'.' || exponent_sign {
+                end += 1;
+            } else {
+                break;
+            }
+        }
+        self.pos = end;
+
+        match split_number(&self.source[start..end]) {
+            Ok((value, suffix, is_float)) => {
+                let literal = NumberLiteral {
+                    value: Cow::Borrowed(value),
+                    suffix: Cow::Borrowed(suffix),
+                };
+                let token = if is_float { Token::FloatLiteral(literal) } else { Token::IntLiteral(literal) };
+                Some(Ok((start, token, end)))
+            }
+            Err(message) => self.error(start, end, message),
+        }
+    }
+
+    fn lex_punct(&mut self) -> Option<Result<Spanned<'input>, LexError>> {
+        let start = self.pos;
+        match PUNCTUATORS.iter().find(|p| self.rest().starts_with(**p)) {
+            Some(punct) => {
+                self.pos += punct.len();
+                Some(Ok((start, Token::Punct(punct), self.pos)))
+            }
+            None => {
+                let c = self.rest().chars().next().unwrap();
+                self.error(start, start + c.len_utf8(), format!("unexpected character '{}'", c.escape_default()))
+            }
+        }
+    }
+}
+
+impl<'input> Iterator for Lexer<'input, '_> {
+    type Item = Result<Spanned<'input>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Err(e) = self.skip_trivia() {
+            return Some(Err(e));
+        }
+
+        let rest = self.rest();
+        let mut chars = rest.chars();
+        let first = chars.next()?;
+        let second = chars.next();
+
+        if first.is_ascii_alphabetic() || first == '_' {
+            self.lex_word()
+        } else if first.is_ascii_digit() || (first == '.' && second.is_some_and(|c| c.is_ascii_digit())) {
+            self.lex_number()
+        } else if first == '\'' || first == '"' {
+            self.lex_text(self.pos, "", first)
+        } else {
+            self.lex_punct()
+        }
+    }
+}
+
+/// Validate one escape sequence, the backslash having been consumed already.
+fn check_escape(chars: &mut std::str::CharIndices<'_>) -> Result<(), String> {
+    let hex_digits = |chars: &mut std::str::CharIndices<'_>, min: usize, max: usize| {
+        let mut count = 0;
+        while count < max && chars.clone().next().is_some_and(|(_, c)| c.is_ascii_hexdigit()) {
+            chars.next();
+            count += 1;
+        }
+        count >= min
+    };
+
+    match chars.next().map(|(_, c)| c) {
+        Some('\'' | '"' | '?' | '\\' | 'a' | 'b' | 'f' | 'n' | 'r' | 't' | 'v') => Ok(()),
+        Some('0'..='7') => {
+            for _ in 0..2 {
+                if chars.clone().next().is_some_and(|(_, c)| ('0'..='7').contains(&c)) {
+                    chars.next();
+                }
+            }
+            Ok(())
+        }
+        Some('x') if hex_digits(chars, 1, usize::MAX) => Ok(()),
+        Some('x') => Err("\\x used with no following hex digits".to_string()),
+        Some('u') if hex_digits(chars, 4, 4) => Ok(()),
+        Some('U') if hex_digits(chars, 8, 8) => Ok(()),
+        Some(c @ ('u' | 'U')) => Err(format!("incomplete universal character name \\{}", c)),
+        Some(c) => Err(format!("unknown escape sequence '\\{}'", c.escape_default())),
+        None => Err("unterminated literal".to_string()),
+    }
+}
+
+/// Split a preprocessing number into value and suffix, and tell whether it is floating.
+fn split_number(s: &str) -> Result<(&str, &str, bool), String> {
+    let bytes = s.as_bytes();
+    let (radix_prefix, digits_ok): (usize, fn(u8) -> bool) =
+        if s.len() > 1 && bytes[0] == b'0' && matches!(bytes[1], b'x' | b'X') {
+            (2, |b: u8| b.is_ascii_hexdigit())
+        } else if s.len() > 1 && bytes[0] == b'0' && matches!(bytes[1], b'b' | b'B') {
+            (2, |b: u8| b == b'0' || b == b'1')
+        } else {
+            (0, |b: u8| b.is_ascii_digit())
+        };
+    let is_hex = radix_prefix == 2 && matches!(bytes[1], b'x' | b'X');
+
+    let mut i = radix_prefix;
+    while i < bytes.len() && digits_ok(bytes[i]) {
+        i += 1;
+    }
+
+    let exponent_chars: &[u8] = if is_hex { b"pP" } else { b"eE" };
+    let is_float = i < bytes.len()
+        && (bytes[i] == b'.' || exponent_chars.contains(&bytes[i]))
+        && (radix_prefix == 0 || is_hex);
+
+    if is_float {
+        if bytes[i] == b'.' {
+            i += 1;
+            while i < bytes.len() && digits_ok(bytes[i]) {
+                i += 1;
+            }
+        }
+        if i < bytes.len() && exponent_chars.contains(&bytes[i]) {
+            i += 1;
+            if i < bytes.len() && matches!(bytes[i], b'+' | b'-') {
+                i += 1;
+            }
+            let exponent_start = i;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            if i == exponent_start {
+                return Err(format!("exponent has no digits in '{}'", s));
+            }
+        } else if is_hex {
+            return Err(format!("hexadecimal floating literal '{}' requires an exponent", s));
+        }
+
+        let (value, suffix) = s.split_at(i);
+        return match suffix {
+            "" | "f" | "F" | "l" | "L" => Ok((value, suffix, true)),
+            _ => Err(format!("invalid suffix '{}' on floating literal", suffix)),
+        };
+    }
+
+    if i == radix_prefix && radix_prefix != 0 {
+        return Err(format!("no digits in integer literal '{}'", s));
+    }
+    let (value, suffix) = s.split_at(i);
+    if radix_prefix == 0 && value.len() > 1 && value.starts_with('0') && value.bytes().any(|b| b > b'7') {
+        return Err(format!("invalid digit in octal literal '{}'", value));
+    }
+
+    let lowercase = suffix.to_ascii_lowercase();
+    let valid_suffix = matches!(lowercase.as_str(), "" | "u" | "l" | "ll" | "ul" | "lu" | "ull" | "llu")
+        && !suffix.contains("lL")
+        && !suffix.contains("Ll");
+    if valid_suffix {
+        Ok((value, suffix, false))
+    } else {
+        Err(format!("invalid suffix '{}' on integer literal", suffix))
+    }
+}
@@ -0,0 +1,315 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{Identifier, Internalize};
+
+
+/// Documentation of an entity, split into the parts backends render separately.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(JsonSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Doc {
+    /// One-paragraph summary.
+    pub brief: DocText,
+    /// Paragraphs following the summary.
+    pub description: Vec<DocText>,
+    pub params: Vec<DocParam>,
+    pub returns: DocText,
+    /// Entities referred to by `@see`.
+    pub see: Vec<Identifier>,
+    /// Deprecation note, possibly empty, if the entity is deprecated.
+    pub deprecated: Option<DocText>,
+}
+
+pub type DocText = Vec<DocSpan>;
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(JsonSchema)]
+pub struct DocParam {
+    pub name: Identifier,
+    pub text: DocText,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(JsonSchema)]
+#[serde(tag = "$kind")]
+pub enum DocSpan {
+    Text { value: String },
+    /// Inline code, from `` `code` ``, `@c` or `@p`.
+    Code { value: String },
+    /// Cross-reference to another entity, from `@ref`.
+    Link { target: Identifier },
+}
+
+/// Section the lines of a comment are added to.
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Brief,
+    Description,
+    Param(usize),
+    Returns,
+    Deprecated,
+}
+
+impl Doc {
+    /// Read the lines of a Doxygen or Javadoc comment, without comment markers.
+    ///
+    /// Commands start with either `@` or `\`. Without `@brief`, the first paragraph is the brief.
+    /// Lines of unknown block commands are kept as text. A `\` before `@`, `\` or a backtick makes
+    /// it literal, in text and in code spans.
+    pub fn parse<S: AsRef<str>>(lines: &[S]) -> Self {
+        let mut brief = String::new();
+        let mut description: Vec<String> = Vec::new();
+        let mut params: Vec<(String, String)> = Vec::new();
+        let mut returns = String::new();
+        let mut deprecated: Option<String> = None;
+        let mut see = Vec::new();
+
+        let mut section = Section::Brief;
+        for line in lines {
+            let line = line.as_ref().trim();
+            if line.is_empty() {
+                if section != Section::Brief || !brief.is_empty() {
+                    section = Section::Description;
+                    description.push(String::new());
+                }
+                continue;
+            }
+
+            let (command, rest) = match block_command(line) {
+                Some((command, rest)) => (command, rest),
+                None => ("", line),
+            };
+            let rest = match command {
+                "brief" | "short" => {
+                    section = Section::Brief;
+                    rest
+                }
+                "details" => {
+                    section = Section::Description;
+                    description.push(String::new());
+                    rest
+                }
+                "param" | "tparam" => {
+                    // `@param[in,out] name text`
+                    let rest = match rest.strip_prefix('[') {
+                        Some(rest) => rest.split_once(']').map_or("", |(_, rest)| rest.trim_start()),
+                        None => rest,
+                    };
+                    let (name, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    section = Section::Param(params.len());
+                    params.push((name.to_string(), String::new()));
+                    text
+                }
+                "return" | "returns" | "result" => {
+                    section = Section::Returns;
+                    rest
+                }
+                "deprecated" => {
+                    section = Section::Deprecated;
+                    deprecated.get_or_insert_with(String::new);
+                    rest
+                }
+                "see" | "sa" => {
+                    see.extend(
+                        rest.split(|c: char| c.is_whitespace() || c == ',')
+                            .map(|name| name.trim_end_matches("()"))
+                            .filter(|name| !name.is_empty())
+                            .map(|name| name.interned()),
+                    );
+                    continue;
+                }
+                _ => line,
+            };
+
+            let text = match section {
+                Section::Brief => &mut brief,
+                Section::Description => description.last_mut().unwrap(),
+                Section::Param(i) => &mut params[i].1,
+                Section::Returns => &mut returns,
+                Section::Deprecated => deprecated.as_mut().unwrap(),
+            };
+            if !text.is_empty() && !rest.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(rest);
+        }
+
+        Self {
+            brief: parse_spans(&brief),
+            description: description
+                .iter()
+                .filter(|paragraph| !paragraph.is_empty())
+                .map(|paragraph| parse_spans(paragraph))
+                .collect(),
+            params: params
+                .iter()
+                .map(|(name, text)| DocParam { name: name.interned(), text: parse_spans(text) })
+                .collect(),
+            returns: parse_spans(&returns),
+            see,
+            deprecated: deprecated.as_deref().map(parse_spans),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Doxygen lines that [`Doc::parse`] reads back into the same documentation.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if !self.brief.is_empty() {
+            lines.push(write_spans(&self.brief));
+        } else if !self.description.is_empty() {
+            lines.push("@details".to_string());
+        }
+        for (i, paragraph) in self.description.iter().enumerate() {
+            if i > 0 || !self.brief.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(write_spans(paragraph));
+        }
+        for param in &self.params {
+            lines.push(format!("@param {} {}", param.name.original(), write_spans(&param.text)).trim_end().to_string());
+        }
+        if !self.returns.is_empty() {
+            lines.push(format!("@return {}", write_spans(&self.returns)));
+        }
+        if let Some(deprecated) = &self.deprecated {
+            lines.push(format!("@deprecated {}", write_spans(deprecated)).trim_end().to_string());
+        }
+        for see in &self.see {
+            lines.push(format!("@see {}", see.original()));
+        }
+        lines
+    }
+}
+
+/// Read a [`Doc`], or the array of comment lines registries stored before it, see [`Doc::parse`].
+pub fn deserialize_doc<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Doc, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum DocOrLines {
+        Lines(Vec<String>),
+        Doc(Doc),
+    }
+
+    Ok(match DocOrLines::deserialize(deserializer)? {
+        DocOrLines::Lines(lines) => Doc::parse(&lines),
+        DocOrLines::Doc(doc) => doc,
+    })
+}
+
+/// Split `@command rest` or `\command rest`.
+fn block_command(line: &str) -> Option<(&str, &str)> {
+    let line = line.strip_prefix('@').or_else(|| line.strip_prefix('\\'))?;
+    let end = line.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(line.len());
+    if end == 0 {
+        return None;
+    }
+    Some((&line[..end], line[end..].trim_start()))
+}
+
+/// Characters a `\` makes literal, in text and in code spans.
+const ESCAPED: [char; 3] = ['\\', '@', '`'];
+
+fn parse_spans(text: &str) -> DocText {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let at_word = plain.is_empty() || plain.ends_with(char::is_whitespace);
+        if c == '\\'
+            && let Some(escaped) = rest[1..].chars().next()
+            && ESCAPED.contains(&escaped)
+        {
+            plain.push(escaped);
+            rest = &rest[2..];
+            continue;
+        }
+        if c == '`'
+            && let Some((value, after)) = code_span(&rest[1..])
+        {
+            flush_text(&mut spans, &mut plain);
+            spans.push(DocSpan::Code { value });
+            rest = after;
+            continue;
+        }
+        if at_word
+            && let Some((command, after)) = block_command(rest)
+            && ["c", "p", "ref"].contains(&command)
+            && !after.is_empty()
+        {
+            let word_len = after.find(char::is_whitespace).unwrap_or(after.len());
+            let word = after[..word_len].trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+            flush_text(&mut spans, &mut plain);
+            spans.push(match command {
+                "ref" => DocSpan::Link { target: word.trim_end_matches("()").interned() },
+                _ => DocSpan::Code { value: word.to_string() },
+            });
+            rest = &after[word.len()..];
+            continue;
+        }
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    flush_text(&mut spans, &mut plain);
+    spans
+}
+
+/// Read a code span up to its closing backtick, given the text after the opening one. Returns
+/// the code and the text after the span, or `None` if it is not closed.
+fn code_span(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '`' => return Some((value, &text[i + 1..])),
+            '\\' if text[i + 1..].starts_with(['\\', '`']) => value.extend(chars.next().map(|(_, c)| c)),
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+fn flush_text(spans: &mut DocText, plain: &mut String) {
+    if !plain.is_empty() {
+        spans.push(DocSpan::Text { value: std::mem::take(plain) });
+    }
+}
+
+fn write_spans(spans: &[DocSpan]) -> String {
+    spans
+        .iter()
+        .map(|span| match span {
+            DocSpan::Text { value } => escape_text(value),
+            DocSpan::Code { value } => format!("`{}`", value.replace('\\', "\\\\").replace('`', "\\`")),
+            DocSpan::Link { target } => format!("@ref {}", target.original()),
+        })
+        .collect()
+}
+
+/// Escape what [`parse_spans`] would read as markup in plain text: backticks, `@` or `\` starting
+/// a word, which may be a command, and `\` before a character it escapes.
+fn escape_text(text: &str) -> String {
+    let mut ret = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut at_word = true;
+    while let Some(c) = chars.next() {
+        let next = chars.peek().copied();
+        let escape = match c {
+            '`' => true,
+            '@' => at_word && next.is_some_and(|next| next.is_ascii_alphabetic()),
+            // At the end, a code span may follow
+            '\\' => next.is_none_or(|next| ESCAPED.contains(&next) || (at_word && next.is_ascii_alphabetic())),
+            _ => false,
+        };
+        if escape {
+            ret.push('\\');
+        }
+        ret.push(c);
+        at_word = c.is_whitespace();
+    }
+    ret
+}
@@ -0,0 +1,751 @@
+//! The human-writable registry DSL.
+//!
+//! A registry file starts with a `registry` header, followed by registry-level items and entity
+//! declarations:
+//!
+//! ```text
+//! registry "vulkan";
+//!
+//! metadef vendor = "Khronos";
+//! import vk_platform;
+//! depend vk_video "1.0";
+//!
+//! @doc("Result codes")
+//! enum VkResult {
+//!     VK_SUCCESS = 0,
+//!     VK_NOT_READY = 1,
+//! }
+//!
+//! @success(VK_SUCCESS)
+//! command vkCreateInstance(
+//!     pCreateInfo: *const VkInstanceCreateInfo,
+//!     @optional pAllocator: ?*const VkAllocationCallbacks,
+//!     pInstance: *VkInstance,
+//! ) -> VkResult;
+//! ```
+//!
+//! `import` and `depend` both add an [`Import`], the latter with `depend` set. Entities are
+//! `alias`, `bitmask`, `constant`, `command`, `enum`, `functype`, `macro`, `opaque`,
+//! `handle`, `struct` and `union`. Every entity (including params, members, bitflags and enum
+//! variants) may be prefixed with `@doc("...")`, `@platform("...")` and `@meta(key = value)`
+//! attributes, plus the entity-specific ones (`@optional`, `@len(...)`, `@bits(...)`,
+//! `@init(...)`, `@success(...)`, `@errors(...)`, `@alias(...)`, `@pointer`, `@native_api`,
+//! `@callconv(...)`).
+//!
+//! Commands and `functype`s that do not use the default C calling convention of the platform
+//! name theirs with `@callconv`, like `@callconv(StdCall)`, using the names of
+//! [`CallingConvention`]. Variadic ones end their parameters with `...`:
+//! `command printf(format: *const char, ...) -> int;`.
+//!
+//! An entity may be declared again under its name for another `@platform`, and is then kept as one
+//! of the [`PlatformVariants`] of the first declaration, which must also have a `@platform`.
+//!
+//! Each `@doc` attribute is one line of a Doxygen comment, read into a [`Doc`] with
+//! [`Doc::parse`].
+//!
+//! Types are written prefix-style: `T`, `[T; N]`, `[T]`, `*T`, `*const T`. A leading `?` marks a
+//! nullable pointer and `&` replaces `*` for pointers to exactly one element. After the `*` come
+//! `restrict`, which qualifies the pointer, then `const`, `volatile` and `_Atomic`, which qualify
+//! what it points to: `*restrict volatile _Atomic int` is `volatile _Atomic int * restrict`. The
+//! qualifiers of a pointer that nothing points to are written before it, so `const ?*char` is a
+//! nullable `char * const`. Identifiers that are not plain C identifiers, or that carry a rename,
+//! are quoted with backticks using the same `original:renamed` form as JSON.
+//!
+//! Function-like macros are written `macro NAME(a, b, ...) = expression;`.
+//!
+//! In expressions, casts are written `cast(T) expr`, and the type operators `sizeof_type(T)`,
+//! `alignof_type(T)` and `offsetof(T, member)`, where `T` is a type written as above.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use lalrpop_util::lexer::Token;
+use lalrpop_util::{ErrorRecovery, ParseError};
+
+use crate::cpl::CExpr;
+use crate::registry::entity::*;
+use crate::registry::lalr::RegistryParser;
+use crate::registry::{Doc, Metadata, Platform, Registry, Type};
+use crate::{Diagnostic, Identifier, Internalize, SourceLocation};
+
+
+/// Parse a registry from DSL source.
+///
+/// `file` is only used to label diagnostics. The parser recovers at item boundaries, so all
+/// syntax and semantic errors found in one run are reported together.
+pub fn parse_registry<'a>(file: &str, source: &'a str) -> Result<Registry<'a>, Vec<Diagnostic>> {
+    let mut errors = Vec::new();
+    let mut renames = Vec::new();
+    let result = RegistryParser::new().parse(&mut errors, &mut renames, source);
+
+    let registry = match result {
+        Ok(registry) => Some(registry),
+        Err(e) => {
+            errors.push(DslError::from_parse_error(e));
+            None
+        }
+    };
+
+    // Ahead of the errors of the items they are in, which are at the same spans
+    let mut rename_errors = Vec::new();
+    check_renames(&renames, &mut rename_errors);
+    errors.splice(0..0, rename_errors);
+
+    match registry {
+        Some(registry) if errors.is_empty() => {
+            for rename in renames {
+                rename.ident.rename(rename.renamed);
+            }
+            Ok(registry)
+        }
+        _ => {
+            errors.sort_by_key(|e| e.span);
+            Err(errors.into_iter().map(|e| e.into_diagnostic(file, source)).collect())
+        }
+    }
+}
+
+pub(crate) type Span = (usize, usize);
+
+/// A located error collected while parsing, converted to a [`Diagnostic`] once parsing is done.
+pub(crate) struct DslError {
+    span: Span,
+    message: String,
+    expected: Vec<String>,
+}
+
+impl DslError {
+    pub(crate) fn new(span: Span, message: impl ToString) -> Self {
+        Self { span, message: message.to_string(), expected: Vec::new() }
+    }
+
+    pub(crate) fn from_recovery(recovery: ErrorRecovery<usize, Token<'_>, &'static str>) -> Self {
+        Self::from_parse_error(recovery.error)
+    }
+
+    fn from_parse_error(error: ParseError<usize, Token<'_>, &'static str>) -> Self {
+        match error {
+            ParseError::InvalidToken { location } => {
+                Self::new((location, location + 1), "invalid token")
+            }
+            ParseError::UnrecognizedEof { location, expected } => Self {
+                span: (location, location),
+                message: "unexpected end of file".to_string(),
+                expected: describe_expected(&expected),
+            },
+            ParseError::UnrecognizedToken { token: (start, Token(_, text), end), expected } => {
+                let at_item_start = expected.iter().any(|e| e == "\"struct\"");
+                let message = if at_item_start && is_identifier_like(text) {
+                    format!("unknown entity kind `{}`", text)
+                } else {
+                    format!("unexpected token `{}`", text)
+                };
+
+                Self {
+                    span: (start, end),
+                    message,
+                    expected: describe_expected(&expected),
+                }
+            }
+            ParseError::ExtraToken { token: (start, Token(_, text), end) } => {
+                Self::new((start, end), format!("extra token `{}`", text))
+            }
+            ParseError::User { error } => Self::new((0, 0), error),
+        }
+    }
+
+    fn into_diagnostic(self, file: &str, source: &str) -> Diagnostic {
+        Diagnostic::new(file, self.message)
+            .with_location(SourceLocation::from_span(source, self.span.0, self.span.1))
+            .with_expected(self.expected)
+    }
+}
+
+fn is_identifier_like(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Turn lalrpop's terminal names into something readable: keywords and punctuation are
+/// backtick-quoted, regex terminals are named after what they match.
+fn describe_expected(expected: &[String]) -> Vec<String> {
+    let mut ret = Vec::new();
+    for terminal in expected {
+        let described = if let Some(fixed) = terminal.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+            format!("`{}`", fixed.replace("\\\"", "\""))
+        } else if terminal.contains("A-Za-z_") {
+            "identifier".to_string()
+        } else if terminal.contains('`') {
+            "quoted identifier".to_string()
+        } else if terminal.contains("eE") {
+            "float literal".to_string()
+        } else if terminal.contains("xX") {
+            "integer literal".to_string()
+        } else if terminal.contains("'") {
+            "character literal".to_string()
+        } else {
+            "string literal".to_string()
+        };
+
+        if !ret.contains(&described) {
+            ret.push(described);
+        }
+    }
+    ret
+}
+
+pub(crate) enum RegistryItem<'a> {
+    Metadef(String, String),
+    Import(Import),
+    Ext(serde_json::Value),
+    Alias(Typedef<'a>),
+    Bitmask(Bitmask<'a>),
+    Constant(Constant<'a>),
+    Command(Command<'a>),
+    Enumeration(Enumeration<'a>),
+    FunctionTypedef(FunctionTypedef<'a>),
+    Macro(Macro<'a>),
+    OpaqueTypedef(OpaqueTypedef),
+    OpaqueHandleTypedef(OpaqueHandleTypedef),
+    Struct(Structure<'a>),
+    Union(Structure<'a>),
+}
+
+pub(crate) enum Attr<'a> {
+    Doc(String),
+    Platform(Platform),
+    Metadata(String, Metadata),
+    Optional,
+    Len(CExpr<'a>),
+    Bits(usize),
+    Init(CExpr<'a>),
+    Success(Vec<CExpr<'a>>),
+    Errors(Vec<CExpr<'a>>),
+    Alias(Identifier),
+    Pointer,
+    NativeApi,
+    CallingConvention(CallingConvention),
+}
+
+impl<'a> Attr<'a> {
+    fn describe(&self) -> &'static str {
+        match self {
+            Attr::Doc(_) => "@doc",
+            Attr::Platform(_) => "@platform",
+            Attr::Metadata(_, _) => "@meta",
+            Attr::Optional => "@optional",
+            Attr::Len(_) => "@len",
+            Attr::Bits(_) => "@bits",
+            Attr::Init(_) => "@init",
+            Attr::Success(_) => "@success",
+            Attr::Errors(_) => "@errors",
+            Attr::Alias(_) => "@alias",
+            Attr::Pointer => "@pointer",
+            Attr::NativeApi => "@native_api",
+            Attr::CallingConvention(_) => "@callconv",
+        }
+    }
+}
+
+/// Attributes attached to one entity, with the common ones already split out.
+pub(crate) struct Attrs<'a> {
+    metadata: HashMap<String, Metadata>,
+    doc: Vec<String>,
+    platform: Option<Platform>,
+    extra: Vec<(Attr<'a>, Span)>,
+}
+
+impl<'a> Attrs<'a> {
+    pub(crate) fn collect(attrs: Vec<(Option<Attr<'a>>, Span)>, errors: &mut Vec<DslError>) -> Self {
+        let mut metadata = HashMap::new();
+        let mut doc = Vec::new();
+        let mut platform = None;
+        let mut extra: Vec<(Attr<'a>, Span)> = Vec::new();
+
+        for (attr, span) in attrs {
+            let Some(attr) = attr else { continue };
+            match attr {
+                Attr::Doc(line) => doc.push(line),
+                Attr::Platform(p) => {
+                    if platform.is_some() {
+                        errors.push(DslError::new(span, "duplicate @platform attribute"));
+                    } else {
+                        platform = Some(p);
+                    }
+                }
+                Attr::Metadata(key, value) => {
+                    match metadata.entry(key) {
+                        Entry::Occupied(e) => errors.push(
+                            DslError::new(span, format!("duplicate metadata key '{}'", e.key()))
+                        ),
+                        Entry::Vacant(e) => {
+                            e.insert(value);
+                        }
+                    }
+                }
+                attr => {
+                    let name = attr.describe();
+                    if extra.iter().any(|(a, _)| a.describe() == name) {
+                        errors.push(DslError::new(span, format!("duplicate {} attribute", name)));
+                    } else {
+                        extra.push((attr, span));
+                    }
+                }
+            }
+        }
+
+        Self { metadata, doc, platform, extra }
+    }
+
+    fn take(&mut self, name: &str) -> Option<Attr<'a>> {
+        let idx = self.extra.iter().position(|(a, _)| a.describe() == name)?;
+        Some(self.extra.remove(idx).0)
+    }
+
+    fn take_flag(&mut self, name: &str) -> bool {
+        self.take(name).is_some()
+    }
+
+    fn take_expr(&mut self, name: &str) -> Option<CExpr<'a>> {
+        match self.take(name)? {
+            Attr::Len(e) | Attr::Init(e) => Some(e),
+            _ => unreachable!(),
+        }
+    }
+
+    fn take_exprs(&mut self, name: &str) -> Vec<CExpr<'a>> {
+        match self.take(name) {
+            Some(Attr::Success(v)) | Some(Attr::Errors(v)) => v,
+            None => Vec::new(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn take_bits(&mut self) -> Option<usize> {
+        match self.take("@bits")? {
+            Attr::Bits(bits) => Some(bits),
+            _ => unreachable!(),
+        }
+    }
+
+    fn take_alias(&mut self) -> Option<Identifier> {
+        match self.take("@alias")? {
+            Attr::Alias(ident) => Some(ident),
+            _ => unreachable!(),
+        }
+    }
+
+    fn take_calling_convention(&mut self) -> Option<CallingConvention> {
+        match self.take("@callconv")? {
+            Attr::CallingConvention(calling_convention) => Some(calling_convention),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Report every attribute not taken by the entity constructor.
+    fn finish(&mut self, kind: &str, errors: &mut Vec<DslError>) {
+        for (attr, span) in self.extra.drain(..) {
+            errors.push(DslError::new(span, format!("{} is not applicable to {}", attr.describe(), kind)));
+        }
+    }
+}
+
+macro_rules! make_entity {
+    ($ty:ident, $attrs:expr, $name:expr $(, $field:ident: $value:expr)* $(,)?) => {
+        $ty {
+            name: $name,
+            metadata: $attrs.metadata,
+            doc: Doc::parse(&$attrs.doc),
+            platform: $attrs.platform,
+            $($field: $value),*
+        }
+    };
+}
+
+pub(crate) fn make_alias<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    target: Type<'a>,
+    errors: &mut Vec<DslError>,
+) -> Typedef<'a> {
+    attrs.finish("alias", errors);
+    make_entity!(Typedef, attrs, name, target: target, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_bitmask<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    bitwidth: Bitwidth,
+    bitflags: Vec<Bitflag<'a>>,
+    errors: &mut Vec<DslError>,
+) -> Bitmask<'a> {
+    attrs.finish("bitmask", errors);
+    make_entity!(Bitmask, attrs, name, bitwidth: bitwidth, bitflags: bitflags, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_bitflag<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    value: CExpr<'a>,
+    errors: &mut Vec<DslError>,
+) -> Bitflag<'a> {
+    attrs.finish("bitflag", errors);
+    make_entity!(Bitflag, attrs, name, value: value)
+}
+
+pub(crate) fn make_constant<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    ty: Type<'a>,
+    expr: CExpr<'a>,
+    errors: &mut Vec<DslError>,
+) -> Constant<'a> {
+    attrs.finish("constant", errors);
+    make_entity!(Constant, attrs, name, ty: ty, expr: expr, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_command<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    params: Vec<Param<'a>>,
+    variadic: bool,
+    result: Type<'a>,
+    errors: &mut Vec<DslError>,
+) -> Command<'a> {
+    let success_codes = attrs.take_exprs("@success");
+    let error_codes = attrs.take_exprs("@errors");
+    let alias_to = attrs.take_alias();
+    let calling_convention = attrs.take_calling_convention();
+    attrs.finish("command", errors);
+    make_entity!(
+        Command, attrs, name,
+        params: params,
+        variadic: variadic,
+        calling_convention: calling_convention,
+        result: result,
+        success_codes: success_codes,
+        error_codes: error_codes,
+        alias_to: alias_to,
+        platform_variants: Vec::new(),
+    )
+}
+
+pub(crate) fn make_param<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    ty: Type<'a>,
+    errors: &mut Vec<DslError>,
+) -> Param<'a> {
+    let optional = attrs.take_flag("@optional");
+    let len = attrs.take_expr("@len");
+    attrs.finish("parameter", errors);
+    make_entity!(Param, attrs, name, ty: ty, optional: optional, len: len)
+}
+
+pub(crate) fn make_enumeration<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    variants: Vec<EnumVariant<'a>>,
+    errors: &mut Vec<DslError>,
+) -> Enumeration<'a> {
+    attrs.finish("enum", errors);
+    make_entity!(Enumeration, attrs, name, variants: variants, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_enum_variant<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    value: CExpr<'a>,
+    errors: &mut Vec<DslError>,
+) -> EnumVariant<'a> {
+    attrs.finish("enum variant", errors);
+    make_entity!(EnumVariant, attrs, name, value: value)
+}
+
+pub(crate) fn make_function_typedef<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    params: Vec<Param<'a>>,
+    variadic: bool,
+    result: Type<'a>,
+    errors: &mut Vec<DslError>,
+) -> FunctionTypedef<'a> {
+    let is_pointer = attrs.take_flag("@pointer");
+    let is_native_api = attrs.take_flag("@native_api");
+    let calling_convention = attrs.take_calling_convention();
+    attrs.finish("functype", errors);
+    make_entity!(
+        FunctionTypedef, attrs, name,
+        params: params,
+        variadic: variadic,
+        calling_convention: calling_convention,
+        result: result,
+        is_pointer: is_pointer,
+        is_native_api: is_native_api,
+        platform_variants: Vec::new(),
+    )
+}
+
+pub(crate) fn make_macro<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    params: Vec<Identifier>,
+    variadic: bool,
+    body: CExpr<'a>,
+    errors: &mut Vec<DslError>,
+) -> Macro<'a> {
+    attrs.finish("macro", errors);
+    make_entity!(Macro, attrs, name, params: params, variadic: variadic, body: body, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_opaque_typedef(
+    mut attrs: Attrs<'_>,
+    name: Identifier,
+    errors: &mut Vec<DslError>,
+) -> OpaqueTypedef {
+    attrs.finish("opaque", errors);
+    make_entity!(OpaqueTypedef, attrs, name, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_opaque_handle_typedef(
+    mut attrs: Attrs<'_>,
+    name: Identifier,
+    errors: &mut Vec<DslError>,
+) -> OpaqueHandleTypedef {
+    attrs.finish("handle", errors);
+    make_entity!(OpaqueHandleTypedef, attrs, name, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_structure<'a>(
+    mut attrs: Attrs<'a>,
+    kind: &str,
+    name: Identifier,
+    members: Vec<Member<'a>>,
+    errors: &mut Vec<DslError>,
+) -> Structure<'a> {
+    attrs.finish(kind, errors);
+    make_entity!(Structure, attrs, name, members: members, platform_variants: Vec::new())
+}
+
+pub(crate) fn make_member<'a>(
+    mut attrs: Attrs<'a>,
+    name: Identifier,
+    ty: Type<'a>,
+    errors: &mut Vec<DslError>,
+) -> Member<'a> {
+    let bits = attrs.take_bits();
+    let init = attrs.take_expr("@init");
+    let optional = attrs.take_flag("@optional");
+    let len = attrs.take_expr("@len");
+    attrs.finish("member", errors);
+    make_entity!(
+        Member, attrs, name,
+        ty: ty,
+        bits: bits,
+        init: init,
+        optional: optional,
+        len: len,
+    )
+}
+
+pub(crate) fn make_metadata_kvs(
+    kvs: Vec<((String, Metadata), Span)>,
+    errors: &mut Vec<DslError>,
+) -> Metadata {
+    let mut map = HashMap::new();
+    for ((key, value), span) in kvs {
+        match map.entry(key) {
+            Entry::Occupied(e) => errors.push(
+                DslError::new(span, format!("duplicate metadata key '{}'", e.key()))
+            ),
+            Entry::Vacant(e) => {
+                e.insert(value);
+            }
+        }
+    }
+    Metadata::KeyValues { kvs: map }
+}
+
+/// An `original:renamed` rename, applied once the whole source parsed without errors.
+pub(crate) struct PendingRename<'input> {
+    ident: Identifier,
+    renamed: &'input str,
+    span: Span,
+}
+
+/// Parse a backtick-quoted identifier, which may carry an `original:renamed` rename.
+///
+/// The rename is only recorded, and applied once the whole source parsed without errors, so that
+/// a failed parse leaves the identifier untouched.
+pub(crate) fn make_quoted_identifier<'input>(
+    quoted: &'input str,
+    span: Span,
+    errors: &mut Vec<DslError>,
+    renames: &mut Vec<PendingRename<'input>>,
+) -> Identifier {
+    let content = &quoted[1..quoted.len() - 1];
+    if content.is_empty() {
+        errors.push(DslError::new(span, "empty quoted identifier"));
+    }
+
+    if let Some((original, renamed)) = content.split_once(':') {
+        let ident = original.interned();
+        renames.push(PendingRename { ident: ident.clone(), renamed, span });
+        ident
+    } else {
+        content.interned()
+    }
+}
+
+/// Report the renames conflicting with an earlier one, in the source or applied before.
+fn check_renames(renames: &[PendingRename<'_>], errors: &mut Vec<DslError>) {
+    let mut pending: HashMap<&str, &str> = HashMap::new();
+    for rename in renames {
+        let current = rename.ident.renamed().or_else(|| pending.get(rename.ident.original()).copied());
+        let error = if rename.renamed.contains(':') {
+            "Renamed identifiers cannot contain ':'".to_string()
+        } else if let Some(current) = current
+            && current != rename.renamed
+        {
+            format!("Identifier '{}' is already renamed to '{}'", rename.ident.original(), current)
+        } else {
+            pending.insert(rename.ident.original(), rename.renamed);
+            continue;
+        };
+        errors.push(DslError::new(
+            rename.span,
+            format!("Failed renaming identifier '{}' to '{}': {}", rename.ident.original(), rename.renamed, error),
+        ));
+    }
+}
+
+/// Resolve the escapes of a DSL string literal, including its surrounding quotes.
+///
+/// Bad escapes are reported and kept verbatim.
+pub(crate) fn unescape_string(quoted: &str, span: Span, errors: &mut Vec<DslError>) -> String {
+    let content = &quoted[1..quoted.len() - 1];
+    let mut ret = String::with_capacity(content.len());
+    let mut chars = content.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            ret.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') => ret.push('\n'),
+            Some('r') => ret.push('\r'),
+            Some('t') => ret.push('\t'),
+            Some('0') => ret.push('\0'),
+            Some('\\') => ret.push('\\'),
+            Some('"') => ret.push('"'),
+            Some('\'') => ret.push('\''),
+            Some('u') => {
+                let rest = chars.as_str();
+                let code = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .and_then(|(hex, _)| u32::from_str_radix(hex, 16).ok().map(|c| (hex, c)))
+                    .and_then(|(hex, c)| char::from_u32(c).map(|c| (hex, c)));
+                if let Some((hex, c)) = code {
+                    ret.push(c);
+                    chars = rest[hex.len() + 2..].chars();
+                } else {
+                    errors.push(DslError::new(span, "invalid unicode escape in string"));
+                    ret.push_str("\\u");
+                }
+            }
+            Some(other) => {
+                errors.push(DslError::new(span, format!("unknown escape '\\{}' in string", other)));
+                ret.push('\\');
+                ret.push(other);
+            }
+            // The lexer never produces a string ending in a lone backslash
+            None => unreachable!(),
+        }
+    }
+
+    ret
+}
+
+pub(crate) fn make_ext(
+    quoted: &str,
+    span: Span,
+    errors: &mut Vec<DslError>,
+) -> Option<serde_json::Value> {
+    let s = unescape_string(quoted, span, errors);
+    match serde_json::from_str(&s) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            errors.push(DslError::new(span, format!("invalid ext JSON: {}", e)));
+            None
+        }
+    }
+}
+
+pub(crate) fn build_registry<'a>(
+    name: String,
+    items: Vec<Option<(RegistryItem<'a>, Span)>>,
+    errors: &mut Vec<DslError>,
+) -> Registry<'a> {
+    let mut registry = Registry::new(name);
+
+    macro_rules! insert {
+        ($map:ident, $kind:literal, $entity:expr, $span:expr) => {{
+            let entity = $entity;
+            match registry.$map.entry(entity.name.clone()) {
+                Entry::Occupied(mut e) => {
+                    // Declarations for other platforms are kept as variants
+                    if let Err(entity) = e.get_mut().add_platform_variant(entity) {
+                        let message = format!("duplicate {} '{}'", $kind, entity.name.original());
+                        errors.push(DslError::new($span, message));
+                    }
+                }
+                Entry::Vacant(e) => {
+                    e.insert(entity);
+                }
+            }
+        }};
+    }
+
+    for (item, span) in items.into_iter().flatten() {
+        match item {
+            RegistryItem::Metadef(key, value) => {
+                match registry.metadefs.entry(key) {
+                    Entry::Occupied(e) => errors.push(
+                        DslError::new(span, format!("duplicate metadef '{}'", e.key()))
+                    ),
+                    Entry::Vacant(e) => {
+                        e.insert(value);
+                    }
+                }
+            }
+            RegistryItem::Import(import) => {
+                let name = import.name.clone();
+                if !registry.imports.insert(import) {
+                    errors.push(DslError::new(span, format!("duplicate import '{}'", name.original())));
+                }
+            }
+            RegistryItem::Ext(ext) => {
+                if !registry.ext.is_null() {
+                    errors.push(DslError::new(span, "duplicate ext item"));
+                }
+                registry.ext = ext;
+            }
+            RegistryItem::Alias(e) => insert!(aliases, "alias", e, span),
+            RegistryItem::Bitmask(e) => insert!(bitmasks, "bitmask", e, span),
+            RegistryItem::Constant(e) => insert!(constants, "constant", e, span),
+            RegistryItem::Command(e) => insert!(commands, "command", e, span),
+            RegistryItem::Enumeration(e) => insert!(enumerations, "enum", e, span),
+            RegistryItem::FunctionTypedef(e) => insert!(function_typedefs, "functype", e, span),
+            RegistryItem::Macro(e) => insert!(macros, "macro", e, span),
+            RegistryItem::OpaqueTypedef(e) => insert!(opaque_typedefs, "opaque", e, span),
+            RegistryItem::OpaqueHandleTypedef(e) => insert!(opaque_handle_typedefs, "handle", e, span),
+            RegistryItem::Struct(e) => insert!(structs, "struct", e, span),
+            RegistryItem::Union(e) => insert!(unions, "union", e, span),
+        }
+    }
+
+    registry
+}
@@ -0,0 +1,588 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::cpl::*;
+use crate::registry::entity::*;
+use crate::registry::{Doc, Metadata, Platform, PointerType, Registry, RegistryBase, RegistryTE, Type};
+use crate::Identifier;
+
+
+/// Words that can never be parsed as identifiers, so identifiers spelled like them need quoting.
+const HARD_KEYWORDS: &[&str] = &[
+    "const", "volatile", "restrict", "_Atomic", "cast", "sizeof", "alignof", "_Alignof", "sizeof_type",
+    "alignof_type", "offsetof",
+];
+
+const INDENT: &str = "    ";
+
+/// Write `registry` in canonical DSL form.
+///
+/// Entities are sorted by their original names and attributes are printed in a fixed order, so
+/// the output is stable across runs and parsing it with [`parse_registry`] gives back an equal
+/// registry.
+///
+/// [`parse_registry`]: crate::registry::parse_registry
+pub fn write_registry(registry: &Registry<'_>) -> String {
+    let mut out = String::new();
+    write_base(&mut out, registry.as_base(), &registry.ext);
+    out
+}
+
+/// Write a registry with typed extension data in canonical DSL form.
+///
+/// The extension is stored as JSON, so this fails if `EXT` cannot be serialized.
+pub fn write_registry_te<EXT: Serialize>(
+    registry: &RegistryTE<'_, EXT>,
+) -> Result<String, serde_json::Error> {
+    let ext = serde_json::to_value(&registry.ext)?;
+    let mut out = String::new();
+    write_base(&mut out, registry.as_base(), &ext);
+    Ok(out)
+}
+
+fn write_base(out: &mut String, base: &RegistryBase<'_>, ext: &serde_json::Value) {
+    writeln!(out, "registry {};", name(&base.name)).unwrap();
+
+    if !base.metadefs.is_empty() || !base.imports.is_empty() || !ext.is_null() {
+        out.push('\n');
+    }
+
+    for (key, value) in sorted_by_key(&base.metadefs) {
+        writeln!(out, "metadef {} = {};", name(key), string(value)).unwrap();
+    }
+
+    for import in &base.imports {
+        let keyword = if import.depend { "depend" } else { "import" };
+        write!(out, "{} {}", keyword, ident(&import.name)).unwrap();
+        if let Some(version) = &import.version {
+            write!(out, " {}", string(version)).unwrap();
+        }
+        out.push_str(";\n");
+    }
+
+    if !ext.is_null() {
+        writeln!(out, "ext {};", string(&ext.to_string())).unwrap();
+    }
+
+    for alias in sorted_with_variants(base.aliases.values()) {
+        out.push('\n');
+        write_attrs(out, "", &alias.doc, &alias.platform, &alias.metadata);
+        writeln!(out, "alias {} = {};", ident(&alias.name), ty(&alias.target)).unwrap();
+    }
+
+    for bitmask in sorted_with_variants(base.bitmasks.values()) {
+        out.push('\n');
+        write_attrs(out, "", &bitmask.doc, &bitmask.platform, &bitmask.metadata);
+        write!(out, "bitmask {}: {} ", ident(&bitmask.name), bitmask.bitwidth).unwrap();
+        write_block(out, &bitmask.bitflags, |out, flag| {
+            write_attrs(out, INDENT, &flag.doc, &flag.platform, &flag.metadata);
+            write!(out, "{}{} = {}", INDENT, ident(&flag.name), expr_at(&flag.value, ASSIGNMENT)).unwrap();
+        });
+    }
+
+    for constant in sorted_with_variants(base.constants.values()) {
+        out.push('\n');
+        write_attrs(out, "", &constant.doc, &constant.platform, &constant.metadata);
+        writeln!(
+            out,
+            "constant {}: {} = {};",
+            ident(&constant.name),
+            ty(&constant.ty),
+            expr(&constant.expr)
+        ).unwrap();
+    }
+
+    for command in sorted_with_variants(base.commands.values()) {
+        out.push('\n');
+        write_attrs(out, "", &command.doc, &command.platform, &command.metadata);
+        if !command.success_codes.is_empty() {
+            writeln!(out, "@success({})", expr_list(&command.success_codes)).unwrap();
+        }
+        if !command.error_codes.is_empty() {
+            writeln!(out, "@errors({})", expr_list(&command.error_codes)).unwrap();
+        }
+        if let Some(alias_to) = &command.alias_to {
+            writeln!(out, "@alias({})", ident(alias_to)).unwrap();
+        }
+        if let Some(calling_convention) = command.calling_convention {
+            writeln!(out, "@callconv({})", calling_convention).unwrap();
+        }
+        write!(out, "command {}", ident(&command.name)).unwrap();
+        write_params(out, &command.params, command.variadic);
+        writeln!(out, " -> {};", ty(&command.result)).unwrap();
+    }
+
+    for enumeration in sorted_with_variants(base.enumerations.values()) {
+        out.push('\n');
+        write_attrs(out, "", &enumeration.doc, &enumeration.platform, &enumeration.metadata);
+        write!(out, "enum {} ", ident(&enumeration.name)).unwrap();
+        write_block(out, &enumeration.variants, |out, variant| {
+            write_attrs(out, INDENT, &variant.doc, &variant.platform, &variant.metadata);
+            write!(out, "{}{} = {}", INDENT, ident(&variant.name), expr_at(&variant.value, ASSIGNMENT)).unwrap();
+        });
+    }
+
+    for typedef in sorted_with_variants(base.function_typedefs.values()) {
+        out.push('\n');
+        write_attrs(out, "", &typedef.doc, &typedef.platform, &typedef.metadata);
+        if typedef.is_pointer {
+            out.push_str("@pointer\n");
+        }
+        if typedef.is_native_api {
+            out.push_str("@native_api\n");
+        }
+        if let Some(calling_convention) = typedef.calling_convention {
+            writeln!(out, "@callconv({})", calling_convention).unwrap();
+        }
+        write!(out, "functype {}", ident(&typedef.name)).unwrap();
+        write_params(out, &typedef.params, typedef.variadic);
+        writeln!(out, " -> {};", ty(&typedef.result)).unwrap();
+    }
+
+    for macro_ in sorted_with_variants(base.macros.values()) {
+        out.push('\n');
+        write_attrs(out, "", &macro_.doc, &macro_.platform, &macro_.metadata);
+        let mut params = macro_.params.iter().map(ident).collect::<Vec<_>>();
+        if macro_.variadic {
+            params.push("...".to_string());
+        }
+        writeln!(out, "macro {}({}) = {};", ident(&macro_.name), params.join(", "), expr(&macro_.body)).unwrap();
+    }
+
+    for opaque in sorted_with_variants(base.opaque_typedefs.values()) {
+        out.push('\n');
+        write_attrs(out, "", &opaque.doc, &opaque.platform, &opaque.metadata);
+        writeln!(out, "opaque {};", ident(&opaque.name)).unwrap();
+    }
+
+    for handle in sorted_with_variants(base.opaque_handle_typedefs.values()) {
+        out.push('\n');
+        write_attrs(out, "", &handle.doc, &handle.platform, &handle.metadata);
+        writeln!(out, "handle {};", ident(&handle.name)).unwrap();
+    }
+
+    for structure in sorted_with_variants(base.structs.values()) {
+        out.push('\n');
+        write_structure(out, "struct", structure);
+    }
+
+    for structure in sorted_with_variants(base.unions.values()) {
+        out.push('\n');
+        write_structure(out, "union", structure);
+    }
+}
+
+fn write_structure(out: &mut String, keyword: &str, structure: &Structure<'_>) {
+    write_attrs(out, "", &structure.doc, &structure.platform, &structure.metadata);
+    write!(out, "{} {} ", keyword, ident(&structure.name)).unwrap();
+    write_block(out, &structure.members, |out, member| {
+        write_attrs(out, INDENT, &member.doc, &member.platform, &member.metadata);
+        if let Some(bits) = member.bits {
+            writeln!(out, "{}@bits({})", INDENT, bits).unwrap();
+        }
+        if let Some(init) = &member.init {
+            writeln!(out, "{}@init({})", INDENT, expr(init)).unwrap();
+        }
+        if member.optional {
+            writeln!(out, "{}@optional", INDENT).unwrap();
+        }
+        if let Some(len) = &member.len {
+            writeln!(out, "{}@len({})", INDENT, expr(len)).unwrap();
+        }
+        write!(out, "{}{}: {}", INDENT, ident(&member.name), ty(&member.ty)).unwrap();
+    });
+}
+
+fn write_params(out: &mut String, params: &[Param<'_>], variadic: bool) {
+    if params.is_empty() {
+        out.push_str(if variadic { "(...)" } else { "()" });
+        return;
+    }
+
+    out.push_str("(\n");
+    for param in params {
+        write_attrs(out, INDENT, &param.doc, &param.platform, &param.metadata);
+        if param.optional {
+            writeln!(out, "{}@optional", INDENT).unwrap();
+        }
+        if let Some(len) = &param.len {
+            writeln!(out, "{}@len({})", INDENT, expr(len)).unwrap();
+        }
+        writeln!(out, "{}{}: {},", INDENT, ident(&param.name), ty(&param.ty)).unwrap();
+    }
+    if variadic {
+        writeln!(out, "{}...", INDENT).unwrap();
+    }
+    out.push(')');
+}
+
+fn write_block<T>(out: &mut String, items: &[T], mut write_item: impl FnMut(&mut String, &T)) {
+    if items.is_empty() {
+        out.push_str("{}\n");
+        return;
+    }
+
+    out.push_str("{\n");
+    for item in items {
+        write_item(out, item);
+        out.push_str(",\n");
+    }
+    out.push_str("}\n");
+}
+
+fn write_attrs(
+    out: &mut String,
+    indent: &str,
+    doc: &Doc,
+    platform: &Option<Platform>,
+    metadata: &HashMap<String, Metadata>,
+) {
+    for line in doc.to_lines() {
+        writeln!(out, "{}@doc({})", indent, string(&line)).unwrap();
+    }
+
+    if let Some(platform) = platform {
+        writeln!(out, "{}@platform({})", indent, string(&platform.to_string())).unwrap();
+    }
+
+    for (key, value) in sorted_by_key(metadata) {
+        writeln!(out, "{}@meta({})", indent, metadata_kv(key, value)).unwrap();
+    }
+}
+
+fn metadata_kv(key: &str, value: &Metadata) -> String {
+    match value {
+        Metadata::None => name(key),
+        Metadata::String { value } => format!("{} = {}", name(key), string(value)),
+        Metadata::KeyValues { kvs } => {
+            let kvs = sorted_by_key(kvs)
+                .into_iter()
+                .map(|(key, value)| metadata_kv(key, value))
+                .collect::<Vec<_>>();
+            format!("{} = {{ {} }}", name(key), kvs.join(", "))
+        }
+    }
+}
+
+fn sorted_by_key<V>(map: &HashMap<String, V>) -> Vec<(&String, &V)> {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(key, _)| *key);
+    entries
+}
+
+fn sorted_entities<'e, T: Ord + 'e>(entities: impl Iterator<Item = &'e T>) -> Vec<&'e T> {
+    let mut entities = entities.collect::<Vec<_>>();
+    entities.sort();
+    entities
+}
+
+/// Entities sorted as by [`sorted_entities`], each followed by its platform variants.
+fn sorted_with_variants<'e, T: Ord + PlatformVariants + 'e>(entities: impl Iterator<Item = &'e T>) -> Vec<&'e T> {
+    sorted_entities(entities).into_iter().flat_map(PlatformVariants::with_platform_variants).collect()
+}
+
+fn is_plain_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !HARD_KEYWORDS.contains(&s)
+}
+
+fn ident(ident: &Identifier) -> String {
+    match ident.renamed() {
+        Some(renamed) => format!("`{}:{}`", ident.original(), renamed),
+        None if is_plain_identifier(ident.original()) => ident.original().to_string(),
+        None => format!("`{}`", ident.original()),
+    }
+}
+
+fn name(s: &str) -> String {
+    if is_plain_identifier(s) {
+        s.to_string()
+    } else {
+        string(s)
+    }
+}
+
+fn string(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            '\0' => ret.push_str("\\0"),
+            c if c.is_control() => write!(ret, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn ty(ty: &Type<'_>) -> String {
+    match ty {
+        Type::IdentifierType(ident_type) => ident(&ident_type.ident),
+        Type::ArrayType(array) => match &array.length {
+            Some(length) => format!("[{}; {}]", self::ty(&array.element), expr(length)),
+            None => format!("[{}]", self::ty(&array.element)),
+        },
+        Type::PointerType(ptr) => {
+            let mut ret = String::new();
+            for (present, qualifier) in [
+                (ptr.is_pointer_const, "const "),
+                (ptr.is_pointer_volatile, "volatile "),
+                (ptr.is_pointer_atomic, "_Atomic "),
+            ] {
+                if present {
+                    ret.push_str(qualifier);
+                }
+            }
+            ret.push_str(&pointer(ptr));
+            ret
+        }
+    }
+}
+
+/// A pointer without its own qualifiers, which are written with the pointer pointing to it if any.
+fn pointer(ptr: &PointerType<'_>) -> String {
+    let mut ret = String::new();
+    if ptr.nullable {
+        ret.push('?');
+    }
+    ret.push(if ptr.pointer_to_one { '&' } else { '*' });
+
+    for (present, qualifier) in [
+        (ptr.is_restrict, "restrict "),
+        (ptr.is_const, "const "),
+        (ptr.is_volatile, "volatile "),
+        (ptr.is_atomic, "_Atomic "),
+    ] {
+        if present {
+            ret.push_str(qualifier);
+        }
+    }
+
+    let pointee = match &ptr.pointee {
+        Type::PointerType(pointee) => pointer(pointee),
+        pointee => self::ty(pointee),
+    };
+    // `&&` would be lexed as one token
+    if ret.ends_with('&') && pointee.starts_with('&') {
+        ret.push(' ');
+    }
+    ret.push_str(&pointee);
+    ret
+}
+
+// Precedence levels of the C expression grammar, loosest first
+const COMMA: u8 = 0;
+const ASSIGNMENT: u8 = 1;
+const CONDITIONAL: u8 = 2;
+const CAST: u8 = 13;
+const UNARY: u8 = 14;
+const POSTFIX: u8 = 15;
+const PRIMARY: u8 = 16;
+
+fn binary_precedence(op: CBinaryOp) -> u8 {
+    match op {
+        CBinaryOp::Comma => COMMA,
+        CBinaryOp::Assign
+        | CBinaryOp::MulAssign
+        | CBinaryOp::DivAssign
+        | CBinaryOp::ModAssign
+        | CBinaryOp::AddAssign
+        | CBinaryOp::SubAssign
+        | CBinaryOp::ShlAssign
+        | CBinaryOp::ShrAssign
+        | CBinaryOp::BitAndAssign
+        | CBinaryOp::BitXorAssign
+        | CBinaryOp::BitOrAssign => ASSIGNMENT,
+        CBinaryOp::Or => 3,
+        CBinaryOp::And => 4,
+        CBinaryOp::BitOr => 5,
+        CBinaryOp::BitXor => 6,
+        CBinaryOp::BitAnd => 7,
+        CBinaryOp::Eq | CBinaryOp::NotEq => 8,
+        CBinaryOp::Less | CBinaryOp::Greater | CBinaryOp::LessEq | CBinaryOp::GreaterEq => 9,
+        CBinaryOp::Shl | CBinaryOp::Shr => 10,
+        CBinaryOp::Add | CBinaryOp::Sub => 11,
+        CBinaryOp::Mul | CBinaryOp::Div | CBinaryOp::Mod => 12,
+    }
+}
+
+fn precedence(expr: &CExpr<'_>) -> u8 {
+    match expr {
+        CExpr::IntLiteral(_)
+        | CExpr::FloatLiteral(_)
+        | CExpr::CharLiteral(_)
+        | CExpr::StringLiteral(_)
+        | CExpr::Identifier(_)
+        | CExpr::Paren(_)
+        | CExpr::Type(_) => PRIMARY,
+        CExpr::Index(_)
+        | CExpr::Call(_)
+        | CExpr::Member(_)
+        | CExpr::PtrMember(_)
+        | CExpr::PostfixIncDec(_)
+        | CExpr::OffsetOf(_) => POSTFIX,
+        CExpr::Unary(_) | CExpr::TypeOperator(_) => UNARY,
+        CExpr::Cast(_) => CAST,
+        CExpr::Binary(binary) => binary_precedence(binary.op),
+        CExpr::Conditional(_) => CONDITIONAL,
+    }
+}
+
+fn expr(expr: &CExpr<'_>) -> String {
+    expr_at(expr, COMMA)
+}
+
+fn expr_list(exprs: &[CExpr<'_>]) -> String {
+    exprs.iter()
+        .map(|e| expr_at(e, ASSIGNMENT))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Print `expr` in a position that requires at least precedence `min`.
+///
+/// Expressions produced by a parser always carry explicit [`CExpr::Paren`] nodes where grouping
+/// is needed. Hand-built trees may not, in which case parentheses are inserted here and will
+/// come back as `Paren` nodes when parsed again.
+fn expr_at(expr: &CExpr<'_>, min: u8) -> String {
+    let ret = match expr {
+        CExpr::IntLiteral(lit) => format!("{}{}", lit.value, lit.suffix),
+        CExpr::FloatLiteral(lit) => format!("{}{}", lit.value, lit.suffix),
+        CExpr::CharLiteral(lit) => format!("'{}'", lit.value),
+        CExpr::StringLiteral(lit) => format!("\"{}\"", lit.value),
+        CExpr::Identifier(id) => ident(&id.ident),
+        CExpr::Index(index) => {
+            format!("{}[{}]", expr_at(&index.base, POSTFIX), self::expr(&index.index))
+        }
+        CExpr::Call(call) => format!("{}({})", expr_at(&call.callee, POSTFIX), expr_list(&call.args)),
+        CExpr::Member(member) => format!("{}.{}", expr_at(&member.obj, POSTFIX), ident(&member.member)),
+        CExpr::PtrMember(member) => {
+            format!("{}->{}", expr_at(&member.obj, POSTFIX), ident(&member.member))
+        }
+        CExpr::PostfixIncDec(e) => {
+            let op = match e.op {
+                CPostfixIncDecOp::Inc => "++",
+                CPostfixIncDecOp::Dec => "--",
+            };
+            format!("{}{}", expr_at(&e.expr, POSTFIX), op)
+        }
+        CExpr::Unary(unary) => {
+            let (op, operand_min) = match unary.op {
+                CUnaryOp::Plus => ("+", CAST),
+                CUnaryOp::Minus => ("-", CAST),
+                CUnaryOp::Not => ("!", CAST),
+                CUnaryOp::BitNot => ("~", CAST),
+                CUnaryOp::Deref => ("*", CAST),
+                CUnaryOp::AddrOf => ("&", CAST),
+                CUnaryOp::Inc => ("++", UNARY),
+                CUnaryOp::Dec => ("--", UNARY),
+                CUnaryOp::SizeOf => ("sizeof", UNARY),
+                CUnaryOp::AlignOf => ("alignof", UNARY),
+            };
+
+            let operand = expr_at(&unary.expr, operand_min);
+            let needs_space = match unary.op {
+                CUnaryOp::SizeOf | CUnaryOp::AlignOf => !operand.starts_with('('),
+                // `- -x` must not become `--x`, nor `& &x` become `&&x`
+                _ => operand.starts_with(op.chars().last().unwrap()),
+            };
+
+            if needs_space {
+                format!("{} {}", op, operand)
+            } else {
+                format!("{}{}", op, operand)
+            }
+        }
+        CExpr::Cast(cast) => format!("cast({}) {}", self::expr(&cast.ty), expr_at(&cast.expr, CAST)),
+        CExpr::TypeOperator(e) => {
+            let op = match e.op {
+                CTypeOperator::SizeOf => "sizeof_type",
+                CTypeOperator::AlignOf => "alignof_type",
+            };
+            format!("{}({})", op, self::expr(&e.ty))
+        }
+        CExpr::OffsetOf(e) => {
+            format!("offsetof({}, {})", expr_at(&e.ty, ASSIGNMENT), expr_at(&e.member, ASSIGNMENT))
+        }
+        CExpr::Binary(binary) => {
+            let prec = binary_precedence(binary.op);
+            let (lhs_min, rhs_min) = match prec {
+                // Assignments are right-associative and require a unary expression on the left
+                ASSIGNMENT => (UNARY, ASSIGNMENT),
+                _ => (prec, prec + 1),
+            };
+            let op = binary_operator(binary.op);
+            let lhs = expr_at(&binary.lhs, lhs_min);
+            let rhs = expr_at(&binary.rhs, rhs_min);
+
+            if binary.op == CBinaryOp::Comma {
+                format!("{}{} {}", lhs, op, rhs)
+            } else {
+                format!("{} {} {}", lhs, op, rhs)
+            }
+        }
+        CExpr::Conditional(cond) => format!(
+            "{} ? {} : {}",
+            expr_at(&cond.cond, CONDITIONAL + 1),
+            self::expr(&cond.then),
+            expr_at(&cond.otherwise, CONDITIONAL)
+        ),
+        CExpr::Paren(paren) => format!("({})", self::expr(&paren.expr)),
+        CExpr::Type(e) => ty(&e.ty),
+    };
+
+    if precedence(expr) < min {
+        format!("({})", ret)
+    } else {
+        ret
+    }
+}
+
+fn binary_operator(op: CBinaryOp) -> &'static str {
+    match op {
+        CBinaryOp::Mul => "*",
+        CBinaryOp::Div => "/",
+        CBinaryOp::Mod => "%",
+        CBinaryOp::Add => "+",
+        CBinaryOp::Sub => "-",
+        CBinaryOp::Shl => "<<",
+        CBinaryOp::Shr => ">>",
+        CBinaryOp::Less => "<",
+        CBinaryOp::Greater => ">",
+        CBinaryOp::LessEq => "<=",
+        CBinaryOp::GreaterEq => ">=",
+        CBinaryOp::Eq => "==",
+        CBinaryOp::NotEq => "!=",
+        CBinaryOp::BitAnd => "&",
+        CBinaryOp::BitXor => "^",
+        CBinaryOp::BitOr => "|",
+        CBinaryOp::And => "&&",
+        CBinaryOp::Or => "||",
+        CBinaryOp::Assign => "=",
+        CBinaryOp::MulAssign => "*=",
+        CBinaryOp::DivAssign => "/=",
+        CBinaryOp::ModAssign => "%=",
+        CBinaryOp::AddAssign => "+=",
+        CBinaryOp::SubAssign => "-=",
+        CBinaryOp::ShlAssign => "<<=",
+        CBinaryOp::ShrAssign => ">>=",
+        CBinaryOp::BitAndAssign => "&=",
+        CBinaryOp::BitXorAssign => "^=",
+        CBinaryOp::BitOrAssign => "|=",
+        CBinaryOp::Comma => ",",
+    }
+}
@@ -4,7 +4,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::cpl::CExpr;
-use crate::registry::{Metadata, Type};
+use crate::registry::{Metadata, Platform, Type};
 use crate::Identifier;
 
 
@@ -64,6 +64,34 @@ pub trait Entity<'de> : Sized + Eq + Ord + Serialize + Deserialize<'de> {
     }
 }
 
+/// Entities of the registry itself, which may be declared differently on several platforms.
+///
+/// The first declaration met is the entity, and those for other platforms are kept as its
+/// `platform_variants`, each with its own `platform`.
+pub trait PlatformVariants: Sized {
+    fn entity_platform(&self) -> Option<&Platform>;
+    fn platform_variants(&self) -> &[Self];
+    fn platform_variants_mut(&mut self) -> &mut Vec<Self>;
+
+    /// This entity, then its variants.
+    fn with_platform_variants(&self) -> impl Iterator<Item = &Self> {
+        std::iter::once(self).chain(self.platform_variants())
+    }
+
+    /// Keep `variant` as declared for its own platform, or give it back as a duplicate when
+    /// either declaration is not specific to a platform or one for its platform is known already.
+    fn add_platform_variant(&mut self, variant: Self) -> Result<(), Self> {
+        let Some(platform) = variant.entity_platform() else {
+            return Err(variant);
+        };
+        if self.with_platform_variants().any(|known| known.entity_platform().is_none_or(|p| p == platform)) {
+            return Err(variant);
+        }
+        self.platform_variants_mut().push(variant);
+        Ok(())
+    }
+}
+
 include!("../macross.rs");
 include!("entity_macross.rs");
 
@@ -72,6 +100,8 @@ entity!{EntityBase,}
 entity!{
     Typedef<'a>,
     target: Type<'a>,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
 }
 
 ss_enum! {
@@ -81,7 +111,9 @@ ss_enum! {
 entity!{
     Bitmask<'a>,
     bitwidth: Bitwidth,
-    bitflags: Vec<Bitflag<'a>>
+    bitflags: Vec<Bitflag<'a>>,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
 }
 
 entity!{
@@ -89,18 +121,29 @@ entity!{
     value: CExpr<'a>
 }
 
+// Calling conventions other than the default C one of the platform
+ss_enum! {
+    CallingConvention,
+    StdCall, FastCall, ThisCall, VectorCall, RegCall, Pascal, SysV, Win64, Aapcs, AapcsVfp, Swift
+}
+
 entity!{
     Command<'a>,
     params: Vec<Param<'a>>,
+    #[serde(default)]
+    variadic: bool,
+    calling_convention: Option<CallingConvention>,
     result: Type<'a>,
     success_codes: Vec<CExpr<'a>>,
     error_codes: Vec<CExpr<'a>>,
-    alias_to: Option<Identifier>
+    alias_to: Option<Identifier>,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
 }
 
 impl<'a> Command<'a> {
     pub fn sanitize(&self) {
-        for param in &self.params {
+        for param in self.with_platform_variants().flat_map(|variant| &variant.params) {
             param.sanitize();
         }
     }
@@ -109,6 +152,9 @@ impl<'a> Command<'a> {
         for param in &mut self.params {
             param.sanitize_fix();
         }
+        for variant in &mut self.platform_variants {
+            variant.sanitize_fix();
+        }
     }
 }
 
@@ -120,12 +166,22 @@ entity!{
 }
 
 impl<'a> Param<'a> {
-    pub fn sanitize(&self) {
-        if let Type::PointerType(ptr_type) = &self.ty {
-            assert_eq!(ptr_type.nullable, self.optional);
+    /// Whether a pointer parameter is nullable exactly when it is optional.
+    pub fn nullability_matches(&self) -> bool {
+        match &self.ty {
+            Type::PointerType(ptr_type) => ptr_type.nullable == self.optional,
+            _ => true,
         }
     }
 
+    pub fn sanitize(&self) {
+        assert!(
+            self.nullability_matches(),
+            "parameter '{}': pointer nullability does not match its optional flag",
+            self.name.original()
+        );
+    }
+
     pub fn sanitize_fix(&mut self) {
         if let Type::PointerType(ptr_type) = &mut self.ty {
             ptr_type.nullable = self.optional;
@@ -137,11 +193,15 @@ entity!{
     Constant<'a>,
     ty: Type<'a>,
     expr: CExpr<'a>,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
 }
 
 entity!{
     Enumeration<'a>,
     variants: Vec<EnumVariant<'a>>,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
 }
 
 entity!{
@@ -152,14 +212,19 @@ entity!{
 entity!{
     FunctionTypedef<'a>,
     params: Vec<Param<'a>>,
+    #[serde(default)]
+    variadic: bool,
+    calling_convention: Option<CallingConvention>,
     result: Type<'a>,
     is_pointer: bool,
-    is_native_api: bool
+    is_native_api: bool,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
 }
 
 impl<'a> FunctionTypedef<'a> {
     pub fn sanitize(&self) {
-        for param in &self.params {
+        for param in self.with_platform_variants().flat_map(|variant| &variant.params) {
             param.sanitize();
         }
     }
@@ -168,16 +233,39 @@ impl<'a> FunctionTypedef<'a> {
         for param in &mut self.params {
             param.sanitize_fix();
         }
+        for variant in &mut self.platform_variants {
+            variant.sanitize_fix();
+        }
     }
 }
 
-entity!{OpaqueTypedef,}
+// Function-like macro whose body is a C expression, `variadic` ones take `__VA_ARGS__` last
+entity!{
+    Macro<'a>,
+    params: Vec<Identifier>,
+    variadic: bool,
+    body: CExpr<'a>,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
+}
+
+entity!{
+    OpaqueTypedef,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
+}
 
-entity!{OpaqueHandleTypedef,}
+entity!{
+    OpaqueHandleTypedef,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
+}
 
 entity!{
     Structure<'a>,
     members: Vec<Member<'a>>,
+    #[serde(default)]
+    platform_variants: Vec<Self>,
 }
 
 entity!{
@@ -189,6 +277,29 @@ entity!{
     len: Option<CExpr<'a>>
 }
 
+macro_rules! platform_variants {
+    ($($name:ident $(<$lifetime:lifetime>)?),* $(,)?) => {
+        $(impl$(<$lifetime>)? PlatformVariants for $name$(<$lifetime>)? {
+            fn entity_platform(&self) -> Option<&Platform> {
+                self.platform.as_ref()
+            }
+
+            fn platform_variants(&self) -> &[Self] {
+                &self.platform_variants
+            }
+
+            fn platform_variants_mut(&mut self) -> &mut Vec<Self> {
+                &mut self.platform_variants
+            }
+        })*
+    };
+}
+
+platform_variants! {
+    Typedef<'a>, Bitmask<'a>, Command<'a>, Constant<'a>, Enumeration<'a>, FunctionTypedef<'a>, Macro<'a>,
+    OpaqueTypedef, OpaqueHandleTypedef, Structure<'a>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[derive(JsonSchema)]
 pub struct Import {
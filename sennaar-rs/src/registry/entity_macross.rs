@@ -1,5 +1,5 @@
 macro_rules! entity {
-    ($name:ident $(<$lifetime:lifetime>)?, $($field:ident: $type:ty),* $(,)?) => {
+    ($name:ident $(<$lifetime:lifetime>)?, $($(#[$attr:meta])* $field:ident: $type:ty),* $(,)?) => {
         #[derive(Debug, Clone, Serialize, Deserialize)]
         #[derive(JsonSchema)]
         #[serde(rename_all = "camelCase")]
@@ -7,9 +7,10 @@ macro_rules! entity {
         pub struct $name$(<$lifetime>)? {
             pub name: crate::Identifier,
             pub metadata: HashMap<String, Metadata>,
-            pub doc: Vec<String>,
+            #[serde(deserialize_with = "crate::registry::deserialize_doc")]
+            pub doc: crate::registry::Doc,
             pub platform: Option<crate::registry::Platform>,
-            $(pub $field: $type),*
+            $($(#[$attr])* pub $field: $type),*
         }
 
         impl<'de $(,$lifetime)?> Entity<'de> for $name$(<$lifetime>)? {
@@ -2,14 +2,24 @@ use lalrpop_util::lalrpop_mod;
 
 mod platform;
 mod metadata;
+mod doc;
 mod rawtype;
 mod entity;
 mod registry;
+mod dsl;
+mod dsl_writer;
+mod xml;
+mod validate;
 
-lalrpop_mod!(lalr, "/registry/lalr.rs");
+lalrpop_mod!(#[allow(clippy::all)] lalr, "/registry/lalr.rs");
 
 pub use metadata::*;
+pub use doc::*;
 pub use platform::*;
 pub use rawtype::*;
 pub use entity::*;
-pub use registry::*;
\ No newline at end of file
+pub use registry::*;
+pub use dsl::*;
+pub use dsl_writer::*;
+pub use xml::*;
+pub use validate::*;
\ No newline at end of file
@@ -1,3 +1,5 @@
+use std::fmt::Display;
+
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
@@ -32,7 +34,121 @@ pub struct ArrayType<'a> {
 #[serde(rename_all = "camelCase")]
 pub struct PointerType<'a> {
     pub pointee: Type<'a>,
+    /// Qualifiers of the pointee, so that in `const char * const *` both levels are const.
     pub is_const: bool,
+    #[serde(default)]
+    pub is_volatile: bool,
+    #[serde(default)]
+    pub is_atomic: bool,
+    /// `restrict` qualifies the pointer itself rather than its pointee.
+    #[serde(default)]
+    pub is_restrict: bool,
+    /// Qualifiers of the pointer itself, so that `int * _Atomic` keeps its `_Atomic`. They are only
+    /// set where no pointer points to this one: a pointer to a pointer holds them as its own
+    /// `is_const`, `is_volatile` and `is_atomic` instead, see [`Type::into_pointee`].
+    #[serde(default)]
+    pub is_pointer_const: bool,
+    #[serde(default)]
+    pub is_pointer_volatile: bool,
+    #[serde(default)]
+    pub is_pointer_atomic: bool,
     pub pointer_to_one: bool,
     pub nullable: bool
 }
+
+impl<'a> Type<'a> {
+    /// Copy every borrowed literal, so that the type outlives its source.
+    pub fn into_owned(self) -> Type<'static> {
+        match self {
+            Type::IdentifierType(t) => Type::IdentifierType(t),
+            Type::ArrayType(t) => Type::ArrayType(Box::new(ArrayType {
+                element: t.element.into_owned(),
+                length: t.length.map(CExpr::into_owned),
+            })),
+            Type::PointerType(t) => {
+                Type::PointerType(Box::new(PointerType { pointee: t.pointee.into_owned(), ..*t }))
+            }
+        }
+    }
+
+    /// This type as the pointee of a pointer, leaving the qualifiers of a pointer to the one
+    /// pointing to it.
+    pub fn into_pointee(self) -> Self {
+        match self {
+            Type::PointerType(mut ptr) => {
+                ptr.is_pointer_const = false;
+                ptr.is_pointer_volatile = false;
+                ptr.is_pointer_atomic = false;
+                Type::PointerType(ptr)
+            }
+            ty => ty,
+        }
+    }
+
+    /// C spelling of a declaration of `name` with this type, `name` may be empty.
+    pub fn declare(&self, name: &str) -> String {
+        self.declare_qualified(Vec::new(), name)
+    }
+
+    fn declare_qualified(&self, qualifiers: Vec<&str>, name: &str) -> String {
+        match self {
+            Type::IdentifierType(t) => {
+                let mut ret = qualifiers.join(" ");
+                if !ret.is_empty() {
+                    ret.push(' ');
+                }
+                ret.push_str(t.ident.original());
+                if !name.is_empty() && !name.starts_with(['*', '[']) {
+                    ret.push(' ');
+                }
+                ret.push_str(name);
+                ret
+            }
+            Type::ArrayType(array) => match &array.length {
+                Some(length) => array.element.declare_qualified(qualifiers, &format!("{}[{}]", name, length)),
+                None => array.element.declare_qualified(qualifiers, &format!("{}[]", name)),
+            },
+            Type::PointerType(ptr) => {
+                let mut qualifiers = qualifiers;
+                for (present, qualifier) in [
+                    (ptr.is_pointer_const, "const"),
+                    (ptr.is_pointer_volatile, "volatile"),
+                    (ptr.is_pointer_atomic, "_Atomic"),
+                    (ptr.is_restrict, "restrict"),
+                ] {
+                    if present && !qualifiers.contains(&qualifier) {
+                        qualifiers.push(qualifier);
+                    }
+                }
+                let mut inner = "*".to_string();
+                for qualifier in &qualifiers {
+                    inner.push(' ');
+                    inner.push_str(qualifier);
+                }
+                if !name.is_empty() && (!qualifiers.is_empty() || !name.starts_with('*')) {
+                    inner.push(' ');
+                }
+                inner.push_str(name);
+                if let Type::ArrayType(_) = ptr.pointee {
+                    inner = format!("({})", inner);
+                }
+
+                let pointee_qualifiers = [
+                    (ptr.is_const, "const"),
+                    (ptr.is_volatile, "volatile"),
+                    (ptr.is_atomic, "_Atomic"),
+                ]
+                    .into_iter()
+                    .filter_map(|(present, qualifier)| present.then_some(qualifier))
+                    .collect();
+                ptr.pointee.declare_qualified(pointee_qualifiers, &inner)
+            }
+        }
+    }
+}
+
+impl Display for Type<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.declare(""))
+    }
+}
@@ -4,7 +4,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde::de::DeserializeOwned;
 
-use crate::Identifier;
+use crate::{collect_rename_errors, Diagnostic, Identifier, RenameError, SourceLocation};
 use crate::registry::entity::*;
 
 include!("registry_macross.rs");
@@ -22,6 +22,7 @@ impl<'a> RegistryBase<'a> {
         self.commands.extend(other.commands);
         self.enumerations.extend(other.enumerations);
         self.function_typedefs.extend(other.function_typedefs);
+        self.macros.extend(other.macros);
         self.opaque_typedefs.extend(other.opaque_typedefs);
         self.opaque_handle_typedefs.extend(other.opaque_handle_typedefs);
         self.structs.extend(other.structs);
@@ -46,6 +47,7 @@ impl<'a> Registry<'a> {
             commands: HashMap::new(),
             enumerations: HashMap::new(),
             function_typedefs: HashMap::new(),
+            macros: HashMap::new(),
             opaque_typedefs: HashMap::new(),
             opaque_handle_typedefs: HashMap::new(),
             structs: HashMap::new(),
@@ -54,6 +56,26 @@ impl<'a> Registry<'a> {
         }
     }
 
+    /// Load a registry from its JSON form, reporting every identifier rename conflict found.
+    pub fn from_json(file: &str, source: &'a str) -> Result<Self, Vec<Diagnostic>> {
+        let (result, rename_errors) = collect_rename_errors(|| serde_json::from_str::<Self>(source));
+
+        let mut diagnostics = rename_diagnostics(file, source, rename_errors);
+
+        match result {
+            Ok(registry) if diagnostics.is_empty() => Ok(registry),
+            Ok(_) => Err(diagnostics),
+            Err(e) => {
+                let location = SourceLocation::from_line_column(source, e.line(), e.column());
+                // The location is rendered separately, drop serde_json's own suffix
+                let message = e.to_string();
+                let message = message.rsplit_once(" at line ").map_or(message.as_str(), |(m, _)| m);
+                diagnostics.push(Diagnostic::new(file, message).with_location(location));
+                Err(diagnostics)
+            }
+        }
+    }
+
     pub fn sanitize(&self) {
         for command in self.commands.values() {
             command.sanitize();
@@ -87,6 +109,26 @@ impl<'a> Registry<'a> {
     }
 }
 
+/// Locate rename conflicts by the first quoted occurrence of their spelling in `source`.
+pub(crate) fn rename_diagnostics(
+    file: &str,
+    source: &str,
+    errors: Vec<RenameError>,
+) -> Vec<Diagnostic> {
+    errors
+        .into_iter()
+        .map(|error| {
+            let diagnostic = Diagnostic::new(file, error.message);
+            match source.find(&format!("\"{}\"", error.spelling)) {
+                Some(start) => diagnostic.with_location(
+                    SourceLocation::from_span(source, start, start + error.spelling.len() + 2)
+                ),
+                None => diagnostic,
+            }
+        })
+        .collect()
+}
+
 impl<'a, EXT: 'a + Default> RegistryTE<'a, EXT> {
     pub fn new(name: String) -> Self {
         Self {
@@ -100,6 +142,7 @@ impl<'a, EXT: 'a + Default> RegistryTE<'a, EXT> {
             commands: HashMap::new(),
             enumerations: HashMap::new(),
             function_typedefs: HashMap::new(),
+            macros: HashMap::new(),
             opaque_typedefs: HashMap::new(),
             opaque_handle_typedefs: HashMap::new(),
             structs: HashMap::new(),
@@ -122,6 +165,7 @@ impl<'a, EXT: 'a> RegistryTE<'a, EXT> {
             commands: HashMap::new(),
             enumerations: HashMap::new(),
             function_typedefs: HashMap::new(),
+            macros: HashMap::new(),
             opaque_typedefs: HashMap::new(),
             opaque_handle_typedefs: HashMap::new(),
             structs: HashMap::new(),
@@ -180,6 +224,7 @@ impl<'a, 'de, EXT: 'a + DeserializeOwned> TryFrom<Registry<'a>> for RegistryTE<'
             commands: registry.commands,
             enumerations: registry.enumerations,
             function_typedefs: registry.function_typedefs,
+            macros: registry.macros,
             opaque_typedefs: registry.opaque_typedefs,
             opaque_handle_typedefs: registry.opaque_handle_typedefs,
             structs: registry.structs,
@@ -15,6 +15,8 @@ macro_rules! registry {
             pub commands: HashMap<Identifier, Command<'a>>,
             pub enumerations: HashMap<Identifier, Enumeration<'a>>,
             pub function_typedefs: HashMap<Identifier, FunctionTypedef<'a>>,
+            #[serde(default)]
+            pub macros: HashMap<Identifier, Macro<'a>>,
             pub opaque_typedefs: HashMap<Identifier, OpaqueTypedef>,
             pub opaque_handle_typedefs: HashMap<Identifier, OpaqueHandleTypedef>,
             pub structs: HashMap<Identifier, Structure<'a>>,
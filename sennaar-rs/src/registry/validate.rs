@@ -0,0 +1,125 @@
+use std::collections::HashSet;
+
+use crate::registry::entity::*;
+use crate::registry::{RegistryBase, Type};
+
+
+/// C type names a registry may refer to without declaring them.
+pub const BUILTIN_TYPES: &[&str] = &[
+    "void", "bool", "_Bool",
+    "char", "signed char", "unsigned char",
+    "short", "unsigned short", "int", "unsigned int",
+    "long", "unsigned long", "long long", "unsigned long long",
+    "float", "double", "long double",
+    "int8_t", "int16_t", "int32_t", "int64_t",
+    "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+    "intptr_t", "uintptr_t", "size_t", "ptrdiff_t",
+];
+
+/// Check `registry` for problems that `sanitize` would assert on, and for references to types
+/// and commands that neither it, `imported` nor [`BUILTIN_TYPES`] declare.
+///
+/// Returns one message per problem, in a stable order.
+pub fn validate_registry(registry: &RegistryBase<'_>, imported: &[&RegistryBase<'_>]) -> Vec<String> {
+    let mut types = BUILTIN_TYPES.iter().map(|s| s.to_string()).collect::<HashSet<_>>();
+    let mut commands = HashSet::new();
+    for base in std::iter::once(registry).chain(imported.iter().copied()) {
+        types.extend(declared_types(base));
+        commands.extend(base.commands.keys().map(|name| name.original().to_string()));
+    }
+
+    let mut errors = Vec::new();
+    let mut check = |context: String, ty: &Type<'_>| {
+        let mut unknown = Vec::new();
+        unknown_types(ty, &types, &mut unknown);
+        for ident in unknown {
+            errors.push(format!("{}: unknown type '{}'", context, ident));
+        }
+    };
+
+    for alias in registry.aliases.values().flat_map(PlatformVariants::with_platform_variants) {
+        check(format!("alias '{}'", alias.name.original()), &alias.target);
+    }
+    for constant in registry.constants.values().flat_map(PlatformVariants::with_platform_variants) {
+        check(format!("constant '{}'", constant.name.original()), &constant.ty);
+    }
+    for command in registry.commands.values().flat_map(PlatformVariants::with_platform_variants) {
+        let context = format!("command '{}'", command.name.original());
+        check_params(&context, &command.params, &mut check);
+        check(format!("{} result", context), &command.result);
+    }
+    for typedef in registry.function_typedefs.values().flat_map(PlatformVariants::with_platform_variants) {
+        let context = format!("functype '{}'", typedef.name.original());
+        check_params(&context, &typedef.params, &mut check);
+        check(format!("{} result", context), &typedef.result);
+    }
+    for (kind, structures) in [("struct", &registry.structs), ("union", &registry.unions)] {
+        for structure in structures.values().flat_map(PlatformVariants::with_platform_variants) {
+            for member in &structure.members {
+                check(
+                    format!("{} '{}' member '{}'", kind, structure.name.original(), member.name.original()),
+                    &member.ty,
+                );
+            }
+        }
+    }
+
+    for command in registry.commands.values().flat_map(PlatformVariants::with_platform_variants) {
+        if let Some(alias_to) = &command.alias_to
+            && !commands.contains(alias_to.original())
+        {
+            errors.push(format!(
+                "command '{}': alias of unknown command '{}'",
+                command.name.original(), alias_to.original()
+            ));
+        }
+    }
+
+    let param_lists = registry.commands.values().flat_map(PlatformVariants::with_platform_variants)
+        .map(|command| (format!("command '{}'", command.name.original()), &command.params))
+        .chain(registry.function_typedefs.values().flat_map(PlatformVariants::with_platform_variants)
+            .map(|typedef| (format!("functype '{}'", typedef.name.original()), &typedef.params)));
+    for (context, params) in param_lists {
+        for param in params {
+            if !param.nullability_matches() {
+                errors.push(format!(
+                    "{} parameter '{}': pointer nullability does not match its optional flag",
+                    context, param.name.original()
+                ));
+            }
+        }
+    }
+
+    errors.sort();
+    errors
+}
+
+fn check_params(context: &str, params: &[Param<'_>], check: &mut impl FnMut(String, &Type<'_>)) {
+    for param in params {
+        check(format!("{} parameter '{}'", context, param.name.original()), &param.ty);
+    }
+}
+
+fn declared_types(registry: &RegistryBase<'_>) -> Vec<String> {
+    let keys = registry.aliases.keys()
+        .chain(registry.bitmasks.keys())
+        .chain(registry.enumerations.keys())
+        .chain(registry.function_typedefs.keys())
+        .chain(registry.opaque_typedefs.keys())
+        .chain(registry.opaque_handle_typedefs.keys())
+        .chain(registry.structs.keys())
+        .chain(registry.unions.keys());
+    keys.map(|name| name.original().to_string()).collect()
+}
+
+fn unknown_types(ty: &Type<'_>, known: &HashSet<String>, unknown: &mut Vec<String>) {
+    match ty {
+        Type::IdentifierType(ident_type) => {
+            if !known.contains(ident_type.ident.original()) {
+                unknown.push(ident_type.ident.original().to_string());
+            }
+        }
+        Type::ArrayType(array_type) => unknown_types(&array_type.element, known, unknown),
+        Type::PointerType(ptr_type) => unknown_types(&ptr_type.pointee, known, unknown),
+    }
+}
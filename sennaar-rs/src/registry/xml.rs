@@ -0,0 +1,610 @@
+//! XML form of registries.
+//!
+//! The element structure follows the JSON schema of [`Registry`], and is derived from it at
+//! runtime so the two forms cannot drift apart:
+//!
+//! - the document element is `<Registry>`;
+//! - scalar fields of an object (strings, identifiers, numbers, booleans) become attributes,
+//!   `null`s are omitted and every other field becomes a child element named after the field;
+//! - a `$kind`-tagged value (`Type`, `CExpr`, `Metadata`, ...) becomes an element named after
+//!   its `$kind`, nested in the field element;
+//! - array elements are `<item>` elements, except for tagged values which appear directly;
+//! - map entries are `<entry key="...">` elements;
+//! - the free-form `ext` value is kept as JSON text.
+//!
+//! Identifiers keep their `original:renamed` spelling, as in JSON.
+
+use std::fmt::Write;
+
+use quick_xml::events::Event;
+use quick_xml::escape::escape;
+use quick_xml::Reader;
+use schemars::schema_for;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::registry::{rename_diagnostics, Registry, RegistryTE};
+use crate::{collect_rename_errors, Diagnostic, SourceLocation};
+
+
+const INDENT: &str = "  ";
+
+/// Write `registry` as an XML document. Map entries are sorted by key, so the output is stable.
+pub fn write_registry_xml(registry: &Registry<'_>) -> String {
+    let value = serde_json::to_value(registry).unwrap();
+    write_value(&value)
+}
+
+/// Write a registry with typed extension data as an XML document.
+///
+/// The extension is stored as JSON text, so this fails if `EXT` cannot be serialized.
+pub fn write_registry_te_xml<EXT: Serialize>(
+    registry: &RegistryTE<'_, EXT>,
+) -> Result<String, serde_json::Error> {
+    let value = serde_json::to_value(registry)?;
+    Ok(write_value(&value))
+}
+
+/// Parse a registry from its XML form.
+///
+/// `file` is only used to label diagnostics. Structural errors are collected across the whole
+/// document before giving up, as are identifier rename conflicts.
+pub fn parse_registry_xml<'a>(file: &str, source: &str) -> Result<Registry<'a>, Vec<Diagnostic>> {
+    let schema = schema_for!(Registry);
+    let schema = SchemaView::new(schema.as_value());
+    let mut errors = Vec::new();
+
+    let value = parse_document(source, &mut errors).and_then(|root| {
+        if root.name != "Registry" {
+            errors.push((root.span, format!("expected `<Registry>`, found `<{}>`", root.name)));
+            return None;
+        }
+        Some(Value::Object(schema.read_struct(&root, &schema.root_properties(), &mut errors)))
+    });
+
+    let mut diagnostics = errors
+        .into_iter()
+        .map(|((start, end), message)| {
+            Diagnostic::new(file, message)
+                .with_location(SourceLocation::from_span(source, start, end))
+        })
+        .collect::<Vec<_>>();
+
+    let Some(value) = value else {
+        return Err(diagnostics);
+    };
+    if !diagnostics.is_empty() {
+        return Err(diagnostics);
+    }
+
+    let (result, rename_errors) = collect_rename_errors(|| Registry::deserialize(&value));
+    diagnostics.extend(rename_diagnostics(file, source, rename_errors));
+    match result {
+        Ok(registry) if diagnostics.is_empty() => Ok(registry),
+        Ok(_) => Err(diagnostics),
+        Err(e) => {
+            diagnostics.push(Diagnostic::new(file, e));
+            Err(diagnostics)
+        }
+    }
+}
+
+type Span = (usize, usize);
+
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+    /// Byte range of the start tag.
+    span: Span,
+}
+
+impl Element {
+    fn new(name: impl ToString) -> Self {
+        Self {
+            name: name.to_string(),
+            attrs: Vec::new(),
+            children: Vec::new(),
+            text: String::new(),
+            span: (0, 0),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Schema
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    String,
+    Boolean,
+    Integer,
+    Number,
+}
+
+type Properties<'s> = Vec<(&'s str, &'s Value)>;
+
+enum Shape<'s> {
+    Scalar(Scalar),
+    Struct(Properties<'s>),
+    /// `$kind`-tagged enum, variants paired with their properties.
+    Tagged(Vec<(&'s str, Properties<'s>)>),
+    Map(&'s Value),
+    Array(&'s Value),
+    /// Anything goes, kept as JSON.
+    Any,
+}
+
+struct SchemaView<'s> {
+    root: &'s Value,
+    defs: Option<&'s Map<String, Value>>,
+}
+
+impl<'s> SchemaView<'s> {
+    fn new(root: &'s Value) -> Self {
+        Self { root, defs: root.get("$defs").and_then(Value::as_object) }
+    }
+
+    fn root_properties(&self) -> Properties<'s> {
+        self.properties(self.root)
+    }
+
+    fn resolve(&self, schema: &'s Value) -> &'s Value {
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => {
+                let name = reference.trim_start_matches("#/$defs/");
+                self.resolve(&self.defs.unwrap()[name])
+            }
+            None => schema,
+        }
+    }
+
+    /// Properties declared inline and through `$ref`, without the `$kind` tag.
+    fn properties(&self, schema: &'s Value) -> Properties<'s> {
+        let mut ret = Vec::new();
+        let mut collect = |schema: &'s Value| {
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                for (key, value) in properties {
+                    if key != "$kind" {
+                        ret.push((key.as_str(), value));
+                    }
+                }
+            }
+        };
+
+        collect(schema);
+        if schema.get("$ref").is_some() {
+            collect(self.resolve(schema));
+        }
+        ret
+    }
+
+    fn shape(&self, schema: &'s Value) -> Shape<'s> {
+        if schema.as_bool() == Some(true) {
+            return Shape::Any;
+        }
+
+        if let Some(any_of) = schema.get("anyOf").and_then(Value::as_array) {
+            // `Option<T>` is `anyOf: [T, null]`
+            let inner = any_of.iter().find(|s| s.get("type").and_then(Value::as_str) != Some("null"));
+            return inner.map_or(Shape::Any, |s| self.shape(s));
+        }
+
+        if let Some(one_of) = schema.get("oneOf").and_then(Value::as_array) {
+            let tags = one_of
+                .iter()
+                .map(|variant| variant.pointer("/properties/$kind/const").and_then(Value::as_str))
+                .collect::<Option<Vec<_>>>();
+            return match tags {
+                Some(tags) => Shape::Tagged(
+                    tags.into_iter().zip(one_of).map(|(tag, v)| (tag, self.properties(v))).collect()
+                ),
+                // Untagged alternatives, such as known-or-custom names, are all strings
+                None => one_of.first().map_or(Shape::Any, |s| self.shape(s)),
+            };
+        }
+
+        if schema.get("$ref").is_some() {
+            return self.shape(self.resolve(schema));
+        }
+
+        let ty = match schema.get("type") {
+            Some(Value::String(ty)) => Some(ty.as_str()),
+            Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).find(|ty| *ty != "null"),
+            _ => None,
+        };
+        match ty {
+            Some("object") if schema.get("properties").is_some() => Shape::Struct(self.properties(schema)),
+            Some("object") => schema.get("additionalProperties").map_or(Shape::Any, Shape::Map),
+            Some("array") => schema.get("items").map_or(Shape::Any, Shape::Array),
+            Some("string") => Shape::Scalar(Scalar::String),
+            Some("boolean") => Shape::Scalar(Scalar::Boolean),
+            Some("integer") => Shape::Scalar(Scalar::Integer),
+            Some("number") => Shape::Scalar(Scalar::Number),
+            None if schema.get("enum").is_some() => Shape::Scalar(Scalar::String),
+            _ => Shape::Any,
+        }
+    }
+
+    fn property_shape(&self, properties: &Properties<'s>, name: &str) -> Option<Shape<'s>> {
+        properties.iter().find(|(key, _)| *key == name).map(|(_, schema)| self.shape(schema))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Writing
+// ---------------------------------------------------------------------------
+
+fn write_value(value: &Value) -> String {
+    let schema = schema_for!(Registry);
+    let schema = SchemaView::new(schema.as_value());
+
+    let mut root = Element::new("Registry");
+    schema.build_struct(&mut root, value, &schema.root_properties());
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    emit(&mut out, &root, 0);
+    out
+}
+
+fn scalar_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl<'s> SchemaView<'s> {
+    fn build_struct(&self, element: &mut Element, value: &Value, properties: &Properties<'s>) {
+        let Some(object) = value.as_object() else { return };
+
+        for (key, value) in object {
+            if key == "$kind" || value.is_null() {
+                continue;
+            }
+
+            match self.property_shape(properties, key).unwrap_or(Shape::Any) {
+                Shape::Scalar(_) => element.attrs.push((key.clone(), scalar_text(value))),
+                shape => {
+                    let mut child = Element::new(key);
+                    self.build_content(&mut child, value, &shape);
+                    element.children.push(child);
+                }
+            }
+        }
+    }
+
+    fn build_content(&self, element: &mut Element, value: &Value, shape: &Shape<'s>) {
+        match shape {
+            Shape::Tagged(variants) => {
+                let kind = value.get("$kind").and_then(Value::as_str).unwrap_or_default();
+                let properties = variants.iter().find(|(tag, _)| *tag == kind).map(|(_, p)| p);
+                let mut child = Element::new(kind);
+                if let Some(properties) = properties {
+                    self.build_struct(&mut child, value, properties);
+                }
+                element.children.push(child);
+            }
+            Shape::Struct(properties) => self.build_struct(element, value, properties),
+            Shape::Map(schema) => {
+                let Some(object) = value.as_object() else { return };
+                let shape = self.shape(schema);
+
+                let mut entries = object.iter().collect::<Vec<_>>();
+                entries.sort_by_key(|(key, _)| key.as_str());
+                for (key, value) in entries {
+                    let mut entry = Element::new("entry");
+                    entry.attrs.push(("key".to_string(), key.clone()));
+                    self.build_content(&mut entry, value, &shape);
+                    element.children.push(entry);
+                }
+            }
+            Shape::Array(schema) => {
+                let shape = self.shape(schema);
+                for item in value.as_array().into_iter().flatten() {
+                    if let Shape::Tagged(_) = shape {
+                        self.build_content(element, item, &shape);
+                    } else {
+                        let mut child = Element::new("item");
+                        self.build_content(&mut child, item, &shape);
+                        element.children.push(child);
+                    }
+                }
+            }
+            Shape::Scalar(_) => element.text = scalar_text(value),
+            Shape::Any => element.text = value.to_string(),
+        }
+    }
+}
+
+/// Escape `s` for use in XML. Line breaks and tabs are written as character references where
+/// a parser would otherwise normalize them.
+fn escape_xml(s: &str, attribute: bool) -> String {
+    let escaped = escape(s).replace('\r', "&#13;");
+    if attribute {
+        escaped.replace('\n', "&#10;").replace('\t', "&#9;")
+    } else {
+        escaped
+    }
+}
+
+fn emit(out: &mut String, element: &Element, depth: usize) {
+    let indent = INDENT.repeat(depth);
+    write!(out, "{}<{}", indent, element.name).unwrap();
+    for (key, value) in &element.attrs {
+        write!(out, " {}=\"{}\"", key, escape_xml(value, true)).unwrap();
+    }
+
+    if !element.children.is_empty() {
+        out.push_str(">\n");
+        for child in &element.children {
+            emit(out, child, depth + 1);
+        }
+        writeln!(out, "{}</{}>", indent, element.name).unwrap();
+    } else if !element.text.is_empty() {
+        writeln!(out, ">{}</{}>", escape_xml(&element.text, false), element.name).unwrap();
+    } else {
+        out.push_str("/>\n");
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Reading
+// ---------------------------------------------------------------------------
+
+fn parse_document(source: &str, errors: &mut Vec<(Span, String)>) -> Option<Element> {
+    let mut reader = Reader::from_str(source);
+    let mut stack: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    macro_rules! fail {
+        ($e:expr) => {{
+            let pos = reader.error_position() as usize;
+            errors.push(((pos, pos + 1), format!("malformed XML: {}", $e)));
+            return None;
+        }};
+    }
+
+    loop {
+        let start = reader.buffer_position() as usize;
+        let event = match reader.read_event() {
+            Ok(event) => event,
+            Err(e) => fail!(e),
+        };
+        let span = (start, reader.buffer_position() as usize);
+
+        let (tag, is_empty) = match event {
+            Event::Start(tag) => (tag, false),
+            Event::Empty(tag) => (tag, true),
+            Event::End(_) => {
+                let element = stack.pop().unwrap();
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => root = Some(element),
+                }
+                continue;
+            }
+            Event::Text(text) => {
+                match text.unescape() {
+                    Ok(text) => {
+                        if let Some(top) = stack.last_mut() {
+                            top.text.push_str(&text);
+                        }
+                    }
+                    Err(e) => fail!(e),
+                }
+                continue;
+            }
+            Event::CData(data) => {
+                if let Some(top) = stack.last_mut() {
+                    top.text.push_str(&String::from_utf8_lossy(&data));
+                }
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+
+        let mut element = Element::new(String::from_utf8_lossy(tag.name().as_ref()));
+        element.span = span;
+        for attr in tag.attributes() {
+            let attr = match attr {
+                Ok(attr) => attr,
+                Err(e) => fail!(e),
+            };
+            let value = match attr.unescape_value() {
+                Ok(value) => value.into_owned(),
+                Err(e) => fail!(e),
+            };
+            element.attrs.push((String::from_utf8_lossy(attr.key.as_ref()).into_owned(), value));
+        }
+
+        if !is_empty {
+            stack.push(element);
+        } else if let Some(parent) = stack.last_mut() {
+            parent.children.push(element);
+        } else {
+            root = Some(element);
+        }
+    }
+
+    if root.is_none() {
+        errors.push(((source.len(), source.len()), "missing document element".to_string()));
+    }
+    root
+}
+
+fn parse_scalar(
+    text: &str,
+    scalar: Scalar,
+    span: Span,
+    errors: &mut Vec<(Span, String)>,
+) -> Value {
+    let parsed = match scalar {
+        Scalar::String => return Value::String(text.to_string()),
+        Scalar::Boolean => text.parse::<bool>().ok().map(Value::Bool),
+        Scalar::Integer => text.parse::<i64>().ok().map(Value::from)
+            .or_else(|| text.parse::<u64>().ok().map(Value::from)),
+        Scalar::Number => text.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(Value::Number),
+    };
+
+    parsed.unwrap_or_else(|| {
+        let expected = match scalar {
+            Scalar::Boolean => "boolean",
+            Scalar::Integer => "integer",
+            _ => "number",
+        };
+        errors.push((span, format!("expected {}, found '{}'", expected, text)));
+        Value::Null
+    })
+}
+
+impl<'s> SchemaView<'s> {
+    fn read_struct(
+        &self,
+        element: &Element,
+        properties: &Properties<'s>,
+        errors: &mut Vec<(Span, String)>,
+    ) -> Map<String, Value> {
+        let mut ret = Map::new();
+
+        for (key, value) in &element.attrs {
+            // Map entries carry their key next to the fields of their value
+            if element.name == "entry" && key == "key" {
+                continue;
+            }
+
+            match self.property_shape(properties, key) {
+                Some(Shape::Scalar(scalar)) => {
+                    ret.insert(key.clone(), parse_scalar(value, scalar, element.span, errors));
+                }
+                _ => errors.push((element.span, format!("unexpected attribute `{}` on `<{}>`", key, element.name))),
+            }
+        }
+
+        for child in &element.children {
+            match self.property_shape(properties, &child.name) {
+                Some(Shape::Scalar(_)) | None => {
+                    errors.push((child.span, format!("unexpected element `<{}>` in `<{}>`", child.name, element.name)));
+                }
+                Some(shape) => {
+                    if ret.contains_key(&child.name) {
+                        errors.push((child.span, format!("duplicate element `<{}>`", child.name)));
+                    }
+                    ret.insert(child.name.clone(), self.read_content(child, &shape, errors));
+                }
+            }
+        }
+
+        self.check_text(element, errors);
+
+        // Free-form values are required even when `null`, which is written as nothing
+        for (key, schema) in properties {
+            if !ret.contains_key(*key) && matches!(self.shape(schema), Shape::Any) {
+                ret.insert(key.to_string(), Value::Null);
+            }
+        }
+
+        ret
+    }
+
+    fn read_tagged(
+        &self,
+        element: &Element,
+        variants: &[(&'s str, Properties<'s>)],
+        errors: &mut Vec<(Span, String)>,
+    ) -> Value {
+        let Some((tag, properties)) = variants.iter().find(|(tag, _)| *tag == element.name) else {
+            let expected = variants.iter().map(|(tag, _)| format!("`<{}>`", tag)).collect::<Vec<_>>();
+            errors.push((
+                element.span,
+                format!("unexpected element `<{}>`, expected one of {}", element.name, expected.join(", ")),
+            ));
+            return Value::Null;
+        };
+
+        let mut object = Map::new();
+        object.insert("$kind".to_string(), Value::String(tag.to_string()));
+        object.extend(self.read_struct(element, properties, errors));
+        Value::Object(object)
+    }
+
+    fn read_content(
+        &self,
+        element: &Element,
+        shape: &Shape<'s>,
+        errors: &mut Vec<(Span, String)>,
+    ) -> Value {
+        match shape {
+            Shape::Tagged(variants) => {
+                self.check_text(element, errors);
+                match element.children.as_slice() {
+                    [child] => self.read_tagged(child, variants, errors),
+                    _ => {
+                        errors.push((element.span, format!("`<{}>` must contain exactly one element", element.name)));
+                        Value::Null
+                    }
+                }
+            }
+            Shape::Struct(properties) => Value::Object(self.read_struct(element, properties, errors)),
+            Shape::Map(schema) => {
+                self.check_text(element, errors);
+                let shape = self.shape(schema);
+                let mut ret = Map::new();
+                for child in &element.children {
+                    let key = child.attrs.iter().find(|(k, _)| k == "key").map(|(_, v)| v);
+                    match key {
+                        Some(key) if child.name == "entry" => {
+                            if ret.contains_key(key) {
+                                errors.push((child.span, format!("duplicate entry '{}'", key)));
+                            }
+                            ret.insert(key.clone(), self.read_content(child, &shape, errors));
+                        }
+                        _ => errors.push((child.span, format!("expected `<entry key=\"...\">`, found `<{}>`", child.name))),
+                    }
+                }
+                Value::Object(ret)
+            }
+            Shape::Array(schema) => {
+                self.check_text(element, errors);
+                let shape = self.shape(schema);
+                let mut ret = Vec::new();
+                for child in &element.children {
+                    if let Shape::Tagged(variants) = &shape {
+                        ret.push(self.read_tagged(child, variants, errors));
+                    } else if child.name == "item" {
+                        ret.push(self.read_content(child, &shape, errors));
+                    } else {
+                        errors.push((child.span, format!("expected `<item>`, found `<{}>`", child.name)));
+                    }
+                }
+                Value::Array(ret)
+            }
+            Shape::Scalar(scalar) => {
+                self.check_leaf(element, errors);
+                parse_scalar(&element.text, *scalar, element.span, errors)
+            }
+            Shape::Any => {
+                self.check_leaf(element, errors);
+                serde_json::from_str(&element.text).unwrap_or_else(|e| {
+                    errors.push((element.span, format!("invalid JSON in `<{}>`: {}", element.name, e)));
+                    Value::Null
+                })
+            }
+        }
+    }
+
+    fn check_text(&self, element: &Element, errors: &mut Vec<(Span, String)>) {
+        if !element.text.trim().is_empty() {
+            errors.push((element.span, format!("unexpected text in `<{}>`", element.name)));
+        }
+    }
+
+    fn check_leaf(&self, element: &Element, errors: &mut Vec<(Span, String)>) {
+        if let Some(child) = element.children.first() {
+            errors.push((child.span, format!("unexpected element `<{}>` in `<{}>`", child.name, element.name)));
+        }
+    }
+}
@@ -0,0 +1,82 @@
+use regex::Regex;
+
+/// Allowlist and blocklist of regexes, each matched against the whole string.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// When not empty, only strings matching one of these pass.
+    pub allow: Vec<Regex>,
+    /// Strings matching one of these never pass, even if allowed.
+    pub block: Vec<Regex>,
+}
+
+impl Filter {
+    pub fn new<S: AsRef<str>>(allow: &[S], block: &[S]) -> Result<Self, regex::Error> {
+        Ok(Self { allow: anchored(allow)?, block: anchored(block)? })
+    }
+
+    pub fn passes(&self, s: &str) -> bool {
+        (self.allow.is_empty() || self.allow.iter().any(|re| re.is_match(s))) && !self.blocks(s)
+    }
+
+    pub fn blocks(&self, s: &str) -> bool {
+        self.block.iter().any(|re| re.is_match(s))
+    }
+}
+
+fn anchored(patterns: &[impl AsRef<str>]) -> Result<Vec<Regex>, regex::Error> {
+    patterns.iter().map(|pattern| Regex::new(&format!("^(?:{})$", pattern.as_ref()))).collect()
+}
+
+/// How libclang parses a header, and which of its declarations make it into the registry.
+#[derive(Debug, Clone, Default)]
+pub struct ParseConfig {
+    /// Directories searched for `#include`, as `-I`.
+    pub include_dirs: Vec<String>,
+    /// Macros defined before the header, as name and optional value, as `-D`.
+    pub defines: Vec<(String, Option<String>)>,
+    /// Target triple such as `x86_64-pc-windows-msvc`, the host if omitted.
+    pub target: Option<String>,
+    pub sysroot: Option<String>,
+    /// Language standard such as `c11` or `gnu17`, the clang default if omitted.
+    pub std: Option<String>,
+    /// Passed to clang as-is, after all of the above.
+    pub clang_args: Vec<String>,
+    /// Filter on entity names, including macros.
+    pub names: Filter,
+    /// Filter on the names of the files declarations are in, as the paths libclang found them at.
+    ///
+    /// The header itself is always extracted unless blocked, the files it includes only when allowed.
+    pub files: Filter,
+}
+
+impl ParseConfig {
+    /// Command line arguments passed to libclang.
+    pub fn args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for dir in &self.include_dirs {
+            args.push(format!("-I{}", dir));
+        }
+        for (name, value) in &self.defines {
+            match value {
+                Some(value) => args.push(format!("-D{}={}", name, value)),
+                None => args.push(format!("-D{}", name)),
+            }
+        }
+        if let Some(target) = &self.target {
+            args.push(format!("--target={}", target));
+        }
+        if let Some(sysroot) = &self.sysroot {
+            args.push(format!("--sysroot={}", sysroot));
+        }
+        if let Some(std) = &self.std {
+            args.push(format!("-std={}", std));
+        }
+        args.extend(self.clang_args.iter().cloned());
+        args
+    }
+
+    /// Whether declarations in `file` are extracted, `main` telling if it is the header itself.
+    pub fn includes_file(&self, file: &str, main: bool) -> bool {
+        if main { !self.files.blocks(file) } else { !self.files.allow.is_empty() && self.files.passes(file) }
+    }
+}
@@ -0,0 +1,135 @@
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::ptr::null_mut;
+
+use clang_sys::*;
+
+/// The cursor a [`ClangError`] happened at, boxed within the error to keep it small.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CursorContext {
+    /// Spelling of the cursor kind, like `BinaryOperator`.
+    pub kind: String,
+    pub spelling: String,
+    /// File, line and column of the cursor, empty and 0 when it has no location.
+    pub file: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl CursorContext {
+    pub fn new(cursor: CXCursor) -> Box<Self> {
+        unsafe {
+            let mut file = null_mut();
+            let mut line = 0;
+            let mut column = 0;
+            clang_getSpellingLocation(clang_getCursorLocation(cursor), &mut file, &mut line, &mut column, null_mut());
+            Box::new(Self {
+                kind: lossy_string(clang_getCursorKindSpelling(clang_getCursorKind(cursor))),
+                spelling: lossy_string(clang_getCursorSpelling(cursor)),
+                file: if file.is_null() { String::new() } else { lossy_string(clang_getFileName(file)) },
+                line,
+                column,
+            })
+        }
+    }
+}
+
+impl Display for CursorContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if !self.file.is_empty() {
+            write!(f, "{}:{}:{}: ", self.file, self.line, self.column)?;
+        }
+        write!(f, "{}", self.kind)?;
+        if !self.spelling.is_empty() {
+            write!(f, " '{}'", self.spelling)?;
+        }
+        Ok(())
+    }
+}
+
+/// Error mapping the AST of libclang.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ClangError {
+    /// libclang could not parse the header at all.
+    Parse { header: String },
+    /// Error libclang reported while parsing, formatted by libclang with its location.
+    Diagnostic { message: String },
+    /// Cursor of a kind that is not mapped.
+    UnsupportedCursor { cursor: Box<CursorContext> },
+    /// Unary or binary operator that is not mapped, by its spelling.
+    UnsupportedOperator { operator: String, cursor: Box<CursorContext> },
+    /// Type that is not mapped, by its spelling and the spelling of its kind.
+    UnsupportedType { spelling: String, kind: String, cursor: Option<Box<CursorContext>> },
+    /// Literal of a type with no C suffix.
+    UnsupportedLiteral { kind: String, cursor: Box<CursorContext> },
+    /// Macro left out of the registry, see [`crate::panspace::lower_defines`].
+    UnsupportedMacro { reason: String, cursor: Box<CursorContext> },
+    /// Anything libclang returned that does not make sense, such as a child missing.
+    Malformed { message: String, cursor: Option<Box<CursorContext>> },
+}
+
+impl ClangError {
+    pub fn malformed(message: impl ToString) -> Self {
+        ClangError::Malformed { message: message.to_string(), cursor: None }
+    }
+
+    pub fn cursor(&self) -> Option<&CursorContext> {
+        match self {
+            ClangError::Parse { .. } | ClangError::Diagnostic { .. } => None,
+            ClangError::UnsupportedCursor { cursor }
+            | ClangError::UnsupportedOperator { cursor, .. }
+            | ClangError::UnsupportedLiteral { cursor, .. }
+            | ClangError::UnsupportedMacro { cursor, .. } => Some(cursor.as_ref()),
+            ClangError::UnsupportedType { cursor, .. } | ClangError::Malformed { cursor, .. } => cursor.as_deref(),
+        }
+    }
+
+    /// Tell that the error happened at `cursor`, unless it already tells where.
+    pub fn at(mut self, cursor: CXCursor) -> Self {
+        if let ClangError::UnsupportedType { cursor: context @ None, .. }
+        | ClangError::Malformed { cursor: context @ None, .. } = &mut self
+        {
+            *context = Some(CursorContext::new(cursor));
+        }
+        self
+    }
+}
+
+impl Display for ClangError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ClangError::Parse { header } => return write!(f, "unable to parse '{}'", header),
+            ClangError::Diagnostic { message } => return write!(f, "{}", message),
+            ClangError::UnsupportedCursor { .. } => write!(f, "unsupported cursor")?,
+            ClangError::UnsupportedOperator { operator, .. } => write!(f, "unsupported operator '{}'", operator)?,
+            ClangError::UnsupportedType { spelling, kind, .. } => {
+                write!(f, "unsupported type '{}' of kind '{}'", spelling, kind)?
+            }
+            ClangError::UnsupportedLiteral { kind, .. } => write!(f, "unsupported literal of type '{}'", kind)?,
+            ClangError::UnsupportedMacro { reason, .. } => write!(f, "unsupported macro: {}", reason)?,
+            ClangError::Malformed { message, .. } => write!(f, "{}", message)?,
+        }
+        if let Some(cursor) = self.cursor() {
+            write!(f, " at {}", cursor)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ClangError {}
+
+impl From<ClangError> for String {
+    fn from(error: ClangError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Convert and consume a string, replacing what is not UTF-8.
+pub(crate) fn lossy_string(s: CXString) -> String {
+    unsafe {
+        let raw = clang_getCString(s);
+        let owned = if raw.is_null() { String::new() } else { CStr::from_ptr(raw).to_string_lossy().into_owned() };
+        clang_disposeString(s);
+        owned
+    }
+}
@@ -1,35 +1,53 @@
 #![allow(non_upper_case_globals)]
 
 use std::borrow::Cow;
-use std::ptr::null_mut;
+use std::collections::HashMap;
 
 use clang_sys::*;
 
 use crate::{Identifier, Internalize};
 use crate::cpl::*;
-use crate::rossetta::clang_ty::map_ty;
+use crate::panspace::{is_standard_type_name, parse_expression, parse_expression_with_types};
+use crate::rossetta::clang_ty::{map_ty, unsupported_type};
+use crate::rossetta::clang_error::{lossy_string, CursorContext};
 use crate::rossetta::clang_utils::*;
 
-// TODO: improve error reporting
 // TODO: improve life time
+/// Map the expression at `cursor`. Errors tell the innermost cursor that could not be mapped.
 pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError> {
+    unsafe { map_node(cursor).map_err(|e| e.at(cursor)) }
+}
+
+unsafe fn map_node(cursor: CXCursor) -> Result<CExpr<'static>, ClangError> {
     unsafe {
         let cursor_kind = clang_getCursorKind(cursor);
 
         if clang_isExpression(cursor_kind) == 0 {
-            return Err("Cursor doesn't point to an expression".to_string());
+            return Err(ClangError::malformed("Cursor doesn't point to an expression"));
+        }
+
+        if let Some(expansion) = map_macro_expansion(cursor)? {
+            return Ok(expansion);
         }
 
         let mapped: CExpr = match cursor_kind {
             CXCursor_IntegerLiteral => {
+                if let [spelling] = get_tokens(clang_Cursor_getTranslationUnit(cursor), cursor)?.as_slice() {
+                    let (value, suffix) = split_suffix(spelling, &['u', 'U', 'l', 'L']);
+                    return Ok(CExpr::IntLiteral(Box::new(CIntLiteralExpr {
+                        value: Cow::Owned(value.to_string()),
+                        suffix: Cow::Owned(suffix.to_string()),
+                    })));
+                }
+
                 let result = clang_Cursor_Evaluate(cursor);
                 if result.is_null() {
-                    return Err("Unable to evaluate an integer literal.".to_string());
+                    return Err(ClangError::malformed("Unable to evaluate an integer literal."));
                 }
                 let result_kind = clang_EvalResult_getKind(result);
 
                 if result_kind != CXEval_Int {
-                    return Err("Unable to evaluate an integer literal to integer.".to_string());
+                    return Err(ClangError::malformed("Unable to evaluate an integer literal to integer."));
                 }
 
                 let str = if clang_EvalResult_isUnsignedInt(result) != 0 {
@@ -40,7 +58,7 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
                     format!("{:#X}", i)
                 };
 
-                let suffix = get_suffix(cursor);
+                let suffix = get_suffix(cursor)?;
 
                 clang_EvalResult_dispose(result);
 
@@ -49,30 +67,31 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
                     suffix: Cow::Borrowed(suffix),
                 }))
             }
-            CXCursor_CharacterLiteral => {
-                let result = clang_Cursor_Evaluate(cursor);
-                if result.is_null() {
-                    return Err("Unable to evaluate a character literal.".to_string());
-                }
-                let result_kind = clang_EvalResult_getKind(result);
-
-                if result_kind != CXEval_Int {
-                    return Err("Unable to evaluate a character literal to integer.".to_string());
-                }
-
-                let codepoint = clang_EvalResult_getAsUnsigned(result);
-                let c = char::from_u32(codepoint as u32)
-                    .ok_or("Unable to convert i32 to char.".to_string())?;
-
-                // let cs = CStr::from_ptr(raw_cs).to_owned();
-                // let s = cs.into_string().map_err(|_| "Failed to convert string")?;
-
-                clang_EvalResult_dispose(result);
-
-                CExpr::CharLiteral(Box::new(CCharLiteralExpr {
-                    value: Cow::Owned(c.escape_default().to_string()),
+            CXCursor_FloatingLiteral => {
+                let tokens = get_tokens(clang_Cursor_getTranslationUnit(cursor), cursor)?;
+                let [spelling] = tokens.as_slice() else {
+                    return Err(ClangError::malformed("Unable to spell a floating literal."));
+                };
+                let (value, suffix) = split_suffix(spelling, &['f', 'F', 'l', 'L']);
+                CExpr::FloatLiteral(Box::new(CFloatLiteralExpr {
+                    value: Cow::Owned(value.to_string()),
+                    suffix: Cow::Owned(suffix.to_string()),
                 }))
             }
+            CXCursor_CharacterLiteral => {
+                // Keep the escapes as spelled, without the encoding prefix, like panspace does
+                let tokens = get_tokens(clang_Cursor_getTranslationUnit(cursor), cursor)?;
+                let value = match tokens.as_slice() {
+                    [spelling] => spelling
+                        .find('\'')
+                        .and_then(|start| spelling[start + 1..].strip_suffix('\'')),
+                    _ => None,
+                };
+                let Some(value) = value else {
+                    return Err(ClangError::malformed("Unable to spell a character literal."));
+                };
+                CExpr::CharLiteral(Box::new(CCharLiteralExpr { value: Cow::Owned(value.to_string()) }))
+            }
             CXCursor_DeclRefExpr => CExpr::Identifier(Box::new(CIdentifierExpr {
                 ident: get_identifier(cursor)?,
             })),
@@ -86,14 +105,14 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
             CXCursor_CallExpr => {
                 let children = get_children(cursor);
                 if children.len() == 0 {
-                    return Err("Size doesn't match(CallExpr)".to_string());
+                    return Err(ClangError::malformed("Size doesn't match(CallExpr)"));
                 } else {
                     let callee = map_nodes(children[0])?;
                     let args = children
                         .into_iter()
                         .skip(1)
                         .map(|e| map_nodes(e))
-                        .collect::<Result<Vec<CExpr>, String>>()?;
+                        .collect::<Result<Vec<CExpr>, ClangError>>()?;
 
                     CExpr::Call(Box::new(CCallExpr { callee, args }))
                 }
@@ -118,8 +137,10 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
                     CXUnaryOperator_Minus => either::Right(CUnaryOp::Minus),
                     CXUnaryOperator_Not => either::Right(CUnaryOp::BitNot),
                     CXUnaryOperator_LNot => either::Right(CUnaryOp::Not),
-                    // TODO: there are unhandled operators, but we doesn't expect them.
-                    _ => unreachable!(),
+                    _ => return Err(ClangError::UnsupportedOperator {
+                        operator: lossy_string(clang_getUnaryOperatorKindSpelling(kind)),
+                        cursor: CursorContext::new(cursor),
+                    }),
                 };
 
                 let [child] = get_children_n(cursor)?;
@@ -131,70 +152,35 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
                     |expr, op| CExpr::Unary(Box::new(CUnaryExpr { expr, op })),
                 )
             }
-            // what?
+            // `sizeof` and `alignof`, whose operand may be a type. libclang tells neither the
+            // operator nor the type, so both are read back from the tokens.
             CXCursor_UnaryExpr => {
-                let kind = clang_getCursorUnaryOperatorKind(cursor);
-                println!("UnaryExpr kind: {}", kind);
-
-                let range = clang_getCursorExtent(cursor);
-                let start = clang_getRangeStart(range);
-                let end = clang_getRangeEnd(range);
-
-                let mut line_start = 0u32;
-                let mut line_end = 0u32;
-                let mut column_start = 0u32;
-                let mut column_end = 0u32;
-                let mut offset_start = 0u32;
-                let mut offset_end = 0u32;
-
-                clang_getFileLocation(
-                    start,
-                    null_mut(),
-                    &mut line_start,
-                    &mut column_start,
-                    &mut offset_start,
-                );
-                clang_getFileLocation(
-                    end,
-                    null_mut(),
-                    &mut line_end,
-                    &mut column_end,
-                    &mut offset_end,
-                );
-
-                eprintln!(
-                    "UnaryExpr location: {}:{} - {}:{}",
-                    line_start, column_start, line_end, column_end
-                );
-                eprintln!("Offsets: {} - {}", offset_start, offset_end);
-
-                // TODO: @chuigda determine what we should do here. maybe retrieve the original source and parse it ourselves.
-
-                let children = get_children(cursor);
-                let ty = clang_getCursorType(cursor);
-                println!("Type kind: {}", ty.kind);
-                let argc = clang_Cursor_getNumArguments(cursor);
-                println!("Children count: {}", children.len());
-                println!("argc: {}", argc);
-                let result = clang_Cursor_Evaluate(cursor);
-                let result_kind = clang_EvalResult_getKind(result);
-                println!("result kind: {}", result_kind);
-                println!("result value: {}", clang_EvalResult_getAsInt(result));
-                clang_EvalResult_dispose(result);
+                let tokens = get_tokens(clang_Cursor_getTranslationUnit(cursor), cursor)?;
+                let keyword = tokens.first().map_or("", String::as_str);
+                let (unary_op, type_op) = match keyword {
+                    "sizeof" => (CUnaryOp::SizeOf, CTypeOperator::SizeOf),
+                    "_Alignof" | "alignof" | "__alignof__" | "__alignof" => (CUnaryOp::AlignOf, CTypeOperator::AlignOf),
+                    _ => return Err(ClangError::UnsupportedOperator {
+                        operator: keyword.to_string(),
+                        cursor: CursorContext::new(cursor),
+                    }),
+                };
 
-                todo!()
+                match get_children(cursor).into_iter().find(|child| is_expression(*child)) {
+                    Some(operand) => CExpr::Unary(Box::new(CUnaryExpr { expr: map_nodes(operand)?, op: unary_op })),
+                    None => CExpr::TypeOperator(Box::new(CTypeOperatorExpr {
+                        op: type_op,
+                        ty: map_type_name(cursor, parenthesized(&tokens[1..])?)?,
+                    })),
+                }
             }
             CXCursor_CStyleCastExpr => {
                 let [casted] = get_children_n::<1>(cursor)?;
-                let ty = clang_getCursorType(cursor);
-                let cty = map_ty(ty)?;
+                let ty = map_cast_type(clang_getCursorType(cursor))?;
 
                 let mapped = map_nodes(casted)?;
 
-                CExpr::Cast(Box::new(CCastExpr {
-                    expr: mapped,
-                    ty: CExpr::identifier(format!("{}", cty).interned()),
-                }))
+                CExpr::Cast(Box::new(CCastExpr { expr: mapped, ty }))
             }
 
             // https://clang.llvm.org/doxygen/group__CINDEX__HIGH.html
@@ -233,7 +219,10 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
                     CXBinaryOperator_XorAssign => CBinaryOp::BitXorAssign,
                     CXBinaryOperator_OrAssign => CBinaryOp::BitOrAssign,
                     CXBinaryOperator_Comma => CBinaryOp::Comma,
-                    _ => unreachable!(),
+                    _ => return Err(ClangError::UnsupportedOperator {
+                        operator: lossy_string(clang_getBinaryOperatorKindSpelling(kind)),
+                        cursor: CursorContext::new(cursor),
+                    }),
                 };
 
                 let [raw_lhs, raw_rhs] = get_children_n(cursor)?;
@@ -270,25 +259,12 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
             // We don't know that it is, so let's hope it has only one child.
             // This is typically a implicit cast.
             // TODO @chuigda: to summarize what CXCursor_UnexposedExpr represents and handle various cases properly.
+            CXCursor_UnexposedExpr if is_offset_of(cursor)? => map_offset_of(cursor)?,
             CXCursor_UnexposedExpr => {
-                let spelling = clang_getCursorKindSpelling(cursor_kind);
-                let s = from_CXString(spelling).unwrap();
-                let argc = clang_Cursor_getNumArguments(cursor);
-                let children_count = get_children(cursor).len();
-
-                eprintln!(
-                    "UnexposedExpr encountered: {}, argc: {}, children_count: {}",
-                    s, argc, children_count
-                );
-
                 let [child] = get_children_n(cursor)?;
                 map_nodes(child)?
             }
-            _ => {
-                let cs = clang_getCursorKindSpelling(cursor_kind);
-                let s = from_CXString(cs).unwrap();
-                todo!("unknown cursor kind: {}", s);
-            }
+            _ => return Err(ClangError::UnsupportedCursor { cursor: CursorContext::new(cursor) }),
         };
 
         Ok(mapped)
@@ -309,6 +285,114 @@ pub unsafe fn map_nodes(cursor: CXCursor) -> Result<CExpr<'static>, ClangError>
 //   Some(mapped_children.try_into().unwrap())
 // }
 
+/// The macro that `cursor` was expanded from as a whole, as an identifier or a call, so that
+/// names like `MAX_SIZE` are kept instead of the values they stand for. This needs the detailed
+/// preprocessing record of the translation unit.
+unsafe fn map_macro_expansion(cursor: CXCursor) -> Result<Option<CExpr<'static>>, ClangError> {
+    unsafe {
+        let unit = clang_Cursor_getTranslationUnit(cursor);
+        let expansion = clang_getCursor(unit, clang_getRangeStart(clang_getCursorExtent(cursor)));
+        if get_kind(expansion) != CXCursor_MacroExpansion || get_offsets(expansion) != get_offsets(cursor) {
+            return Ok(None);
+        }
+
+        // Arguments of function-like macros are not always expressions, in which case the
+        // expansion is mapped instead
+        let tokens = get_tokens(unit, cursor)?;
+        match tokens.as_slice() {
+            [name] => Ok(Some(CExpr::identifier(name.interned()))),
+            _ => Ok(parse_expression(&tokens.join(" ")).ok().map(CExpr::into_owned)),
+        }
+    }
+}
+
+/// Split the suffix made of `suffix_chars` off a literal spelling.
+fn split_suffix<'s>(spelling: &'s str, suffix_chars: &[char]) -> (&'s str, &'s str) {
+    spelling.split_at(spelling.trim_end_matches(suffix_chars).len())
+}
+
+/// Whether `cursor` is `offsetof`, which libclang does not expose. Its children are references to
+/// the type and members, while an implicit cast of it has the `offsetof` as its only child.
+unsafe fn is_offset_of(cursor: CXCursor) -> Result<bool, ClangError> {
+    unsafe {
+        let tokens = get_tokens(clang_Cursor_getTranslationUnit(cursor), cursor)?;
+        Ok(matches!(tokens.first().map(String::as_str), Some("offsetof" | "__builtin_offsetof"))
+            && get_children(cursor).into_iter().any(|child| get_kind(child) == CXCursor_MemberRef))
+    }
+}
+
+/// Map `offsetof(type, designator)` from its tokens.
+unsafe fn map_offset_of(cursor: CXCursor) -> Result<CExpr<'static>, ClangError> {
+    unsafe {
+        let tokens = get_tokens(clang_Cursor_getTranslationUnit(cursor), cursor)?;
+        let args = parenthesized(&tokens[1..])?;
+        let mut depth = 0;
+        let comma = args
+            .iter()
+            .position(|token| {
+                match token.as_str() {
+                    "(" | "[" => depth += 1,
+                    ")" | "]" => depth -= 1,
+                    _ => {}
+                }
+                depth == 0 && token == ","
+            })
+            .ok_or(ClangError::malformed("offsetof without a member"))?;
+
+        let designator = args[comma + 1..].join(" ");
+        let member = parse_expression(&designator)
+            .map_err(|_| ClangError::malformed(format!("Unable to parse the offsetof member '{}'", designator)))?
+            .into_owned();
+
+        Ok(CExpr::OffsetOf(Box::new(COffsetOfExpr {
+            ty: map_type_name(cursor, &args[..comma])?,
+            member,
+        })))
+    }
+}
+
+/// The tokens within the parentheses that `tokens` consists of.
+fn parenthesized(tokens: &[String]) -> Result<&[String], ClangError> {
+    match tokens {
+        [open, inner @ .., close] if open == "(" && close == ")" => Ok(inner),
+        _ => Err(ClangError::malformed(format!("Expected a parenthesized operand, but got '{}'", tokens.join(" ")))),
+    }
+}
+
+/// The type of a cast, converted the way declarations are. A function type would need a
+/// function typedef of its own, which cannot be added to the registry from an expression.
+#[allow(clippy::mutable_key_type)]
+unsafe fn map_cast_type(ty: CXType) -> Result<CExpr<'static>, ClangError> {
+    unsafe {
+        let mut function_typedefs = HashMap::new();
+        let mapped = map_ty(ty)?.to_registry_type("", &mut function_typedefs);
+        if !function_typedefs.is_empty() {
+            return Err(unsupported_type(ty));
+        }
+        Ok(CExpr::Type(Box::new(CTypeExpr { ty: mapped })))
+    }
+}
+
+/// Parse the tokens of a type name, such as the operand of `sizeof`, like panspace does. Its
+/// typedef names are told by the type references under `cursor`.
+unsafe fn map_type_name(cursor: CXCursor, tokens: &[String]) -> Result<CExpr<'static>, ClangError> {
+    unsafe {
+        let typedefs = get_children(cursor)
+            .into_iter()
+            .filter(|child| get_kind(*child) == CXCursor_TypeRef)
+            .map(|child| lossy_string(clang_getCursorSpelling(child)))
+            .collect::<Vec<_>>();
+        let is_type_name = |word: &str| is_standard_type_name(word) || typedefs.iter().any(|name| name == word);
+
+        // Only the operand of `sizeof` is parsed as a type name on its own
+        let source = format!("sizeof({})", tokens.join(" "));
+        match parse_expression_with_types(&source, &is_type_name) {
+            Ok(CExpr::TypeOperator(operator)) => Ok(operator.ty.into_owned()),
+            _ => Err(ClangError::malformed(format!("Unable to parse the type name '{}'", tokens.join(" ")))),
+        }
+    }
+}
+
 /// Get identifier from the display name of [cursor]
 unsafe fn get_identifier(cursor: CXCursor) -> Result<Identifier, ClangError> {
     unsafe {
@@ -318,10 +402,10 @@ unsafe fn get_identifier(cursor: CXCursor) -> Result<Identifier, ClangError> {
     }
 }
 
-unsafe fn get_suffix(cursor: CXCursor) -> &'static str {
+unsafe fn get_suffix(cursor: CXCursor) -> Result<&'static str, ClangError> {
     unsafe {
         let ty = clang_getCursorType(cursor);
-        match ty.kind {
+        Ok(match ty.kind {
             CXType_Int => "",
             CXType_UInt => "U",
             CXType_ULong => "UL",
@@ -331,7 +415,10 @@ unsafe fn get_suffix(cursor: CXCursor) -> &'static str {
             CXType_Float => "F",
             CXType_Double => "",
             CXType_LongDouble => "L",
-            _ => unreachable!(),
-        }
+            _ => return Err(ClangError::UnsupportedLiteral {
+                kind: lossy_string(clang_getTypeKindSpelling(ty.kind)),
+                cursor: CursorContext::new(cursor),
+            }),
+        })
     }
 }
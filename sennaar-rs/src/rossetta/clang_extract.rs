@@ -0,0 +1,787 @@
+#![allow(non_upper_case_globals)]
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::ptr::null_mut;
+
+use clang_sys::*;
+
+use crate::{Identifier, Internalize};
+use crate::cpl::{CExpr, CIntLiteralExpr, CUnaryExpr, CUnaryOp};
+use crate::panspace::{is_standard_type_name, lower_defines, HparseDefine, SkipReason};
+use crate::registry::*;
+use crate::rossetta::clang_config::{Filter, ParseConfig};
+use crate::rossetta::clang_expr::map_nodes;
+use crate::rossetta::clang_ty::{is_function_type, map_function_abi, map_ty};
+use crate::rossetta::clang_error::{lossy_string, CursorContext};
+use crate::rossetta::clang_utils::*;
+
+/// What to do with declarations that cannot be mapped.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ExtractMode {
+    /// Fail on the first one.
+    #[default]
+    Strict,
+    /// Leave them out of the registry, with a warning.
+    SkipUnsupported,
+}
+
+#[derive(Debug)]
+pub struct Extraction {
+    pub registry: Registry<'static>,
+    /// Declarations left out, macros that are not expressions, and the errors libclang reported.
+    pub warnings: Vec<ClangError>,
+}
+
+/// Parse `header` with libclang and collect its declarations into a registry named `name`.
+/// `config` gives the compiler arguments, and which declarations are collected; by default, those
+/// of the header itself.
+///
+/// Functions become commands, structs, unions and enums become the entities of the same kind,
+/// and typedefs become aliases, function typedefs, opaque typedefs or handles. The enumerators
+/// of an enum without a name become constants. Function-like macros become macros and
+/// object-like ones constants, see [`lower_defines`]; those that are not expressions are only
+/// warned about.
+///
+/// libclang recovers from errors in the header, which fail the extraction in strict mode and are
+/// warned about otherwise.
+pub fn extract_registry(
+    header: &str,
+    name: &str,
+    config: &ParseConfig,
+    mode: ExtractMode,
+) -> Result<Extraction, ClangError> {
+    let header = CString::new(header).map_err(ClangError::malformed)?;
+    let args = config
+        .args()
+        .into_iter()
+        .map(|arg| CString::new(arg).map_err(ClangError::malformed))
+        .collect::<Result<Vec<_>, _>>()?;
+    let arg_ptrs = args.iter().map(|arg| arg.as_ptr()).collect::<Vec<_>>();
+
+    unsafe {
+        let index = clang_createIndex(0, 0);
+        let unit = clang_parseTranslationUnit(
+            index,
+            header.as_ptr(),
+            arg_ptrs.as_ptr(),
+            arg_ptrs.len() as i32,
+            null_mut(),
+            0,
+            CXTranslationUnit_DetailedPreprocessingRecord,
+        );
+        if unit.is_null() {
+            clang_disposeIndex(index);
+            return Err(ClangError::Parse { header: header.to_string_lossy().into_owned() });
+        }
+
+        let result = extract_unit(unit, name, config, mode);
+
+        clang_disposeTranslationUnit(unit);
+        clang_disposeIndex(index);
+        result
+    }
+}
+
+unsafe fn extract_unit(
+    unit: CXTranslationUnit,
+    name: &str,
+    config: &ParseConfig,
+    mode: ExtractMode,
+) -> Result<Extraction, ClangError> {
+    unsafe {
+        let mut registry = Registry::new(name.to_string());
+        let mut warnings = Vec::new();
+        let mut defines = Vec::new();
+        let root = clang_getTranslationUnitCursor(unit);
+
+        for error in get_errors(unit) {
+            match mode {
+                ExtractMode::Strict => return Err(error),
+                ExtractMode::SkipUnsupported => warnings.push(error),
+            }
+        }
+
+        let children = get_children(root);
+        for (i, &cursor) in children.iter().enumerate() {
+            let main = clang_Location_isFromMainFile(clang_getCursorLocation(cursor)) != 0;
+            if !get_file(cursor).is_some_and(|file| config.includes_file(&file, main)) {
+                continue;
+            }
+            // Those without a name are named by their typedef, which is filtered instead
+            if is_unnamed(cursor).is_ok_and(|unnamed| !unnamed)
+                && !config.names.passes(&lossy_string(clang_getCursorSpelling(cursor)))
+            {
+                continue;
+            }
+            // `typedef struct Foo* Bar;` also shows `struct Foo` on its own, the typedef maps it
+            if clang_isCursorDefinition(cursor) == 0
+                && let Some(&next) = children.get(i + 1)
+                && get_offsets(next).0 <= get_offsets(cursor).0
+            {
+                continue;
+            }
+
+            if let Err(e) = map_declaration(unit, &mut registry, &mut defines, &config.names, cursor) {
+                match mode {
+                    ExtractMode::Strict => return Err(e.at(cursor)),
+                    ExtractMode::SkipUnsupported => warnings.push(e.at(cursor)),
+                }
+            }
+        }
+
+        // After the declarations, so that constants may refer to enumerators and typedefs
+        let typedefs = registry.aliases.keys().map(|name| name.original().to_string()).collect::<HashSet<_>>();
+        let type_names = |name: &str| is_standard_type_name(name) || typedefs.contains(name);
+        let (defines, cursors): (Vec<_>, Vec<_>) = defines.into_iter().unzip();
+        for skipped in lower_defines(registry.as_base_mut(), &defines, &type_names) {
+            if skipped.reason == SkipReason::EmptyDefinition {
+                continue;
+            }
+            let Some(i) = defines.iter().position(|define| define.name == skipped.name && define.line == skipped.line)
+            else {
+                continue;
+            };
+            warnings.push(ClangError::UnsupportedMacro {
+                reason: skipped.reason.to_string(),
+                cursor: CursorContext::new(cursors[i]),
+            });
+        }
+
+        Ok(Extraction { registry, warnings })
+    }
+}
+
+/// The errors libclang found while parsing `unit`.
+unsafe fn get_errors(unit: CXTranslationUnit) -> Vec<ClangError> {
+    unsafe {
+        let mut errors = Vec::new();
+        for i in 0..clang_getNumDiagnostics(unit) {
+            let diagnostic = clang_getDiagnostic(unit, i);
+            if clang_getDiagnosticSeverity(diagnostic) >= CXDiagnostic_Error {
+                let options = clang_defaultDiagnosticDisplayOptions();
+                let message = lossy_string(clang_formatDiagnostic(diagnostic, options));
+                errors.push(ClangError::Diagnostic { message });
+            }
+            clang_disposeDiagnostic(diagnostic);
+        }
+        errors
+    }
+}
+
+/// Map a top-level declaration into `registry`, or collect it into `defines` if it is a macro.
+/// `names` filters the constants of enums without a tag, which are not filtered as a whole.
+unsafe fn map_declaration(
+    unit: CXTranslationUnit,
+    registry: &mut Registry<'static>,
+    defines: &mut Vec<(HparseDefine, CXCursor)>,
+    names: &Filter,
+    cursor: CXCursor,
+) -> Result<(), ClangError> {
+    unsafe {
+        match get_kind(cursor) {
+            CXCursor_FunctionDecl => {
+                let command = map_command(registry, cursor)?;
+                registry.commands.entry(command.name.clone()).or_insert(command);
+            }
+            CXCursor_StructDecl | CXCursor_UnionDecl => map_record_decl(registry, cursor)?,
+            CXCursor_EnumDecl if clang_isCursorDefinition(cursor) != 0 && !is_unnamed(cursor)? => {
+                let enumeration = map_enum(cursor, get_name(cursor)?)?;
+                registry.enumerations.entry(enumeration.name.clone()).or_insert(enumeration);
+            }
+            // Unless named by a typedef, which maps it
+            CXCursor_EnumDecl if clang_isCursorDefinition(cursor) != 0 && record_name(cursor).is_err() => {
+                add_enum_constants(registry, cursor, names)?;
+            }
+            CXCursor_TypedefDecl => map_typedef(registry, cursor)?,
+            CXCursor_MacroDefinition => defines.push((map_macro(unit, cursor)?, cursor)),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Map a struct or union declaration: a named definition becomes a structure, and a forward
+/// declaration never completed in the unit an opaque typedef.
+unsafe fn map_record_decl(registry: &mut Registry<'static>, cursor: CXCursor) -> Result<(), ClangError> {
+    unsafe {
+        if is_unnamed(cursor)? {
+            // Named by the typedef declaring it, if any
+            return Ok(());
+        }
+        let name = get_name(cursor)?;
+        if clang_isCursorDefinition(cursor) != 0 {
+            let structure = map_record(registry, cursor, name)?;
+            add_record(registry, cursor, structure);
+        } else if clang_Cursor_isNull(clang_getCursorDefinition(cursor)) != 0 {
+            add_opaque(registry, cursor, name)?;
+        }
+        Ok(())
+    }
+}
+
+fn add_record(registry: &mut Registry<'static>, cursor: CXCursor, structure: Structure<'static>) {
+    let name = structure.name.clone();
+    if get_kind(cursor) == CXCursor_UnionDecl {
+        registry.unions.entry(name).or_insert(structure);
+    } else {
+        registry.structs.entry(name).or_insert(structure);
+    }
+}
+
+fn add_opaque(registry: &mut Registry<'static>, cursor: CXCursor, name: Identifier) -> Result<(), ClangError> {
+    let opaque = OpaqueTypedef {
+        name: name.clone(),
+        metadata: map_attributes(cursor)?,
+        doc: get_doc(cursor)?,
+        platform: None,
+        platform_variants: Vec::new(),
+    };
+    registry.opaque_typedefs.entry(name).or_insert(opaque);
+    Ok(())
+}
+
+/// Map the members of a record. Records and enums defined inside it are added to `registry`
+/// as well, those without a tag named by [`record_name`]; anonymous structs and unions and
+/// unnamed bitfields become members named `anon0`, `anon1`... marked `anonymous`.
+unsafe fn map_record(
+    registry: &mut Registry<'static>,
+    cursor: CXCursor,
+    name: Identifier,
+) -> Result<Structure<'static>, ClangError> {
+    unsafe {
+        let mut members = Vec::new();
+        let mut anonymous = 0;
+        for child in get_children(cursor) {
+            let mut metadata = map_attributes(child)?;
+            let member_name = if is_anonymous_member(child) {
+                metadata.insert("anonymous".to_string(), Metadata::None);
+                anonymous += 1;
+                format!("anon{}", anonymous - 1).interned()
+            } else {
+                get_name(child)?
+            };
+
+            let ty = match get_kind(child) {
+                CXCursor_StructDecl | CXCursor_UnionDecl if clang_isCursorDefinition(child) != 0 => {
+                    let nested_name = record_name(child)?;
+                    let structure = map_record(registry, child, nested_name.clone())?;
+                    add_record(registry, child, structure);
+                    if !metadata.contains_key("anonymous") {
+                        continue;
+                    }
+                    Type::IdentifierType(Box::new(IdentifierType { ident: nested_name }))
+                }
+                CXCursor_EnumDecl if clang_isCursorDefinition(child) != 0 => {
+                    // An enum without a tag nor a member of its type only declares constants
+                    match record_name(child) {
+                        Ok(enum_name) => {
+                            let enumeration = map_enum(child, enum_name)?;
+                            registry.enumerations.entry(enumeration.name.clone()).or_insert(enumeration);
+                        }
+                        Err(_) => add_enum_constants(registry, child, &Filter::default())?,
+                    }
+                    continue;
+                }
+                CXCursor_FieldDecl if member_name.original().is_empty() => {
+                    // The member of an anonymous struct or union, which is mapped above
+                    continue;
+                }
+                CXCursor_FieldDecl => {
+                    let hint = format!("{}_{}", name.original(), member_name.original());
+                    let mut ty = map_decl_type(registry, child, clang_getCursorType(child), &hint)?;
+                    if is_nonnull(clang_getCursorType(child)) {
+                        mark_nonnull(&mut ty, &mut metadata);
+                    }
+                    ty
+                }
+                _ => continue,
+            };
+
+            let bits = if clang_Cursor_isBitField(child) != 0 {
+                Some(clang_getFieldDeclBitWidth(child).max(0) as usize)
+            } else {
+                None
+            };
+            members.push(Member {
+                name: member_name,
+                metadata,
+                doc: get_doc(child)?,
+                platform: None,
+                ty,
+                bits,
+                init: None,
+                optional: false,
+                len: None,
+            });
+        }
+
+        Ok(Structure {
+            name,
+            metadata: map_attributes(cursor)?,
+            doc: get_doc(cursor)?,
+            platform: None,
+            members,
+            platform_variants: Vec::new(),
+        })
+    }
+}
+
+unsafe fn map_enum(cursor: CXCursor, name: Identifier) -> Result<Enumeration<'static>, ClangError> {
+    unsafe {
+        Ok(Enumeration {
+            name,
+            metadata: map_attributes(cursor)?,
+            doc: get_doc(cursor)?,
+            platform: None,
+            variants: map_enumerators(cursor)?,
+            platform_variants: Vec::new(),
+        })
+    }
+}
+
+/// Add the enumerators of an enum without a tag, nor a typedef or member naming it, as `int`
+/// constants, which is all such an enum declares. Those `names` does not pass are left out.
+unsafe fn add_enum_constants(
+    registry: &mut Registry<'static>,
+    cursor: CXCursor,
+    names: &Filter,
+) -> Result<(), ClangError> {
+    unsafe {
+        for variant in map_enumerators(cursor)? {
+            if !names.passes(variant.name.original()) {
+                continue;
+            }
+            let constant = Constant {
+                name: variant.name.clone(),
+                metadata: variant.metadata,
+                doc: variant.doc,
+                platform: None,
+                ty: Type::IdentifierType(Box::new(IdentifierType { ident: "int".interned() })),
+                expr: variant.value,
+                platform_variants: Vec::new(),
+            };
+            registry.constants.entry(variant.name).or_insert(constant);
+        }
+        Ok(())
+    }
+}
+
+unsafe fn map_enumerators(cursor: CXCursor) -> Result<Vec<EnumVariant<'static>>, ClangError> {
+    unsafe {
+        let mut variants = Vec::new();
+        for constant in get_children(cursor) {
+            if get_kind(constant) != CXCursor_EnumConstantDecl {
+                continue;
+            }
+            // The initializer as written, so that macros and other enumerators are referred to
+            // by name, or the value when there is none or it cannot be mapped
+            let init = get_children(constant).into_iter().find(|child| is_expression(*child));
+            let value = match init.map(|init| map_nodes(init)) {
+                Some(Ok(expr)) => expr,
+                _ => enum_constant_value(constant),
+            };
+            variants.push(EnumVariant {
+                name: get_name(constant)?,
+                metadata: map_attributes(constant)?,
+                doc: get_doc(constant)?,
+                platform: None,
+                value,
+            });
+        }
+        Ok(variants)
+    }
+}
+
+fn enum_constant_value(constant: CXCursor) -> CExpr<'static> {
+    let value = unsafe { clang_getEnumConstantDeclValue(constant) };
+    let literal = CExpr::IntLiteral(Box::new(CIntLiteralExpr::new(Cow::Owned(value.unsigned_abs().to_string()))));
+    if value < 0 {
+        CExpr::Unary(Box::new(CUnaryExpr { expr: literal, op: CUnaryOp::Minus }))
+    } else {
+        literal
+    }
+}
+
+/// Map the type `ty` of the declaration at `cursor`, naming function prototypes in it after
+/// `hint`, see [`CType::to_registry_type`]. Array lengths are taken from the size expressions
+/// under the declaration, so that they read `MAX_NAME_SIZE` rather than its value.
+///
+/// [`CType::to_registry_type`]: crate::rossetta::clang_ty::CType::to_registry_type
+unsafe fn map_decl_type(
+    registry: &mut Registry<'static>,
+    cursor: CXCursor,
+    ty: CXType,
+    hint: &str,
+) -> Result<Type<'static>, ClangError> {
+    unsafe {
+        let cty = map_ty(ty).map_err(|e| e.at(cursor))?;
+        let mut mapped = cty.to_registry_type(hint, &mut registry.function_typedefs);
+
+        let mut lengths = Vec::new();
+        let mut current = &mut mapped;
+        while let Type::ArrayType(array) = current {
+            let ArrayType { element, length } = &mut **array;
+            // Flexible array members have no size to take
+            if length.is_some() {
+                lengths.push(length);
+            }
+            current = element;
+        }
+
+        // libclang visits the sizes from the innermost array out
+        let sizes = get_children(cursor).into_iter().filter(|child| is_expression(*child));
+        for (length, size) in lengths.into_iter().rev().zip(sizes) {
+            if let Ok(expr) = map_nodes(size) {
+                *length = Some(expr);
+            }
+        }
+        Ok(mapped)
+    }
+}
+
+/// Map a typedef the way panspace lowers one: function types make function typedefs, and
+/// records never defined make opaque typedefs, or handles when pointed to.
+unsafe fn map_typedef(registry: &mut Registry<'static>, cursor: CXCursor) -> Result<(), ClangError> {
+    unsafe {
+        let name = get_name(cursor)?;
+        let underlying = named_type(clang_getTypedefDeclUnderlyingType(cursor));
+        let pointee = named_type(clang_getPointeeType(underlying));
+
+        if is_function_type(underlying) || (underlying.kind == CXType_Pointer && is_function_type(pointee)) {
+            let is_pointer = underlying.kind == CXType_Pointer;
+            let function = if is_pointer { pointee } else { underlying };
+            let typedef = map_function_typedef(registry, cursor, function, name, is_pointer)?;
+            registry.function_typedefs.entry(typedef.name.clone()).or_insert(typedef);
+            return Ok(());
+        }
+
+        if matches!(underlying.kind, CXType_Record | CXType_Enum) {
+            let declaration = clang_getTypeDeclaration(underlying);
+            let definition = clang_getCursorDefinition(declaration);
+            if is_unnamed(declaration)? {
+                // `typedef struct { ... } Name;` names the record after the typedef, and the
+                // comment before it documents the record
+                let doc = get_doc(cursor)?;
+                if underlying.kind == CXType_Enum {
+                    let mut enumeration = map_enum(definition, name)?;
+                    if enumeration.doc.is_empty() {
+                        enumeration.doc = doc;
+                    }
+                    registry.enumerations.entry(enumeration.name.clone()).or_insert(enumeration);
+                } else {
+                    let mut structure = map_record(registry, definition, name)?;
+                    if structure.doc.is_empty() {
+                        structure.doc = doc;
+                    }
+                    add_record(registry, definition, structure);
+                }
+                return Ok(());
+            }
+            if clang_Cursor_isNull(definition) != 0 {
+                return add_opaque(registry, cursor, name);
+            }
+            if get_name(declaration)? == name {
+                // The typedef names a record or enum declared under the same tag
+                return Ok(());
+            }
+        }
+
+        if underlying.kind == CXType_Pointer
+            && pointee.kind == CXType_Record
+            && clang_Cursor_isNull(clang_getCursorDefinition(clang_getTypeDeclaration(pointee))) != 0
+        {
+            let handle = OpaqueHandleTypedef {
+                name: name.clone(),
+                metadata: map_attributes(cursor)?,
+                doc: get_doc(cursor)?,
+                platform: None,
+                platform_variants: Vec::new(),
+            };
+            registry.opaque_handle_typedefs.entry(name).or_insert(handle);
+            return Ok(());
+        }
+
+        let typedef = Typedef {
+            name: name.clone(),
+            metadata: map_attributes(cursor)?,
+            doc: get_doc(cursor)?,
+            platform: None,
+            target: map_decl_type(
+                registry,
+                cursor,
+                clang_getTypedefDeclUnderlyingType(cursor),
+                &format!("{}_target", name.original()),
+            )?,
+            platform_variants: Vec::new(),
+        };
+        registry.aliases.entry(name).or_insert(typedef);
+        Ok(())
+    }
+}
+
+unsafe fn map_function_typedef(
+    registry: &mut Registry<'static>,
+    cursor: CXCursor,
+    function: CXType,
+    name: Identifier,
+    is_pointer: bool,
+) -> Result<FunctionTypedef<'static>, ClangError> {
+    unsafe {
+        // Parameter names are only found on the declarations under the typedef
+        let param_decls = get_children(cursor)
+            .into_iter()
+            .filter(|child| get_kind(*child) == CXCursor_ParmDecl)
+            .collect::<Vec<_>>();
+        let mut params = Vec::new();
+        for (i, ty) in get_parameters(function).map_err(|e| e.at(cursor))?.into_iter().enumerate() {
+            let decl = param_decls.get(i);
+            let mut param_name = match decl {
+                Some(decl) => from_CXString(clang_getCursorSpelling(*decl))?,
+                None => String::new(),
+            };
+            if param_name.is_empty() {
+                param_name = format!("param{}", i);
+            }
+            let hint = format!("{}_{}", name.original(), param_name);
+            let mut metadata = match decl {
+                Some(decl) => map_attributes(*decl)?,
+                None => HashMap::new(),
+            };
+            let nonnull = is_nonnull(ty);
+            let mut ty = map_ty(ty)?.to_registry_type(&hint, &mut registry.function_typedefs);
+            if nonnull {
+                mark_nonnull(&mut ty, &mut metadata);
+            }
+            params.push(Param {
+                name: param_name.interned(),
+                metadata,
+                doc: match decl {
+                    Some(decl) => get_doc(*decl)?,
+                    None => Doc::default(),
+                },
+                platform: None,
+                optional: is_nullable(&ty),
+                ty,
+                len: None,
+            });
+        }
+
+        let result = map_ty(clang_getResultType(function))?
+            .to_registry_type(&format!("{}_result", name.original()), &mut registry.function_typedefs);
+        let abi = map_function_abi(function)?;
+        Ok(FunctionTypedef {
+            name,
+            metadata: map_attributes(cursor)?,
+            doc: get_doc(cursor)?,
+            platform: None,
+            params,
+            variadic: abi.variadic,
+            calling_convention: abi.calling_convention,
+            result,
+            is_pointer,
+            is_native_api: false,
+            platform_variants: Vec::new(),
+        })
+    }
+}
+
+/// `ty` without the `struct`, `enum` or qualified name sugar around it.
+fn named_type(mut ty: CXType) -> CXType {
+    unsafe {
+        while ty.kind == CXType_Elaborated {
+            ty = clang_Type_getNamedType(ty);
+        }
+        ty
+    }
+}
+
+fn is_nullable(ty: &Type<'_>) -> bool {
+    matches!(ty, Type::PointerType(pointer) if pointer.nullable)
+}
+
+/// Whether `ty` is marked `_Nonnull`.
+fn is_nonnull(ty: CXType) -> bool {
+    unsafe { clang_Type_getNullability(ty) == CXTypeNullability_NonNull }
+}
+
+/// Make the outermost pointer of `ty` never null, and say so with the `nonnull` metadata, so that
+/// it is told apart from a pointer that says nothing about being null.
+fn mark_nonnull(ty: &mut Type<'_>, metadata: &mut HashMap<String, Metadata>) {
+    if let Type::PointerType(pointer) = ty {
+        pointer.nullable = false;
+        metadata.insert("nonnull".to_string(), Metadata::None);
+    }
+}
+
+/// Metadata from the attributes of the declaration at `cursor`: `deprecated` with its message if
+/// it has one, the string of each `annotate` as a key, and any other attribute under its name
+/// with its arguments as a string value. `nonnull` is only put on the parameters it names.
+fn map_attributes(cursor: CXCursor) -> Result<HashMap<String, Metadata>, ClangError> {
+    unsafe {
+        let mut metadata = HashMap::new();
+        if clang_getCursorAvailability(cursor) == CXAvailability_Deprecated {
+            let mut message = std::mem::zeroed::<CXString>();
+            clang_getCursorPlatformAvailability(cursor, null_mut(), &mut message, null_mut(), null_mut(), null_mut(), 0);
+            let message = lossy_string(message);
+            let value = if message.is_empty() { Metadata::None } else { Metadata::String { value: message } };
+            metadata.insert("deprecated".to_string(), value);
+        }
+
+        for child in get_children(cursor) {
+            if get_kind(child) == CXCursor_AnnotateAttr {
+                metadata.insert(from_CXString(clang_getCursorSpelling(child))?, Metadata::None);
+            }
+        }
+        for (attribute, args) in get_attributes(cursor)? {
+            if matches!(attribute.as_str(), "deprecated" | "nonnull") {
+                continue;
+            }
+            let value = if args.is_empty() { Metadata::None } else { Metadata::String { value: args.join(", ") } };
+            metadata.insert(attribute, value);
+        }
+        Ok(metadata)
+    }
+}
+
+/// Names and arguments of the attributes written on the declaration at `cursor`, such as
+/// `("nonnull", ["1", "2"])` for `__attribute__((nonnull(1, 2)))`. Implicit attributes have no
+/// spelling and are left out, and so is `annotate`, whose string libclang gives directly.
+fn get_attributes(cursor: CXCursor) -> Result<Vec<(String, Vec<String>)>, ClangError> {
+    unsafe {
+        let unit = clang_Cursor_getTranslationUnit(cursor);
+        let mut attributes = Vec::new();
+        for child in get_children(cursor) {
+            let kind = get_kind(child);
+            if clang_isAttribute(kind) == 0 || kind == CXCursor_AnnotateAttr {
+                continue;
+            }
+            let tokens = get_tokens(unit, child)?;
+            let Some((name, rest)) = tokens.split_first() else {
+                continue;
+            };
+
+            // The arguments in parentheses after the name, split at top-level commas
+            let mut args = Vec::new();
+            if let [open, inner @ .., close] = rest
+                && open == "("
+                && close == ")"
+            {
+                let mut depth = 0;
+                let mut arg = Vec::new();
+                for token in inner {
+                    match token.as_str() {
+                        "(" | "[" | "{" => depth += 1,
+                        ")" | "]" | "}" => depth -= 1,
+                        "," if depth == 0 => {
+                            args.push(arg.join(" "));
+                            arg.clear();
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    arg.push(token.as_str());
+                }
+                args.push(arg.join(" "));
+            }
+            // `__warn_unused_result__` is `warn_unused_result`
+            attributes.push((name.trim_matches('_').to_string(), args));
+        }
+        Ok(attributes)
+    }
+}
+
+fn get_name(cursor: CXCursor) -> Result<Identifier, ClangError> {
+    unsafe { Ok(from_CXString(clang_getCursorSpelling(cursor))?.interned()) }
+}
+
+unsafe fn map_command(registry: &mut Registry<'static>, cursor: CXCursor) -> Result<Command<'static>, ClangError> {
+    unsafe {
+        let name = from_CXString(clang_getCursorSpelling(cursor))?;
+        let result = map_ty(clang_getCursorResultType(cursor))?
+            .to_registry_type(&format!("{}_result", name), &mut registry.function_typedefs);
+
+        // `nonnull` on the function lists the parameters never null, or means all of them
+        let nonnull = get_attributes(cursor)?
+            .into_iter()
+            .filter_map(|(attribute, args)| (attribute == "nonnull").then_some(args))
+            .collect::<Vec<_>>();
+
+        let argc = clang_Cursor_getNumArguments(cursor).max(0) as u32;
+        let mut params = Vec::new();
+        for i in 0..argc {
+            let arg = clang_Cursor_getArgument(cursor, i);
+            let mut param_name = from_CXString(clang_getCursorSpelling(arg))?;
+            if param_name.is_empty() {
+                param_name = format!("param{}", i);
+            }
+
+            let hint = format!("{}_{}", name, param_name);
+            let mut ty = map_decl_type(registry, arg, clang_getCursorType(arg), &hint)?;
+            let mut metadata = map_attributes(arg)?;
+            if is_nonnull(clang_getCursorType(arg))
+                || get_attributes(arg)?.iter().any(|(attribute, _)| attribute == "nonnull")
+                || nonnull.iter().any(|args| args.is_empty() || args.contains(&(i + 1).to_string()))
+            {
+                mark_nonnull(&mut ty, &mut metadata);
+            }
+            params.push(Param {
+                name: param_name.interned(),
+                metadata,
+                doc: get_doc(arg)?,
+                platform: None,
+                optional: is_nullable(&ty),
+                ty,
+                len: None,
+            });
+        }
+
+        let abi = map_function_abi(clang_getCursorType(cursor))?;
+        Ok(Command {
+            name: name.interned(),
+            metadata: map_attributes(cursor)?,
+            doc: get_doc(cursor)?,
+            platform: None,
+            params,
+            variadic: abi.variadic,
+            calling_convention: abi.calling_convention,
+            result,
+            success_codes: Vec::new(),
+            error_codes: Vec::new(),
+            alias_to: None,
+            platform_variants: Vec::new(),
+        })
+    }
+}
+
+/// Read a macro definition for [`lower_defines`], with the parameters of a function-like one.
+unsafe fn map_macro(unit: CXTranslationUnit, cursor: CXCursor) -> Result<HparseDefine, ClangError> {
+    unsafe {
+        // NAME ( params ) body, or NAME body
+        let tokens = get_tokens(unit, cursor)?;
+        let (params, body) = if clang_Cursor_isMacroFunctionLike(cursor) != 0 {
+            let Some(close) = tokens.iter().position(|token| token == ")") else {
+                return Err(ClangError::UnsupportedMacro {
+                    reason: "unterminated parameter list".to_string(),
+                    cursor: CursorContext::new(cursor),
+                });
+            };
+            // A named variadic parameter `args...` is two tokens
+            let params = tokens[2..close].split(|token| token == ",").map(|param| param.concat());
+            (Some(params.filter(|param| !param.is_empty()).collect()), &tokens[close + 1..])
+        } else {
+            (None, tokens.get(1..).unwrap_or_default())
+        };
+
+        Ok(HparseDefine {
+            line: get_line(cursor),
+            name: from_CXString(clang_getCursorSpelling(cursor))?,
+            params,
+            body: body.join(" "),
+            platform: None,
+            doc: get_doc(cursor)?.to_lines(),
+            is_include_guard: false,
+        })
+    }
+}
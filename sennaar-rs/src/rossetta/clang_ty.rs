@@ -1,10 +1,15 @@
 #![allow(non_upper_case_globals)]
 
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::Display;
 
 use clang_sys::*;
 
 use crate::{Identifier, Internalize};
+use crate::cpl::{CExpr, CIntLiteralExpr, RawQualifiers};
+use crate::registry::{ArrayType, CallingConvention, Doc, FunctionTypedef, IdentifierType, Param, PointerType, Type};
+use crate::rossetta::clang_error::lossy_string;
 use crate::rossetta::clang_utils::*;
 
 #[derive(Debug)]
@@ -14,15 +19,28 @@ pub enum CSign {
     Unsigned,
 }
 
+/// How a function is called, beside its parameter and result types.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CFunctionAbi {
+    pub variadic: bool,
+    /// `None` for the default C calling convention of the target.
+    pub calling_convention: Option<CallingConvention>,
+}
+
 #[derive(Debug)]
 pub enum CType {
     Primitive { signed: CSign, ident: Identifier },
-    Array(Box<CType>, u64),
+    /// An array, without a size for flexible array members.
+    Array(Box<CType>, Option<u64>),
     Pointer(Box<CType>),
-    FunProto(Box<CType>, Vec<CType>),
+    FunProto(Box<CType>, Vec<CType>, CFunctionAbi),
     Struct(Identifier),
+    Union(Identifier),
     Enum(Identifier),
     Typedef(Identifier),
+    Qualified(Box<CType>, RawQualifiers),
+    /// A pointer marked `_Nullable`.
+    Nullable(Box<CType>),
 }
 
 impl CType {
@@ -40,10 +58,33 @@ impl CType {
         }
     }
 
+    /// `ctype` with `qualifiers` added to its own.
+    pub fn qualified(ctype: CType, qualifiers: RawQualifiers) -> CType {
+        match ctype {
+            CType::Qualified(ctype, own) => CType::Qualified(ctype, RawQualifiers {
+                is_const: own.is_const || qualifiers.is_const,
+                is_volatile: own.is_volatile || qualifiers.is_volatile,
+                is_restrict: own.is_restrict || qualifiers.is_restrict,
+                is_atomic: own.is_atomic || qualifiers.is_atomic,
+            }),
+            _ => CType::Qualified(Box::new(ctype), qualifiers),
+        }
+    }
+
+    /// Qualifiers of this type, through nullability.
+    pub fn qualifiers(&self) -> RawQualifiers {
+        match self {
+            CType::Qualified(_, qualifiers) => *qualifiers,
+            CType::Nullable(ctype) => ctype.qualifiers(),
+            _ => RawQualifiers::default(),
+        }
+    }
+
     pub fn fmt_fun(
         f: &mut std::fmt::Formatter<'_>,
         ret: &Box<CType>,
         params: &Vec<CType>,
+        abi: &CFunctionAbi,
         name: Option<Identifier>,
     ) -> std::fmt::Result {
         write!(f, "{} ", ret)?;
@@ -56,6 +97,7 @@ impl CType {
         let params_str = params
             .iter()
             .map(|p| format!("{}", p))
+            .chain(abi.variadic.then(|| "...".to_string()))
             .collect::<Vec<String>>();
         let param_comma_seq = params_str.join(", ");
         write!(f, "{}", param_comma_seq)?;
@@ -66,6 +108,114 @@ impl CType {
     }
 }
 
+impl CType {
+    /// Convert into the registry type model, the way panspace lowers declarations: records and
+    /// enums are referred to by their tag, and qualifiers are only kept on pointers, both those
+    /// of what they point to and their own. `_Nullable` makes the pointer nullable.
+    ///
+    /// Function prototypes have no registry type, so each one becomes a function typedef named
+    /// `name` in `function_typedefs` and is referred to by that name. Prototypes in its
+    /// parameters and result are named after it in turn, like `name_param0`.
+    #[allow(clippy::mutable_key_type)]
+    pub fn to_registry_type(
+        &self,
+        name: &str,
+        function_typedefs: &mut HashMap<Identifier, FunctionTypedef<'static>>,
+    ) -> Type<'static> {
+        let ident = |ident: Identifier| Type::IdentifierType(Box::new(IdentifierType { ident }));
+        match self {
+            CType::Primitive { .. } => ident(self.to_string().interned()),
+            CType::Struct(name) | CType::Union(name) | CType::Enum(name) | CType::Typedef(name) => ident(name.clone()),
+            CType::Qualified(ctype, qualifiers) => {
+                let mut ty = ctype.to_registry_type(name, function_typedefs);
+                if let Type::PointerType(pointer) = &mut ty {
+                    pointer.is_restrict = qualifiers.is_restrict;
+                    pointer.is_pointer_const = qualifiers.is_const;
+                    pointer.is_pointer_volatile = qualifiers.is_volatile;
+                    pointer.is_pointer_atomic = qualifiers.is_atomic;
+                }
+                ty
+            }
+            CType::Nullable(ctype) => {
+                let mut ty = ctype.to_registry_type(name, function_typedefs);
+                if let Type::PointerType(pointer) = &mut ty {
+                    pointer.nullable = true;
+                }
+                ty
+            }
+            CType::Array(element, size) => Type::ArrayType(Box::new(ArrayType {
+                element: element.to_registry_type(name, function_typedefs),
+                length: size.map(|size| CExpr::IntLiteral(Box::new(CIntLiteralExpr::new(Cow::Owned(size.to_string()))))),
+            })),
+            CType::FunProto(result, params, abi) => {
+                ident(add_function_typedef(name, result, params, abi, false, function_typedefs))
+            }
+            CType::Pointer(pointee) => match &**pointee {
+                CType::FunProto(result, params, abi) => {
+                    ident(add_function_typedef(name, result, params, abi, true, function_typedefs))
+                }
+                _ => {
+                    let qualifiers = pointee.qualifiers();
+                    Type::PointerType(Box::new(PointerType {
+                        pointee: pointee.to_registry_type(name, function_typedefs).into_pointee(),
+                        is_const: qualifiers.is_const,
+                        is_volatile: qualifiers.is_volatile,
+                        is_atomic: qualifiers.is_atomic,
+                        is_restrict: false,
+                        is_pointer_const: false,
+                        is_pointer_volatile: false,
+                        is_pointer_atomic: false,
+                        pointer_to_one: false,
+                        nullable: false,
+                    }))
+                }
+            },
+        }
+    }
+}
+
+#[allow(clippy::mutable_key_type)]
+fn add_function_typedef(
+    name: &str,
+    result: &CType,
+    params: &[CType],
+    abi: &CFunctionAbi,
+    is_pointer: bool,
+    function_typedefs: &mut HashMap<Identifier, FunctionTypedef<'static>>,
+) -> Identifier {
+    let params = params
+        .iter()
+        .enumerate()
+        .map(|(i, param)| {
+            let param_name = format!("param{}", i);
+            let ty = param.to_registry_type(&format!("{}_{}", name, param_name), function_typedefs);
+            Param {
+                optional: matches!(&ty, Type::PointerType(pointer) if pointer.nullable),
+                ty,
+                name: param_name.interned(),
+                metadata: HashMap::new(),
+                doc: Doc::default(),
+                platform: None,
+                len: None,
+            }
+        })
+        .collect();
+    let typedef = FunctionTypedef {
+        name: name.interned(),
+        metadata: HashMap::new(),
+        doc: Doc::default(),
+        platform: None,
+        params,
+        variadic: abi.variadic,
+        calling_convention: abi.calling_convention,
+        result: result.to_registry_type(&format!("{}_result", name), function_typedefs),
+        is_pointer,
+        is_native_api: false,
+        platform_variants: Vec::new(),
+    };
+    function_typedefs.entry(typedef.name.clone()).or_insert(typedef).name.clone()
+}
+
 impl Display for CType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self {
@@ -74,21 +224,39 @@ impl Display for CType {
                 CSign::ExplicitSigned => write!(f, "signed {}", ident),
                 CSign::Unsigned => write!(f, "unsigned {}", ident),
             },
-            CType::Array(ctype, size) => write!(f, "{}[{}]", ctype, size),
+            CType::Array(ctype, Some(size)) => write!(f, "{}[{}]", ctype, size),
+            CType::Array(ctype, None) => write!(f, "{}[]", ctype),
             CType::Pointer(ctype) => match &*(*ctype) {
-                CType::FunProto(ret, params) => CType::fmt_fun(f, ret, params, Some("".interned())),
+                CType::FunProto(ret, params, abi) => CType::fmt_fun(f, ret, params, abi, Some("".interned())),
                 _ => write!(f, "{}*", ctype),
             },
-            CType::FunProto(ret, params) => CType::fmt_fun(f, ret, params, None),
+            CType::FunProto(ret, params, abi) => CType::fmt_fun(f, ret, params, abi, None),
             CType::Typedef(ident) => write!(f, "{}", ident),
             CType::Struct(ident) => write!(f, "struct {}", ident),
+            CType::Union(ident) => write!(f, "union {}", ident),
             CType::Enum(ident) => write!(f, "enum {}", ident),
+            CType::Qualified(ctype, qualifiers) => match &**ctype {
+                CType::Pointer(_) => write!(f, "{} {}", ctype, qualifiers.spelling().join(" ")),
+                _ => write!(f, "{} {}", qualifiers.spelling().join(" "), ctype),
+            },
+            CType::Nullable(ctype) => write!(f, "{} _Nullable", ctype),
         }
     }
 }
 
 pub unsafe fn map_ty(ty: CXType) -> Result<CType, ClangError> {
     unsafe {
+        let qualifiers = RawQualifiers {
+            is_const: clang_isConstQualifiedType(ty) != 0,
+            is_volatile: clang_isVolatileQualifiedType(ty) != 0,
+            is_restrict: clang_isRestrictQualifiedType(ty) != 0,
+            is_atomic: false,
+        };
+        if qualifiers != RawQualifiers::default() {
+            let unqualified = map_ty(clang_getUnqualifiedType(ty))?;
+            return Ok(CType::qualified(unqualified, qualifiers));
+        }
+
         if let Some(prime) = try_map_primitive(ty) {
             return Ok(prime);
         }
@@ -100,38 +268,41 @@ pub unsafe fn map_ty(ty: CXType) -> Result<CType, ClangError> {
                 CType::Pointer(Box::new(mapped))
             }
 
-            // function with parameters
-            CXType_FunctionProto => {
-                let result = clang_getResultType(ty);
-                let params = get_parameters(ty);
-
-                let mapped_result = map_ty(result)?;
-                let mapped_params = params
-                    .into_iter()
-                    .map(|p| map_ty(p))
-                    .collect::<Result<Vec<CType>, String>>()?;
-
-                CType::FunProto(Box::new(mapped_result), mapped_params)
-            }
-
-            // function with no parameters
-            CXType_FunctionNoProto => {
-                let result = clang_getResultType(ty);
-                let mapped_result = map_ty(result)?;
-
-                CType::FunProto(Box::new(mapped_result), Vec::new())
+            CXType_FunctionProto | CXType_FunctionNoProto => map_function(ty)?,
+
+            // calling convention attributes sit on function types, and are read off `ty` itself
+            CXType_Attributed if is_function_type(ty) => map_function(ty)?,
+            CXType_Attributed => {
+                let modified = map_ty(clang_Type_getModifiedType(ty))?;
+                match clang_Type_getNullability(ty) {
+                    CXTypeNullability_Nullable | CXTypeNullability_NullableResult => {
+                        CType::Nullable(Box::new(modified))
+                    }
+                    _ => modified,
+                }
             }
 
             CXType_ConstantArray => {
                 let element_ty = clang_getArrayElementType(ty);
                 let size = clang_getArraySize(ty);
-                if size == -1 {
-                    unreachable!()
+                if size < 0 {
+                    return Err(ClangError::malformed("Constant array without a size"));
                 }
 
                 let mapped_element_ty = map_ty(element_ty)?;
 
-                CType::Array(Box::new(mapped_element_ty), size as u64)
+                CType::Array(Box::new(mapped_element_ty), Some(size as u64))
+            }
+
+            // flexible array member
+            CXType_IncompleteArray => {
+                let element_ty = clang_getArrayElementType(ty);
+                CType::Array(Box::new(map_ty(element_ty)?), None)
+            }
+
+            CXType_Atomic => {
+                let value = map_ty(clang_Type_getValueType(ty))?;
+                CType::qualified(value, RawQualifiers { is_atomic: true, ..RawQualifiers::default() })
             }
 
             // struct Foo/enum Bar/typedef things
@@ -146,50 +317,95 @@ pub unsafe fn map_ty(ty: CXType) -> Result<CType, ClangError> {
                 CType::Typedef(name.interned())
             }
 
-            CXType_Record => {
-                // dont think this works
-                // FIXME: doesn't work, removing leading 'struct '
-                let raw_name = clang_getTypeSpelling(ty);
-                let name_with_struct = from_CXString(raw_name)?;
-                if let Some(name) = name_with_struct.strip_prefix("struct ") {
-                    CType::Struct(name.interned())
-                } else {
-                    unreachable!();
-                }
-            }
-
-            CXType_Enum => {
-                let raw_name = clang_getTypeSpelling(ty);
-                let name_with_enum = from_CXString(raw_name)?;
-                if let Some(name) = name_with_enum.strip_prefix("enum ") {
-                    CType::Enum(name.interned())
-                } else {
-                    unreachable!();
+            CXType_Record | CXType_Enum => {
+                // Named by the declaration, as the spelling may be qualified or `(unnamed ...)`
+                let declaration = clang_getTypeDeclaration(ty);
+                let name = record_name(declaration)?;
+                match clang_getCursorKind(declaration) {
+                    CXCursor_StructDecl => CType::Struct(name),
+                    CXCursor_UnionDecl => CType::Union(name),
+                    CXCursor_EnumDecl => CType::Enum(name),
+                    _ => return Err(unsupported_type(ty)),
                 }
             }
 
-            _ => {
-                let raw_type_display = clang_getTypeSpelling(ty);
-                let raw_kind_display = clang_getTypeKindSpelling(ty.kind);
-                let type_display = from_CXString(raw_type_display)?;
-                let kind_display = from_CXString(raw_kind_display)?;
-                todo!(
-                    "Unhandled type '{}' with kind '{}'",
-                    type_display,
-                    kind_display
-                );
-            }
+            _ => return Err(unsupported_type(ty)),
         };
 
         Ok(cty)
     }
 }
 
+unsafe fn map_function(ty: CXType) -> Result<CType, ClangError> {
+    unsafe {
+        let result = clang_getResultType(ty);
+        let mapped_result = map_ty(result)?;
+
+        // function with no parameters has no prototype either
+        let mapped_params = if clang_getNumArgTypes(ty) < 0 {
+            Vec::new()
+        } else {
+            get_parameters(ty)?
+                .into_iter()
+                .map(|p| map_ty(p))
+                .collect::<Result<Vec<CType>, ClangError>>()?
+        };
+
+        Ok(CType::FunProto(Box::new(mapped_result), mapped_params, map_function_abi(ty)?))
+    }
+}
+
+/// Whether `ty` is a function type, possibly under attributes.
+pub fn is_function_type(mut ty: CXType) -> bool {
+    unsafe {
+        while ty.kind == CXType_Attributed {
+            ty = clang_Type_getModifiedType(ty);
+        }
+    }
+    matches!(ty.kind, CXType_FunctionProto | CXType_FunctionNoProto)
+}
+
+/// Variadicity and calling convention of the function type `ty`. Conventions without a
+/// [`CallingConvention`] are unsupported.
+pub fn map_function_abi(ty: CXType) -> Result<CFunctionAbi, ClangError> {
+    unsafe {
+        let calling_convention = match clang_getFunctionTypeCallingConv(ty) {
+            CXCallingConv_Default | CXCallingConv_C => None,
+            CXCallingConv_X86StdCall => Some(CallingConvention::StdCall),
+            CXCallingConv_X86FastCall => Some(CallingConvention::FastCall),
+            CXCallingConv_X86ThisCall => Some(CallingConvention::ThisCall),
+            CXCallingConv_X86VectorCall => Some(CallingConvention::VectorCall),
+            CXCallingConv_X86RegCall => Some(CallingConvention::RegCall),
+            CXCallingConv_X86Pascal => Some(CallingConvention::Pascal),
+            CXCallingConv_X86_64SysV => Some(CallingConvention::SysV),
+            CXCallingConv_X86_64Win64 => Some(CallingConvention::Win64),
+            CXCallingConv_AAPCS => Some(CallingConvention::Aapcs),
+            CXCallingConv_AAPCS_VFP => Some(CallingConvention::AapcsVfp),
+            CXCallingConv_Swift => Some(CallingConvention::Swift),
+            _ => return Err(unsupported_type(ty)),
+        };
+        Ok(CFunctionAbi {
+            variadic: clang_isFunctionTypeVariadic(ty) != 0,
+            calling_convention,
+        })
+    }
+}
+
+pub(crate) fn unsupported_type(ty: CXType) -> ClangError {
+    unsafe {
+        ClangError::UnsupportedType {
+            spelling: lossy_string(clang_getTypeSpelling(ty)),
+            kind: lossy_string(clang_getTypeKindSpelling(ty.kind)),
+            cursor: None,
+        }
+    }
+}
+
 fn try_map_primitive(ty: CXType) -> Option<CType> {
     let ident = match ty.kind {
         CXType_Void => "void",
-        CXType_Bool => "bool", // ??
-        CXType_UChar | CXType_Char_S | CXType_SChar => "char",
+        CXType_Bool => "_Bool",
+        CXType_UChar | CXType_Char_S | CXType_Char_U | CXType_SChar => "char",
         CXType_UShort | CXType_Short => "short",
         CXType_UInt | CXType_Int => "int",
         CXType_ULong | CXType_Long => "long",
@@ -1,8 +1,16 @@
+#![allow(non_upper_case_globals)]
+
 use std::ffi::{CStr, c_void};
+use std::ptr::null_mut;
 
 use clang_sys::*;
 
-pub type ClangError = String;
+use crate::{Identifier, Internalize};
+use crate::panspace::parse_doc_comment;
+use crate::registry::Doc;
+use crate::rossetta::clang_error::CursorContext;
+
+pub use crate::rossetta::clang_error::ClangError;
 
 /// convert and consume
 #[allow(non_snake_case)]
@@ -11,7 +19,7 @@ pub unsafe fn from_CXString(s: CXString) -> Result<String, ClangError> {
         let raw_cs = clang_getCString(s);
         let owned = CStr::from_ptr(raw_cs)
             .to_str()
-            .map_err(|e| e.to_string())?
+            .map_err(ClangError::malformed)?
             .to_owned();
         clang_disposeString(s);
         Ok(owned)
@@ -53,23 +61,173 @@ pub fn is_expression(cursor: CXCursor) -> bool {
 pub fn get_children_n<const N: usize>(cursor: CXCursor) -> Result<[CXCursor; N], ClangError> {
     let children = get_children(cursor);
     children.try_into().map_err(|v: Vec<CXCursor>| {
-        format!(
+        ClangError::malformed(format!(
             "Children size doesn't match, expected {}, but got {}",
             N,
             v.len()
-        )
+        ))
     })
 }
 
-pub fn get_parameters(ty: CXType) -> Vec<CXType> {
+pub fn get_parameters(ty: CXType) -> Result<Vec<CXType>, ClangError> {
     unsafe {
         let argc = clang_getNumArgTypes(ty);
         if argc == -1 {
-            panic!("Not a function type")
+            Err(ClangError::malformed("not a function type"))
         } else {
-            (0..(argc as u32))
+            Ok((0..(argc as u32))
                 .map(|i| clang_getArgType(ty, i))
-                .collect::<Vec<CXType>>()
+                .collect::<Vec<CXType>>())
+        }
+    }
+}
+
+/// Spellings of the tokens within the extent of `cursor`.
+///
+/// # Safety
+///
+/// `cursor` must belong to `unit`, which must not have been disposed of.
+pub unsafe fn get_tokens(unit: CXTranslationUnit, cursor: CXCursor) -> Result<Vec<String>, ClangError> {
+    unsafe {
+        let mut tokens = std::ptr::null_mut();
+        let mut count = 0;
+        clang_tokenize(unit, clang_getCursorExtent(cursor), &mut tokens, &mut count);
+        let spellings = (0..count as usize)
+            .map(|i| from_CXString(clang_getTokenSpelling(unit, *tokens.add(i))))
+            .collect();
+        clang_disposeTokens(unit, tokens, count);
+        spellings
+    }
+}
+
+/// Documentation comment attached to `cursor`, empty when it has none.
+pub fn get_doc(cursor: CXCursor) -> Result<Doc, ClangError> {
+    unsafe {
+        let raw = clang_Cursor_getRawCommentText(cursor);
+        if clang_getCString(raw).is_null() {
+            clang_disposeString(raw);
+            return Ok(Doc::default());
+        }
+        let text = from_CXString(raw)?;
+        // Consecutive `///` lines come as a single raw comment
+        let comments = if text.trim_start().starts_with("//") {
+            text.lines().collect()
+        } else {
+            vec![text.as_str()]
+        };
+        let lines = comments
+            .into_iter()
+            .filter_map(parse_doc_comment)
+            .flat_map(|(lines, _)| lines)
+            .collect::<Vec<_>>();
+        Ok(Doc::parse(&lines))
+    }
+}
+
+/// 1-based line of the start of `cursor` in its file.
+pub fn get_line(cursor: CXCursor) -> usize {
+    unsafe {
+        let mut line = 0;
+        clang_getSpellingLocation(clang_getCursorLocation(cursor), null_mut(), &mut line, null_mut(), null_mut());
+        line as usize
+    }
+}
+
+/// Path of the file `cursor` is in, as libclang found it, or `None` for builtins.
+pub fn get_file(cursor: CXCursor) -> Option<String> {
+    unsafe {
+        let mut file = null_mut();
+        clang_getSpellingLocation(clang_getCursorLocation(cursor), &mut file, null_mut(), null_mut(), null_mut());
+        if file.is_null() { None } else { from_CXString(clang_getFileName(file)).ok() }
+    }
+}
+
+/// Byte offsets of the start and end of `cursor` in its file.
+pub fn get_offsets(cursor: CXCursor) -> (usize, usize) {
+    unsafe {
+        let extent = clang_getCursorExtent(cursor);
+        let offset = |location| {
+            let mut offset = 0;
+            clang_getFileLocation(location, null_mut(), null_mut(), null_mut(), &mut offset);
+            offset as usize
+        };
+        (offset(clang_getRangeStart(extent)), offset(clang_getRangeEnd(extent)))
+    }
+}
+
+/// Whether a record or enum has no tag. Depending on the version, libclang spells those empty
+/// or as `(unnamed ...)` / `(anonymous ...)`.
+pub fn is_unnamed(cursor: CXCursor) -> Result<bool, ClangError> {
+    unsafe {
+        let spelling = from_CXString(clang_getCursorSpelling(cursor))?;
+        Ok(clang_Cursor_isAnonymous(cursor) != 0
+            || spelling.is_empty()
+            || spelling.contains("(unnamed")
+            || spelling.contains("(anonymous"))
+    }
+}
+
+/// Whether `cursor`, under a record, is a member without a name: an anonymous struct or union,
+/// or an unnamed bitfield. Those are named `anon0`, `anon1`... in order.
+pub fn is_anonymous_member(cursor: CXCursor) -> bool {
+    unsafe {
+        match get_kind(cursor) {
+            CXCursor_StructDecl | CXCursor_UnionDecl => clang_Cursor_isAnonymousRecordDecl(cursor) != 0,
+            CXCursor_FieldDecl => {
+                clang_Cursor_isBitField(cursor) != 0
+                    && from_CXString(clang_getCursorSpelling(cursor)).is_ok_and(|name| name.is_empty())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Name of a struct, union or enum declaration. One without a tag is named after the typedef
+/// naming it, or else after its place in the enclosing record: the member declared with it,
+/// like `Outer_inner`, or the anonymous member it is, like `Outer_anon0`.
+pub fn record_name(declaration: CXCursor) -> Result<Identifier, ClangError> {
+    unsafe {
+        if !is_unnamed(declaration)? {
+            return Ok(from_CXString(clang_getCursorSpelling(declaration))?.interned());
+        }
+        // The type of `typedef struct { ... } Name;` is spelled `Name`
+        let spelling = from_CXString(clang_getTypeSpelling(clang_getCursorType(declaration)))?;
+        if !spelling.is_empty() && spelling.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Ok(spelling.interned());
+        }
+
+        let parent = clang_getCursorSemanticParent(declaration);
+        if matches!(get_kind(parent), CXCursor_StructDecl | CXCursor_UnionDecl) {
+            let parent_name = record_name(parent)?;
+            let mut anonymous = 0;
+            for child in get_children(parent) {
+                if is_anonymous_member(child) {
+                    if clang_equalCursors(child, declaration) != 0 {
+                        return Ok(format!("{}_anon{}", parent_name.original(), anonymous).interned());
+                    }
+                    anonymous += 1;
+                } else if get_kind(child) == CXCursor_FieldDecl && declares(child, declaration) {
+                    let field = from_CXString(clang_getCursorSpelling(child))?;
+                    return Ok(format!("{}_{}", parent_name.original(), field).interned());
+                }
+            }
+        }
+        Err(ClangError::UnsupportedCursor { cursor: CursorContext::new(declaration) })
+    }
+}
+
+/// Whether the type of `field`, through arrays and pointers, is the record `declaration`.
+fn declares(field: CXCursor, declaration: CXCursor) -> bool {
+    unsafe {
+        let mut ty = clang_getCursorType(field);
+        loop {
+            ty = match ty.kind {
+                CXType_Elaborated => clang_Type_getNamedType(ty),
+                CXType_ConstantArray | CXType_IncompleteArray => clang_getArrayElementType(ty),
+                CXType_Pointer => clang_getPointeeType(ty),
+                _ => break,
+            };
         }
+        clang_equalCursors(clang_getTypeDeclaration(ty), declaration) != 0
     }
 }
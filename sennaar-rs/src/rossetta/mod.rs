@@ -1,3 +1,6 @@
+pub mod clang_config;
+pub mod clang_error;
 pub mod clang_expr;
+pub mod clang_extract;
 pub mod clang_ty;
 pub mod clang_utils;
@@ -0,0 +1,17 @@
+struct attributes_buffer;
+
+int attributes_read(struct attributes_buffer* _Nonnull buffer, void* _Nullable out, const char* _Nullable* names);
+
+__attribute__((nonnull(1, 3))) __attribute__((warn_unused_result))
+int attributes_copy(struct attributes_buffer* _Nullable dst, const void* src, const void* _Nullable label);
+
+__attribute__((deprecated("use attributes_read instead")))
+int attributes_old_read(struct attributes_buffer* buffer);
+
+struct attributes_point {
+    __attribute__((annotate("sennaar:len=count"))) int* items;
+    int count;
+    const char* _Nonnull label;
+} __attribute__((deprecated));
+
+typedef void (*attributes_callback)(void* _Nullable user);
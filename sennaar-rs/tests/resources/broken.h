@@ -0,0 +1,3 @@
+int broken_ok(void);
+
+int broken_read(broken_missing value);
@@ -0,0 +1,15 @@
+#include <stddef.h>
+#include "config_item.h"
+
+#ifdef CONFIG_WIDE
+typedef long config_size;
+#else
+typedef int config_size;
+#endif
+
+config_size config_len(const config_item *item, size_t count);
+void config_internal_reset(void);
+char config_separator(void);
+
+#define CONFIG_VERSION 3
+#define CONFIG_INTERNAL_FLAG 1
@@ -0,0 +1,59 @@
+#ifndef EXTRACT_H
+#define EXTRACT_H
+
+#define EXTRACT_MAX 16
+#define EXTRACT_FLAG (1u << 2)
+#define EXTRACT_SQUARE(x) ((x) * (x))
+#define EXTRACT_NAME_SIZE 0x20
+#define EXTRACT_SIZE(x) ((extract_size)(x))
+
+typedef unsigned int extract_size;
+typedef int * _Atomic extract_atomic_pointer;
+typedef char * const extract_const_pointer;
+
+/** Kind of a shape. */
+typedef enum extract_kind {
+    EXTRACT_CIRCLE,
+    EXTRACT_SQUARE_KIND = 4,
+    EXTRACT_NONE = -1,
+    EXTRACT_OCTAL = 010u,
+    EXTRACT_LAST = EXTRACT_MAX,
+    EXTRACT_NUL = '\0',
+} extract_kind;
+
+enum { EXTRACT_LIMIT = 256 };
+
+typedef struct {
+    int x, y;
+} extract_point;
+
+struct extract_shape {
+    extract_kind kind;
+    unsigned flags : 3;
+    extract_point origin;
+    char name[EXTRACT_NAME_SIZE];
+};
+
+union extract_value {
+    int i;
+    float f;
+};
+
+struct extract_context;
+typedef struct extract_context extract_context;
+typedef struct extract_device_T* extract_device;
+
+typedef void (*extract_callback)(void* user, int code);
+
+/// Draw a shape.
+int extract_draw(extract_context* context, const struct extract_shape* shape);
+
+void extract_copy(char* restrict dst, const char* const* src, volatile _Atomic int* flag);
+
+int extract_log(const char* format, ...);
+
+typedef void (__attribute__((ms_abi)) *extract_win64_callback)(int code);
+
+void extract_visit(const char* label, int (*visitor)(const struct extract_shape* shape, void* user));
+
+#endif
@@ -0,0 +1,5 @@
+typedef struct config_item {
+    int value;
+} config_item;
+
+typedef int config_detail;
@@ -0,0 +1,32 @@
+typedef union records_color {
+    float float32[4];
+    int int32[4];
+} records_color;
+
+typedef union records_value {
+    records_color color;
+    struct {
+        float depth;
+        unsigned stencil;
+    } depth_stencil;
+} records_value;
+
+typedef struct {
+    unsigned type;
+    union {
+        struct records_key {
+            int code;
+            unsigned repeat : 1;
+            unsigned : 7;
+        } key;
+        struct {
+            int x, y;
+        };
+    };
+} records_event;
+
+struct records_buffer {
+    enum { RECORDS_BUFFER_MAX = 64 };
+    unsigned length;
+    char data[];
+};
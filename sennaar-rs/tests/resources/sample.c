@@ -13,14 +13,20 @@ typedef unsigned long long ull;
 
 void foo(int a, int b) {
   int arr[5];
-  // comment to make CI happy, uncomment to test sizeof
-  // int int_size = sizeof(int);
+  int int_size = sizeof(int);
   arr[0] = a++;
   arr[0x01] = ++b;
   arr[2] = 1 ? !MAKRO : 0;
   foo(&arr, *arr);
   (int) 0x114514;
   a += b;
+  sizeof(unsigned int *);
+  sizeof arr;
+  _Alignof(struct Foo);
+  __builtin_offsetof(struct Foo, aa);
+  (_Bool) a;
+  (const ull *) arr;
+  sizeof(const ull *);
 }
 
 int callback(int (*f)(unsigned int, int), int arr[10]) {
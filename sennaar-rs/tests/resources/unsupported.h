@@ -0,0 +1,8 @@
+struct unsupported_wide {
+    __int128 value;
+};
+
+#define UNSUPPORTED_EXPORT __attribute__((visibility("default")))
+#define UNSUPPORTED_DECLARE(name) int name;
+
+int unsupported_ok(int value);
@@ -5,10 +5,14 @@ use std::{
 
 use clang_sys::*;
 use sennaar::rossetta::{
+    clang_config::{Filter, ParseConfig},
     clang_expr::{self, map_nodes},
+    clang_extract::{extract_registry, ExtractMode},
     clang_ty::map_ty,
-    clang_utils::{from_CXString, get_children, is_expression},
+    clang_utils::{from_CXString, get_children, is_expression, ClangError},
 };
+use sennaar::registry::{CallingConvention, Entity, Metadata, Type};
+use sennaar::Internalize;
 
 #[test]
 fn adapt_expr() {
@@ -56,12 +60,19 @@ fn real_test_with_assertion() {
             .collect::<Vec<String>>();
 
         let expected = vec![
-            "arr[0x0] = a++",
-            "arr[0x1] = ++b",
-            "arr[0x2] = 0x1 ? !(0x1BF52) : 0x0",
+            "arr[0] = a++",
+            "arr[0x01] = ++b",
+            "arr[2] = 1 ? !MAKRO : 0",
             "foo(&arr, *arr)",
             "(int) 0x114514",
             "a += b",
+            "sizeof(unsigned int*)",
+            "sizeof(arr)",
+            "alignof(Foo)",
+            "offsetof(Foo, aa)",
+            "(_Bool) a",
+            "(const ull*) arr",
+            "sizeof(const ull*)",
         ];
 
         for i in 0..expected.len() {
@@ -84,6 +95,236 @@ fn real_test_with_assertion() {
     }
 }
 
+#[test]
+fn extract_header_registry() {
+    let extraction =
+        extract_registry("./tests/resources/extract.h", "extract", &ParseConfig::default(), ExtractMode::Strict).unwrap();
+    let registry = &extraction.registry;
+
+    let draw = &registry.commands[&"extract_draw".interned()];
+    assert_eq!(draw.params.len(), 2);
+    assert_eq!(draw.doc.to_lines(), ["Draw a shape."]);
+    let Type::PointerType(shape) = &draw.params[1].ty else { panic!("{:?}", draw.params[1].ty) };
+    assert!(shape.is_const);
+    assert!(matches!(&shape.pointee, Type::IdentifierType(ident) if ident.ident == "extract_shape".interned()));
+
+    let copy = &registry.commands[&"extract_copy".interned()];
+    let Type::PointerType(dst) = &copy.params[0].ty else { panic!("{:?}", copy.params[0].ty) };
+    assert!(dst.is_restrict && !dst.is_const);
+    let Type::PointerType(src) = &copy.params[1].ty else { panic!("{:?}", copy.params[1].ty) };
+    assert!(src.is_const && matches!(&src.pointee, Type::PointerType(inner) if inner.is_const));
+    let Type::PointerType(flag) = &copy.params[2].ty else { panic!("{:?}", copy.params[2].ty) };
+    assert!(flag.is_volatile && flag.is_atomic);
+    let atomic = &registry.aliases[&"extract_atomic_pointer".interned()].target;
+    let Type::PointerType(atomic) = atomic else { panic!("{:?}", atomic) };
+    assert!(atomic.is_pointer_atomic && !atomic.is_atomic);
+    let constant = &registry.aliases[&"extract_const_pointer".interned()].target;
+    let Type::PointerType(constant) = constant else { panic!("{:?}", constant) };
+    assert!(constant.is_pointer_const && !constant.is_const);
+
+    let log = &registry.commands[&"extract_log".interned()];
+    assert!(log.variadic && log.calling_convention.is_none());
+    let win64 = &registry.function_typedefs[&"extract_win64_callback".interned()];
+    assert_eq!(win64.calling_convention, Some(CallingConvention::Win64));
+
+    // Function prototypes without a typedef become function typedefs named after their use
+    let visit = &registry.commands[&"extract_visit".interned()];
+    assert!(matches!(&visit.params[1].ty, Type::IdentifierType(ident) if ident.ident == "extract_visit_visitor".interned()));
+    let visitor = &registry.function_typedefs[&"extract_visit_visitor".interned()];
+    assert!(visitor.is_pointer);
+    assert_eq!(visitor.params.len(), 2);
+    assert!(matches!(&visitor.result, Type::IdentifierType(ident) if ident.ident == "int".interned()));
+
+    let kind = &registry.enumerations[&"extract_kind".interned()];
+    let variants = kind.variants.iter().map(|v| format!("{} = {}", v.name, v.value)).collect::<Vec<_>>();
+    assert_eq!(
+        variants,
+        [
+            "EXTRACT_CIRCLE = 0",
+            "EXTRACT_SQUARE_KIND = 4",
+            "EXTRACT_NONE = -1",
+            "EXTRACT_OCTAL = 010u",
+            "EXTRACT_LAST = EXTRACT_MAX",
+            "EXTRACT_NUL = '\\0'",
+        ]
+    );
+    assert_eq!(kind.doc.to_lines(), ["Kind of a shape."]);
+
+    let point = &registry.structs[&"extract_point".interned()];
+    assert_eq!(point.members.len(), 2);
+    let shape = &registry.structs[&"extract_shape".interned()];
+    assert_eq!(shape.members[1].bits, Some(3));
+    let Type::ArrayType(name) = &shape.members[3].ty else { panic!("{:?}", shape.members[3].ty) };
+    assert_eq!(name.length.as_ref().unwrap().to_string(), "EXTRACT_NAME_SIZE");
+    assert!(registry.unions.contains_key(&"extract_value".interned()));
+
+    assert!(registry.aliases.contains_key(&"extract_size".interned()));
+    assert!(!registry.aliases.contains_key(&"extract_kind".interned()));
+    assert!(registry.opaque_typedefs.contains_key(&"extract_context".interned()));
+    assert!(registry.opaque_handle_typedefs.contains_key(&"extract_device".interned()));
+    let callback = &registry.function_typedefs[&"extract_callback".interned()];
+    assert!(callback.is_pointer);
+    assert_eq!(callback.params[1].name, "code".interned());
+
+    assert_eq!(registry.constants[&"EXTRACT_MAX".interned()].expr.to_string(), "16");
+    assert!(registry.constants.contains_key(&"EXTRACT_FLAG".interned()));
+    assert!(!registry.constants.contains_key(&"EXTRACT_H".interned()));
+    assert_eq!(registry.macros[&"EXTRACT_SQUARE".interned()].params, ["x".interned()]);
+    // Casts in macros know the typedefs
+    assert!(registry.macros.contains_key(&"EXTRACT_SIZE".interned()));
+
+    // An enum without a tag only declares constants
+    let limit = &registry.constants[&"EXTRACT_LIMIT".interned()];
+    assert_eq!((limit.ty.to_string(), limit.expr.to_string()), ("int".to_string(), "256".to_string()));
+}
+
+#[test]
+fn extract_nested_records() {
+    let extraction =
+        extract_registry("./tests/resources/records.h", "records", &ParseConfig::default(), ExtractMode::Strict).unwrap();
+    let registry = &extraction.registry;
+    let ident = |ty: &Type| match ty {
+        Type::IdentifierType(ident) => ident.ident.to_string(),
+        _ => panic!("{:?}", ty),
+    };
+
+    // Records without a tag are named after the member declared with them
+    let value = &registry.unions[&"records_value".interned()];
+    assert_eq!(ident(&value.members[1].ty), "records_value_depth_stencil");
+    assert_eq!(registry.structs[&"records_value_depth_stencil".interned()].members.len(), 2);
+
+    // ... or after the anonymous member they are
+    let event = &registry.structs[&"records_event".interned()];
+    let members = event.members.iter().map(|m| m.name.to_string()).collect::<Vec<_>>();
+    assert_eq!(members, ["type", "anon0"]);
+    assert!(event.members[1].metadata.contains_key("anonymous"));
+    assert_eq!(ident(&event.members[1].ty), "records_event_anon0");
+    let anon = &registry.unions[&"records_event_anon0".interned()];
+    assert_eq!(ident(&anon.members[0].ty), "records_key");
+    assert_eq!(ident(&anon.members[1].ty), "records_event_anon0_anon0");
+    assert_eq!(registry.structs[&"records_event_anon0_anon0".interned()].members.len(), 2);
+
+    let key = &registry.structs[&"records_key".interned()];
+    let bits = key.members.iter().map(|m| (m.name.to_string(), m.bits)).collect::<Vec<_>>();
+    assert_eq!(bits, [("code".to_string(), None), ("repeat".to_string(), Some(1)), ("anon0".to_string(), Some(7))]);
+
+    let buffer = &registry.structs[&"records_buffer".interned()];
+    let Type::ArrayType(data) = &buffer.members[1].ty else { panic!("{:?}", buffer.members[1].ty) };
+    assert!(data.length.is_none());
+    assert_eq!(registry.constants[&"RECORDS_BUFFER_MAX".interned()].expr.to_string(), "64");
+}
+
+#[test]
+fn extract_attributes() {
+    let extraction =
+        extract_registry("./tests/resources/attributes.h", "attributes", &ParseConfig::default(), ExtractMode::Strict).unwrap();
+    let registry = &extraction.registry;
+    let nullable = |ty: &Type| match ty {
+        Type::PointerType(pointer) => pointer.nullable,
+        _ => panic!("{:?}", ty),
+    };
+
+    // `_Nullable` makes parameters optional, at whichever level it is written
+    let read = &registry.commands[&"attributes_read".interned()];
+    let optional = read.params.iter().map(|param| param.optional).collect::<Vec<_>>();
+    assert_eq!(optional, [false, true, false]);
+    let Type::PointerType(names) = &read.params[2].ty else { panic!("{:?}", read.params[2].ty) };
+    assert!(!names.nullable && nullable(&names.pointee));
+    // A pointer marked never null is told apart from one that says nothing
+    let nonnull = read.params.iter().map(|param| param.has_metadata("nonnull")).collect::<Vec<_>>();
+    assert_eq!(nonnull, [true, false, false]);
+    assert!(!nullable(&read.params[0].ty) && !nullable(&read.params[2].ty));
+
+    // `nonnull` wins over `_Nullable` for the parameters it lists
+    let copy = &registry.commands[&"attributes_copy".interned()];
+    let optional = copy.params.iter().map(|param| param.optional).collect::<Vec<_>>();
+    assert_eq!(optional, [false, false, false]);
+    assert!(!nullable(&copy.params[0].ty));
+    assert_eq!(copy.try_get_metadata("warn_unused_result"), Some(&Metadata::None));
+    assert!(!copy.has_metadata("nonnull"));
+    let nonnull = copy.params.iter().map(|param| param.has_metadata("nonnull")).collect::<Vec<_>>();
+    assert_eq!(nonnull, [true, false, true]);
+
+    let old_read = &registry.commands[&"attributes_old_read".interned()];
+    let message = Metadata::String { value: "use attributes_read instead".to_string() };
+    assert_eq!(old_read.try_get_metadata("deprecated"), Some(&message));
+
+    let point = &registry.structs[&"attributes_point".interned()];
+    assert_eq!(point.try_get_metadata("deprecated"), Some(&Metadata::None));
+    assert!(point.members[0].has_metadata("sennaar:len=count"));
+    assert!(!point.members[0].has_metadata("nonnull") && point.members[2].has_metadata("nonnull"));
+
+    let callback = &registry.function_typedefs[&"attributes_callback".interned()];
+    assert!(callback.params[0].optional && nullable(&callback.params[0].ty));
+}
+
+#[test]
+fn extract_with_config() {
+    let config = ParseConfig {
+        include_dirs: vec!["./tests/resources/include".to_string()],
+        defines: vec![("CONFIG_WIDE".to_string(), None)],
+        std: Some("c11".to_string()),
+        clang_args: vec!["-funsigned-char".to_string()],
+        names: Filter::new(&[], &["config_internal_.*", "CONFIG_INTERNAL_.*", "config_detail"]).unwrap(),
+        files: Filter::new(&[".*/include/config_item\\.h"], &[]).unwrap(),
+        ..ParseConfig::default()
+    };
+    let extraction = extract_registry("./tests/resources/config.h", "config", &config, ExtractMode::Strict).unwrap();
+    let registry = &extraction.registry;
+
+    let size = &registry.aliases[&"config_size".interned()];
+    assert!(matches!(&size.target, Type::IdentifierType(ident) if ident.ident == "long".interned()));
+    assert!(registry.commands.contains_key(&"config_len".interned()));
+    assert!(registry.constants.contains_key(&"CONFIG_VERSION".interned()));
+    // `char` is still `char` when it is unsigned
+    let separator = &registry.commands[&"config_separator".interned()];
+    assert!(matches!(&separator.result, Type::IdentifierType(ident) if ident.ident == "char".interned()));
+
+    // Allowed by file, unlike stddef.h
+    assert!(registry.structs.contains_key(&"config_item".interned()));
+    assert!(!registry.aliases.contains_key(&"size_t".interned()));
+
+    // Blocked by name
+    assert!(!registry.commands.contains_key(&"config_internal_reset".interned()));
+    assert!(!registry.constants.contains_key(&"CONFIG_INTERNAL_FLAG".interned()));
+    assert!(!registry.aliases.contains_key(&"config_detail".interned()));
+}
+
+#[test]
+fn skip_unsupported_declarations() {
+    let header = "./tests/resources/unsupported.h";
+    let error = extract_registry(header, "unsupported", &ParseConfig::default(), ExtractMode::Strict).unwrap_err();
+    let ClangError::UnsupportedType { kind, cursor: Some(cursor), .. } = &error else {
+        panic!("{:?}", error);
+    };
+    assert_eq!(kind, "Int128");
+    assert_eq!((cursor.kind.as_str(), cursor.spelling.as_str(), cursor.line), ("FieldDecl", "value", 2));
+
+    let extraction =
+        extract_registry(header, "unsupported", &ParseConfig::default(), ExtractMode::SkipUnsupported).unwrap();
+    assert!(!extraction.registry.structs.contains_key(&"unsupported_wide".interned()));
+    assert!(extraction.registry.commands.contains_key(&"unsupported_ok".interned()));
+    let warnings = extraction.warnings.iter().map(|w| w.to_string()).collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 3, "{:?}", warnings);
+    assert!(warnings[0].starts_with("unsupported type '__int128' of kind 'Int128' at "), "{}", warnings[0]);
+    assert!(warnings[1].starts_with("unsupported macro: "), "{}", warnings[1]);
+    assert!(warnings[2].starts_with("unsupported macro: not an expression"), "{}", warnings[2]);
+}
+
+#[test]
+fn report_parse_errors() {
+    let header = "./tests/resources/broken.h";
+    let error = extract_registry(header, "broken", &ParseConfig::default(), ExtractMode::Strict).unwrap_err();
+    let ClangError::Diagnostic { message } = &error else { panic!("{:?}", error) };
+    assert!(message.contains("broken.h:3:"), "{}", message);
+    assert!(message.contains("unknown type name 'broken_missing'"), "{}", message);
+
+    let extraction =
+        extract_registry(header, "broken", &ParseConfig::default(), ExtractMode::SkipUnsupported).unwrap();
+    assert!(extraction.registry.commands.contains_key(&"broken_ok".interned()));
+    assert!(matches!(&extraction.warnings[..], [ClangError::Diagnostic { .. }]), "{:?}", extraction.warnings);
+}
+
 struct ClientData {
     level: u32,
 }
@@ -140,7 +381,7 @@ fn print_padding(level: u32) {
     print!("{}", " ".repeat(level as usize));
 }
 
-fn error(err: String, e: CXCursor) -> ! {
+fn error(err: ClangError, e: CXCursor) -> ! {
     unsafe {
         let cursor_kind = clang_getCursorKind(e);
         let kind_spelling = clang_getCursorKindSpelling(cursor_kind);
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use sennaar::registry::{parse_registry, parse_registry_xml, Registry};
+
+fn sennaar(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sennaar")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("sennaar-{}-{}", std::process::id(), name))
+}
+
+#[test]
+fn convert_between_formats() {
+    let json = stdout(&sennaar(&["convert", "./tests/resources/sample.sennaar", "--to", "json"]));
+    let registry = Registry::from_json("sample.json", &json).unwrap();
+    assert_eq!(registry.name, "sample");
+
+    // The output format is guessed from the output file
+    let xml_path = temp_path("sample.xml");
+    stdout(&sennaar(&["convert", "./tests/resources/sample.sennaar", "-o", xml_path.to_str().unwrap()]));
+    let xml = std::fs::read_to_string(&xml_path).unwrap();
+    std::fs::remove_file(&xml_path).unwrap();
+    let from_xml = parse_registry_xml("sample.xml", &xml).unwrap();
+    assert_eq!(serde_json::to_value(&from_xml).unwrap(), serde_json::to_value(&registry).unwrap());
+
+    let dsl = stdout(&sennaar(&["convert", "./tests/resources/sample.sennaar", "--to", "dsl"]));
+    assert_eq!(parse_registry("sample.sennaar", &dsl).unwrap().name, "sample");
+
+    let output = sennaar(&["convert", "./tests/resources/sample.sennaar"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("use --to"));
+}
+
+#[test]
+fn validate_registries() {
+    let base = temp_path("base.sennaar");
+    let registry = temp_path("r.sennaar");
+    std::fs::write(&base, "registry base; opaque Allocator;").unwrap();
+    std::fs::write(&registry, "registry r;\ncommand destroy(allocator: *Allocator) -> void;\n").unwrap();
+
+    let imported = sennaar(&["validate", registry.to_str().unwrap(), "--import", base.to_str().unwrap()]);
+    let alone = sennaar(&["validate", registry.to_str().unwrap()]);
+    std::fs::remove_file(&base).unwrap();
+    std::fs::remove_file(&registry).unwrap();
+
+    stdout(&imported);
+    assert!(!alone.status.success());
+    let stderr = String::from_utf8_lossy(&alone.stderr);
+    assert!(stderr.contains("command 'destroy' parameter 'allocator': unknown type 'Allocator'"), "{}", stderr);
+    assert!(stderr.contains("validation failed with 1 error(s)"), "{}", stderr);
+}
+
+#[test]
+fn print_schema() {
+    let schema: serde_json::Value = serde_json::from_str(&stdout(&sennaar(&["schema"]))).unwrap();
+    assert_eq!(schema["title"], "Registry");
+    assert!(schema["$defs"]["Macro"].is_object());
+}
@@ -0,0 +1,84 @@
+use sennaar::registry::{parse_registry, Registry};
+use sennaar::Internalize;
+
+#[test]
+fn collect_multiple_dsl_errors() {
+    let source = "registry r;\n\
+                  opaque Foo;\n\
+                  opaque Foo;\n\
+                  @bits(3) handle Bar;\n\
+                  strukt Baz { x: int }\n\
+                  opaque `Qux:Q1`;\n\
+                  opaque `Qux:Q2`;\n";
+    let errors = parse_registry("test.sennaar", source).unwrap_err();
+    let messages = errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>();
+
+    assert_eq!(errors.len(), 5, "{:#?}", messages);
+    assert_eq!(messages[0], "duplicate opaque 'Foo'");
+    assert_eq!(messages[1], "@bits is not applicable to handle");
+    assert_eq!(messages[2], "unknown entity kind `strukt`");
+    assert!(messages[3].starts_with("Failed renaming identifier 'Qux' to 'Q2'"));
+    assert_eq!(messages[4], "duplicate opaque 'Qux'");
+
+    let lines = errors.iter().map(|e| e.location.as_ref().unwrap().line).collect::<Vec<_>>();
+    assert_eq!(lines, [3, 4, 5, 7, 7]);
+}
+
+#[test]
+fn keep_renames_of_failed_parse() {
+    let source = "registry r;\n\
+                  opaque `Quux:Q`;\n\
+                  strukt Baz { x: int }\n";
+    assert!(parse_registry("test.sennaar", source).is_err());
+    assert_eq!("Quux".interned().renamed(), None);
+
+    let registry = parse_registry("test.sennaar", "registry r; opaque `Quux:Q`;").unwrap();
+    assert_eq!(registry.opaque_typedefs[&"Quux".interned()].name.value(), "Q");
+}
+
+#[test]
+fn render_dsl_error() {
+    let source = "registry r;\nstruct Foo {\n    x: int;\n}\n";
+    let errors = parse_registry("test.sennaar", source).unwrap_err();
+    assert_eq!(errors.len(), 1);
+
+    let rendered = errors[0].to_string();
+    assert_eq!(
+        rendered,
+        "error: unexpected token `;`\n \
+         --> test.sennaar:3:11\n  \
+         |\n\
+         3 |     x: int;\n  \
+         |           ^\n  \
+         = expected one of `,`, `}`"
+    );
+}
+
+#[test]
+fn collect_json_rename_errors() {
+    let source = r#"{
+        "name": "r",
+        "imports": [],
+        "metadefs": {},
+        "aliases": {},
+        "bitmasks": {},
+        "constants": {},
+        "commands": {},
+        "enumerations": {},
+        "functionTypedefs": {},
+        "opaqueTypedefs": {
+            "JsonFoo:A": { "name": "JsonFoo:A", "metadata": {}, "doc": [] }
+        },
+        "opaqueHandleTypedefs": {
+            "JsonBar:B": { "name": "JsonBar:C", "metadata": {}, "doc": [] }
+        },
+        "structs": {},
+        "unions": {},
+        "ext": null
+    }"#;
+    let errors = Registry::from_json("test.json", source).unwrap_err();
+
+    assert_eq!(errors.len(), 1, "{:#?}", errors);
+    assert!(errors[0].message.starts_with("Failed renaming identifier 'JsonBar' to 'C'"));
+    assert_eq!(errors[0].location.as_ref().unwrap().line, 15);
+}
@@ -0,0 +1,392 @@
+use std::collections::BTreeMap;
+
+use sennaar::Internalize;
+use sennaar::panspace::{
+    hparse, is_standard_type_name, lower_declarations, lower_defines, AnnotationTable, DeclarationReader,
+    HparseConfig, HparseLine, HparseRule, SkipReason,
+};
+use sennaar::registry::{CallingConvention, Endian, PlatformVariants, Registry, Type};
+
+const HEADER: &str = r#"#ifndef DEMO_H_
+#define DEMO_H_ 1
+
+/*
+ * Demo API
+ */
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct VkInstance_T* VkInstance;
+VK_DEFINE_HANDLE(VkDevice)
+typedef void (VKAPI_PTR *PFN_vkVoidFunction)(void);
+
+typedef struct VkExtent2D {
+    uint32_t width;
+#if defined(DEMO_WIDE)
+    uint32_t depth;
+#endif
+    uint32_t height;
+} VkExtent2D;
+
+// Creates an instance
+VKAPI_ATTR int VKAPI_CALL vkCreateInstance(
+    const VkExtent2D*                           pExtent,
+    VkInstance*                                 pInstance);
+
+GLAPI void APIENTRY glClear (GLbitfield mask);
+typedef void (APIENTRYP PFNGLCLEARPROC) (GLbitfield mask);
+__declspec(dllimport) BOOL WINAPI CloseHandle(HANDLE hObject);
+
+#ifdef __cplusplus
+}
+#endif
+#endif
+"#;
+
+#[test]
+fn pick_out_declaration_regions() {
+    let mut config = HparseConfig::default();
+    config.rules.extend([HparseRule::vulkan(), HparseRule::opengl(), HparseRule::win32()]);
+    let output = hparse(HEADER, &config);
+
+    let regions = output.regions.iter().map(|region| (region.rule.as_str(), region.line)).collect::<Vec<_>>();
+    assert_eq!(
+        regions,
+        [
+            ("declarations", 11),
+            ("declarations", 13),
+            ("declarations", 15),
+            ("vulkan", 24),
+            ("opengl", 28),
+            ("declarations", 29),
+            ("win32", 30),
+        ]
+    );
+    assert_eq!(output.unrecognized, [HparseLine { line: 12, text: "VK_DEFINE_HANDLE(VkDevice)".to_string() }]);
+
+    // Expansion keeps every region the same length as its source text
+    for region in &output.regions {
+        assert!(region.text.ends_with(';'), "{}", region.text);
+        assert_eq!(HEADER.as_bytes()[region.start + region.text.len() - 1], b';', "{}", region.text);
+    }
+    assert!(!output.regions[2].text.contains('#'));
+    assert_eq!(output.regions[5].text.trim(), "typedef void (APIENTRY* PFNGLCLEARPROC) (GLbitfield mask);");
+    assert_eq!(output.regions[6].text.trim(), "BOOL WINAPI CloseHandle(HANDLE hObject);");
+
+    // Calling convention macros are kept for the annotation tables
+    let mut annotations = AnnotationTable::vulkan();
+    annotations.extend(AnnotationTable::opengl());
+    annotations.extend(AnnotationTable::win32());
+    let is_type_name = |name: &str| {
+        is_standard_type_name(name) || ["GLbitfield", "BOOL", "HANDLE"].contains(&name)
+    };
+    let reader = DeclarationReader::new(&is_type_name).with_annotations(&annotations);
+    let (declarations, diagnostics) = output.parse_declarations("demo.h", HEADER, &reader);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let names = declarations
+        .iter()
+        .map(|declaration| declaration.declarators[0].declarator.name().unwrap().original().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["VkInstance", "PFN_vkVoidFunction", "VkExtent2D", "vkCreateInstance", "glClear", "PFNGLCLEARPROC", "CloseHandle"]
+    );
+
+    let mut registry = Registry::new("demo".to_string());
+    let messages = lower_declarations(registry.as_base_mut(), &declarations, &annotations);
+    assert!(messages.is_empty(), "{:?}", messages);
+    for name in ["vkCreateInstance", "glClear", "CloseHandle"] {
+        let command = &registry.commands[&name.interned()];
+        assert_eq!(command.calling_convention, Some(CallingConvention::StdCall), "{}", name);
+    }
+    for name in ["PFN_vkVoidFunction", "PFNGLCLEARPROC"] {
+        let functype = &registry.function_typedefs[&name.interned()];
+        assert_eq!(functype.calling_convention, Some(CallingConvention::StdCall), "{}", name);
+    }
+}
+
+#[test]
+fn report_region_parse_errors() {
+    let source = "typedef int Good;\n\ntypedef struct Bad {\n    int x\n} Bad;\n";
+    let output = hparse(source, &HparseConfig::default());
+    let (declarations, diagnostics) = output.parse_declarations("bad.h", source, &DeclarationReader::new(&is_standard_type_name));
+    assert_eq!(declarations.len(), 1);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].to_string().lines().take(5).collect::<Vec<_>>(),
+        ["error: unexpected token `}`", " --> bad.h:5:1", "  |", "5 | } Bad;", "  | ^"]
+    );
+}
+
+#[test]
+fn skip_comments_around_code() {
+    let source = "/* a */ typedef int Foo;\nextern int x; /* start\ntypedef int Hidden;\n*/ typedef int Bar;\n\
+        // typedef int Line;\n/* one */ /* two\n   three */\n";
+    let output = hparse(source, &HparseConfig::default());
+    let regions = output.regions.iter().map(|region| (region.line, region.text.trim())).collect::<Vec<_>>();
+    assert_eq!(
+        regions,
+        [(1, "/* a */ typedef int Foo;"), (2, "extern int x;"), (4, "typedef int Bar;")]
+    );
+    assert!(output.unrecognized.is_empty(), "{:?}", output.unrecognized);
+
+    let (declarations, diagnostics) =
+        output.parse_declarations("comments.h", source, &DeclarationReader::new(&is_standard_type_name));
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    assert_eq!(declarations.len(), 3);
+}
+
+const PLATFORM_HEADER: &str = r#"#ifndef PLAT_H
+#define PLAT_H
+
+#if defined(_WIN32)
+typedef void* plat_handle;
+#elif defined __APPLE__ && !defined(__x86_64__)
+typedef unsigned int plat_handle;
+#else
+typedef int plat_handle;
+#endif
+
+#ifdef __BIG_ENDIAN__
+typedef struct plat_pair { short hi; short lo; } plat_pair;
+#else
+typedef struct plat_pair { short lo; short hi; } plat_pair;
+#endif
+
+#if defined(_WIN32) && defined(__linux__)
+typedef int plat_never;
+#endif
+
+#if defined(_WIN32) || defined(__linux__)
+typedef int plat_either;
+#endif
+
+extern int plat_open(plat_handle* handle);
+#endif
+"#;
+
+#[test]
+fn tag_conditional_branches_with_platforms() {
+    let output = hparse(PLATFORM_HEADER, &HparseConfig::default());
+    let platforms = output
+        .regions
+        .iter()
+        .map(|region| (region.line, region.platform.as_ref().map(|platform| platform.to_string())))
+        .collect::<Vec<_>>();
+    let platform = |s: &str| Some(s.to_string());
+    assert_eq!(
+        platforms,
+        [
+            (5, platform("any_arch-any_endian-windows-any_libc-[any]")),
+            (7, platform("other_arch-any_endian-macos-any_libc-[any]")),
+            (9, platform("any_arch-any_endian-other_os-any_libc-[any]")),
+            (13, platform("any_arch-big-any_os-any_libc-[any]")),
+            (15, platform("any_arch-little-any_os-any_libc-[any]")),
+            (23, None),
+            (26, None),
+        ]
+    );
+    // `#else` cannot rule out the `__APPLE__ && !__x86_64__` branch, and is only kept off Windows
+    let unsupported = output.unsupported_conditions.iter().map(|line| line.line).collect::<Vec<_>>();
+    assert_eq!(unsupported, [8, 22]);
+    assert!(output.unsupported_conditions[1].text.starts_with("#if defined(_WIN32) || defined(__linux__): "));
+
+    let reader = DeclarationReader::new(&is_standard_type_name);
+    let (declarations, diagnostics) = output.parse_declarations("plat.h", PLATFORM_HEADER, &reader);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    let mut registry = Registry::new("plat".to_string());
+    let messages = lower_declarations(registry.as_base_mut(), &declarations, &AnnotationTable::new());
+    assert!(messages.is_empty(), "{:?}", messages);
+
+    // Every branch is kept as a variant for its own platform
+    let handle = &registry.aliases[&"plat_handle".interned()];
+    let handles = handle
+        .with_platform_variants()
+        .map(|variant| (variant.platform.as_ref().unwrap().to_string(), variant.target.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        handles,
+        [
+            ("any_arch-any_endian-windows-any_libc-[any]".to_string(), "void*".to_string()),
+            ("other_arch-any_endian-macos-any_libc-[any]".to_string(), "unsigned int".to_string()),
+            ("any_arch-any_endian-other_os-any_libc-[any]".to_string(), "int".to_string()),
+        ]
+    );
+    let pair = &registry.structs[&"plat_pair".interned()];
+    assert_eq!(pair.platform.as_ref().unwrap().endian, Some(Endian::big));
+    let little = &pair.platform_variants[0];
+    assert_eq!(little.platform.as_ref().unwrap().endian, Some(Endian::little));
+    assert_eq!(little.members[0].name.original(), "lo");
+    assert!(registry.commands[&"plat_open".interned()].platform.is_none());
+}
+
+const DEFINE_HEADER: &str = r#"#ifndef DEFS_H
+#define DEFS_H
+
+#define DEFS_EXPORT __attribute__((visibility("default")))
+#define DEFS_INLINE
+#define DEFS_MAX 256
+#define DEFS_FLAG (1u << 3)
+#define DEFS_MASK 0xFFFFFFFF
+#define DEFS_BIG 4294967296
+#define DEFS_LIMIT (DEFS_MAX * 2L)
+#define DEFS_RATIO 0.5f
+#define DEFS_NAME "defs" /* library name */
+#define DEFS_HANDLE ((defs_handle_t) 0)
+#define DEFS_NULL ((void*)0)
+#define DEFS_STEP \
+    (DEFS_MAX + 1)
+#define DEFS_SQUARE(x) ((x) * (x))
+#define DEFS_LOG(fmt, ...) defs_log(fmt, __VA_ARGS__)
+#define DEFS_UNUSED(x)
+#define DEFS_OTHER UNKNOWN_MACRO
+#ifdef _WIN32
+#define DEFS_SEPARATOR '\\'
+#else
+#define DEFS_SEPARATOR '/'
+#endif
+
+#endif
+"#;
+
+#[test]
+fn lower_object_like_defines() {
+    let output = hparse(DEFINE_HEADER, &HparseConfig::default());
+    assert!(output.regions.is_empty());
+    assert!(output.defines[0].is_include_guard);
+    assert_eq!(output.defines[13].params, Some(vec!["x".to_string()]));
+    assert_eq!(output.defines[12].body, "(DEFS_MAX + 1)");
+
+    let mut registry = Registry::new("defs".to_string());
+    let type_names = |name: &str| is_standard_type_name(name) || name == "defs_handle_t";
+    let skipped = lower_defines(registry.as_base_mut(), &output.defines, &type_names);
+    let skipped = skipped.into_iter().map(|skipped| (skipped.name, skipped.reason)).collect::<Vec<_>>();
+    let unknown = |reason: &str| SkipReason::UnknownType(reason.to_string());
+    assert_eq!(
+        skipped,
+        [
+            ("DEFS_H".to_string(), SkipReason::IncludeGuard),
+            ("DEFS_EXPORT".to_string(), unknown("cannot tell the type of '__attribute__((visibility(\"default\")))'")),
+            ("DEFS_INLINE".to_string(), SkipReason::EmptyDefinition),
+            ("DEFS_UNUSED".to_string(), SkipReason::EmptyDefinition),
+            ("DEFS_OTHER".to_string(), unknown("refers to unknown 'UNKNOWN_MACRO'")),
+        ]
+    );
+
+    let types = registry
+        .constants
+        .values()
+        .map(|constant| (constant.name.original().to_string(), spell(&constant.ty)))
+        .collect::<BTreeMap<_, _>>();
+    let expected = [
+        ("DEFS_MAX", "int"),
+        ("DEFS_FLAG", "unsigned int"),
+        ("DEFS_MASK", "unsigned int"),
+        ("DEFS_BIG", "long long"),
+        ("DEFS_LIMIT", "long"),
+        ("DEFS_RATIO", "float"),
+        ("DEFS_NAME", "const char*"),
+        ("DEFS_HANDLE", "defs_handle_t"),
+        ("DEFS_NULL", "void*"),
+        ("DEFS_STEP", "int"),
+        ("DEFS_SEPARATOR", "int"),
+    ];
+    assert_eq!(
+        types,
+        expected.iter().map(|(name, ty)| (name.to_string(), ty.to_string())).collect::<BTreeMap<_, _>>()
+    );
+
+    let square = &registry.macros[&"DEFS_SQUARE".interned()];
+    assert_eq!(square.params, ["x".interned()]);
+    assert_eq!(square.body.to_string(), "((x) * (x))");
+    let log = &registry.macros[&"DEFS_LOG".interned()];
+    assert_eq!(log.params, ["fmt".interned()]);
+    assert!(log.variadic);
+
+    let separator = &registry.constants[&"DEFS_SEPARATOR".interned()];
+    assert_eq!(separator.expr.to_string(), "'\\\\'");
+    assert_eq!(separator.platform, Some("any_arch-any_endian-windows-any_libc-[any]".parse().unwrap()));
+    let [other] = separator.platform_variants.as_slice() else { panic!("expected one variant") };
+    assert_eq!(other.expr.to_string(), "'/'");
+    assert_eq!(other.platform, Some("any_arch-any_endian-other_os-any_libc-[any]".parse().unwrap()));
+}
+
+fn spell(ty: &Type<'_>) -> String {
+    match ty {
+        Type::IdentifierType(ident) => ident.ident.original().to_string(),
+        Type::PointerType(ptr) if ptr.is_const => format!("const {}*", spell(&ptr.pointee)),
+        Type::PointerType(ptr) => format!("{}*", spell(&ptr.pointee)),
+        Type::ArrayType(array) => format!("{}[]", spell(&array.element)),
+    }
+}
+
+const DOC_HEADER: &str = r#"/** Largest size of a buffer. */
+#define DOC_MAX 64
+#define DOC_MIN 1 ///< Smallest size of a buffer.
+
+/* Not documentation */
+typedef int doc_size;
+
+/**
+ * Color of a pixel.
+ *
+ * Channels are stored in this order.
+ */
+typedef enum doc_color {
+    DOC_RED,   ///< Red channel.
+    /// Green channel.
+    DOC_GREEN,
+    DOC_BLUE
+} doc_color;
+
+/// A pixel.
+typedef struct doc_pixel {
+    /// Position of the pixel,
+    /// from the top left.
+    int x, y;
+    doc_color color; /**< Its color. */
+} doc_pixel;
+
+extern int doc_fill(
+    doc_pixel* pixel, ///< Pixel to fill.
+    doc_color color   ///< Color to fill with.
+); ///< Fill a pixel.
+"#;
+
+#[test]
+fn attach_doc_comments() {
+    let output = hparse(DOC_HEADER, &HparseConfig::default());
+    assert_eq!(output.defines[0].doc, ["Largest size of a buffer."]);
+    assert_eq!(output.defines[1].doc, ["Smallest size of a buffer."]);
+
+    let reader = DeclarationReader::new(&is_standard_type_name);
+    let (declarations, diagnostics) = output.parse_declarations("doc.h", DOC_HEADER, &reader);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    let mut registry = Registry::new("doc".to_string());
+    let messages = lower_declarations(registry.as_base_mut(), &declarations, &AnnotationTable::new());
+    assert!(messages.is_empty(), "{:?}", messages);
+    let skipped = lower_defines(registry.as_base_mut(), &output.defines, &is_standard_type_name);
+    assert!(skipped.is_empty(), "{:?}", skipped);
+
+    assert_eq!(registry.constants[&"DOC_MAX".interned()].doc.to_lines(), ["Largest size of a buffer."]);
+    assert!(registry.aliases[&"doc_size".interned()].doc.is_empty());
+
+    let color = &registry.enumerations[&"doc_color".interned()];
+    assert_eq!(color.doc.to_lines(), ["Color of a pixel.", "", "Channels are stored in this order."]);
+    let variants = color.variants.iter().map(|variant| variant.doc.to_lines()).collect::<Vec<_>>();
+    assert_eq!(variants, [vec!["Red channel."], vec!["Green channel."], vec![]]);
+
+    let pixel = &registry.structs[&"doc_pixel".interned()];
+    assert_eq!(pixel.doc.to_lines(), ["A pixel."]);
+    let members = pixel.members.iter().map(|member| member.doc.to_lines()).collect::<Vec<_>>();
+    let position = vec!["Position of the pixel, from the top left."];
+    assert_eq!(members, [position.clone(), position, vec!["Its color."]]);
+
+    let fill = &registry.commands[&"doc_fill".interned()];
+    assert_eq!(fill.doc.to_lines(), ["Fill a pixel."]);
+    let params = fill.params.iter().map(|param| param.doc.to_lines()).collect::<Vec<_>>();
+    assert_eq!(params, [vec!["Pixel to fill."], vec!["Color to fill with."]]);
+}
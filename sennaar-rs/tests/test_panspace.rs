@@ -0,0 +1,327 @@
+use lalrpop_util::ParseError;
+use sennaar::cpl::{CBinaryOp, CExpr, CTypeOperator, CUnaryOp, RawType};
+use sennaar::panspace::{
+    is_standard_type_name, AnnotationEffect, AnnotationTable, DeclarationReader, lower_declarations, parse_declarations, parse_expression,
+    parse_expression_with_types, Lexer, Token,
+};
+use sennaar::registry::{validate_registry, write_registry, CallingConvention, Entity, Metadata, Registry, Type};
+use sennaar::Internalize;
+
+fn lex(source: &str) -> Vec<String> {
+    Lexer::new(source)
+        .map(|token| {
+            let (start, token, end) = token.unwrap();
+            let kind = match token {
+                Token::Ident(_) => "ident",
+                Token::TypeName(_) => "type",
+                Token::Annotation(_) | Token::AnnotationCall(_) => "annotation",
+                Token::Keyword(_) => "keyword",
+                Token::IntLiteral(_) => "int",
+                Token::FloatLiteral(_) => "float",
+                Token::CharLiteral(_) => "char",
+                Token::StringLiteral(_) => "string",
+                Token::Punct(_) => "punct",
+            };
+            format!("{} {} {}..{}", kind, token, start, end)
+        })
+        .collect()
+}
+
+#[test]
+fn lex_tokens() {
+    let source = "foo->bar[0x1FuLL] /* c */ += sizeof(L'\\n') // tail\n\
+                  ... 1.5e-3f .5 0x1.8p3 u8\"a\\\"b\" \\\n'\\x41'";
+    assert_eq!(
+        lex(source),
+        [
+            "ident foo 0..3",
+            "punct -> 3..5",
+            "ident bar 5..8",
+            "punct [ 8..9",
+            "int 0x1FuLL 9..16",
+            "punct ] 16..17",
+            "punct += 26..28",
+            "keyword sizeof 29..35",
+            "punct ( 35..36",
+            "char L'\\n' 36..41",
+            "punct ) 41..42",
+            "punct ... 51..54",
+            "float 1.5e-3f 55..62",
+            "float .5 63..65",
+            "float 0x1.8p3 66..73",
+            "string u8\"a\\\"b\" 74..82",
+            "char '\\x41' 85..91",
+        ]
+    );
+}
+
+#[test]
+fn split_literal_suffixes() {
+    let tokens = Lexer::new("42ull 0777 2.0L").map(|t| t.unwrap().1).collect::<Vec<_>>();
+    let [Token::IntLiteral(a), Token::IntLiteral(b), Token::FloatLiteral(c)] = tokens.as_slice() else {
+        panic!("{:?}", tokens);
+    };
+    assert_eq!((a.value.as_ref(), a.suffix.as_ref()), ("42", "ull"));
+    assert_eq!((b.value.as_ref(), b.suffix.as_ref()), ("0777", ""));
+    assert_eq!((c.value.as_ref(), c.suffix.as_ref()), ("2.0", "L"));
+}
+
+#[test]
+fn report_lex_errors() {
+    let first_error = |source: &str| {
+        let e = Lexer::new(source).find_map(Result::err).unwrap();
+        (e.message, e.start, e.end)
+    };
+
+    assert_eq!(first_error("a + 12abc"), ("invalid suffix 'abc' on integer literal".to_string(), 4, 9));
+    assert_eq!(first_error("09"), ("invalid digit in octal literal '09'".to_string(), 0, 2));
+    assert_eq!(first_error("x = \"abc"), ("unterminated literal".to_string(), 4, 8));
+    assert_eq!(first_error("'\\q'"), ("unknown escape sequence '\\q'".to_string(), 1, 3));
+    assert_eq!(first_error("''"), ("empty character literal".to_string(), 0, 2));
+    assert_eq!(first_error("a @ b"), ("unexpected character '@'".to_string(), 2, 3));
+    assert_eq!(first_error("/* open"), ("unterminated block comment".to_string(), 0, 2));
+    assert_eq!(first_error("1e+"), ("exponent has no digits in '1e+'".to_string(), 0, 3));
+}
+
+#[test]
+fn parse_postfix_expressions() {
+    let expr = parse_expression("callbacks[1].table->fn(x, 'c', \"s\")++").unwrap();
+    assert_eq!(expr.to_string(), "(*callbacks[1].table).fn(x, 'c', \"s\")++");
+
+    assert!(parse_expression("a[1").is_err());
+    assert!(parse_expression("a + 1ux").is_err());
+}
+
+#[test]
+fn parse_operator_precedence() {
+    let parse = |source| parse_expression(source).unwrap().to_string();
+
+    assert_eq!(parse("a | b ^ c & d == e < f << g + h * i"), "a | b ^ c & d == e < f << g + h * i");
+    assert_eq!(parse("-1 + ~x * !y"), "-1 + ~x * !y");
+    assert_eq!(parse("c ? a : b ? 1 : 2"), "c ? a : b ? 1 : 2");
+    assert_eq!(parse("x = y += 1, z"), "x = y += 1 , z");
+    assert_eq!(parse("a && b || c"), "a && b || c");
+
+    let CExpr::Binary(sum) = parse_expression("1 - 2 - 3").unwrap() else { panic!() };
+    assert_eq!(sum.op, CBinaryOp::Sub);
+    assert!(matches!(&sum.lhs, CExpr::Binary(lhs) if lhs.op == CBinaryOp::Sub));
+
+    let CExpr::Binary(assign) = parse_expression("a = b = c").unwrap() else { panic!() };
+    assert!(matches!(&assign.rhs, CExpr::Binary(rhs) if rhs.op == CBinaryOp::Assign));
+
+    assert!(parse_expression("a ? b").is_err());
+    assert!(parse_expression("a + * ").is_err());
+}
+
+#[test]
+fn parse_casts_and_sizeof() {
+    let expr = parse_expression("(uint32_t)x << 2").unwrap();
+    let CExpr::Binary(shift) = &expr else { panic!("{}", expr) };
+    let CExpr::Cast(cast) = &shift.lhs else { panic!("{}", expr) };
+    assert_eq!(cast.ty.to_string(), "uint32_t");
+
+    let expr = parse_expression("(const struct Foo * const *)p").unwrap();
+    let CExpr::Cast(cast) = &expr else { panic!("{}", expr) };
+    assert_eq!(cast.ty.to_string(), "const Foo* const *");
+    let CExpr::Type(ty) = &cast.ty else { panic!("{}", expr) };
+    let Type::PointerType(pointer) = &ty.ty else { panic!("{}", expr) };
+    assert!(pointer.is_const && matches!(&pointer.pointee, Type::PointerType(pointee) if pointee.is_const));
+
+    // Abstract declarators, as in declarations
+    let expr = parse_expression("(void (*)(int))x").unwrap();
+    assert_eq!(expr.to_string(), "(void (*)(int)) x");
+    let expr = parse_expression("sizeof(int (*)[3])").unwrap();
+    let CExpr::TypeOperator(e) = &expr else { panic!("{}", expr) };
+    assert!(matches!(&e.ty, CExpr::Type(ty) if matches!(&ty.ty, Type::PointerType(pointer)
+        if matches!(&pointer.pointee, Type::ArrayType(_)))));
+    assert_eq!(expr.to_string(), "sizeof(int (*)[3])");
+
+    let expr = parse_expression("sizeof(unsigned long[4]) + sizeof x + _Alignof(double)").unwrap();
+    assert_eq!(expr.to_string(), "sizeof(unsigned long[4]) + sizeof(x) + alignof(double)");
+    let CExpr::Binary(sum) = &expr else { panic!("{}", expr) };
+    assert!(matches!(&sum.rhs, CExpr::TypeOperator(e) if e.op == CTypeOperator::AlignOf));
+    let CExpr::Binary(sum) = &sum.lhs else { panic!("{}", expr) };
+    assert!(matches!(&sum.rhs, CExpr::Unary(unary) if unary.op == CUnaryOp::SizeOf));
+
+    // Without knowing `VkFlags` is a type, this is a parenthesized identifier applied to `x`
+    assert!(parse_expression("(VkFlags)x").is_err());
+    let is_type = |name: &str| name == "VkFlags";
+    let expr = parse_expression_with_types("(VkFlags)-1", &is_type).unwrap();
+    assert!(matches!(&expr, CExpr::Cast(cast) if cast.ty.to_string() == "VkFlags"));
+}
+
+#[test]
+fn parse_declarators() {
+    let source = "typedef unsigned long long u64;\n\
+                  extern const char *const names[4], **(*lookup)(u64 key, ...);\n\
+                  void (*signal(int sig, void (*handler)(int)))(int);";
+    let declarations = parse_declarations(source, &is_standard_type_name).unwrap();
+    let types = declarations
+        .iter()
+        .flat_map(|declaration| {
+            declaration.declarators.iter().map(|init| {
+                let (name, ty) = init.declarator.resolve(RawType::from_specifiers(&declaration.specifiers));
+                ty.declare(name.unwrap().original())
+            })
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        [
+            "unsigned long long u64",
+            "const char* const names[4]",
+            "const char** (*lookup)(u64 key, ...)",
+            "void (*signal(int sig, void (*handler)(int)))(int)",
+        ]
+    );
+
+    let error = parse_declarations("int a;\nlong float b;", &is_standard_type_name).unwrap_err();
+    let ParseError::User { error } = error else { panic!("{:?}", error) };
+    assert_eq!((error.message.as_str(), error.start, error.end), ("invalid combination of type specifiers 'long float'", 7, 17));
+    assert!(parse_declarations("u64 x;", &is_standard_type_name).is_err());
+}
+
+#[test]
+fn lower_declarations_to_entities() {
+    let source = r#"
+        typedef struct Instance_T *Instance;
+        typedef struct Opaque Opaque;
+        typedef enum { RED, GREEN = 4, BLUE, ALIAS = GREEN, NEXT } Color;
+        enum Radix { OCTAL = 010, AFTER_OCTAL, HEX = 0x1F, AFTER_HEX, MAX = 0xFFFFFFFFFFFFFFFF, AFTER_MAX };
+        typedef struct Point { int x, y : 4; const struct Point *next; } Point;
+        union Value { float f; uint32_t bits[2]; };
+        typedef struct Point PointAlias;
+        typedef void (*Callback)(void *user, Color color);
+        typedef int Handler(void);
+        int draw(Instance instance, const Point *points, size_t count);
+        void copy(char * restrict dst, const char * const *src, volatile _Atomic int *flag);
+        typedef int * _Atomic AtomicPointer;
+        typedef char * const ConstPointer;
+        int print(const char *format, ...);
+        typedef void (*Logger)(int level, ...);
+        int counter;
+    "#;
+    let declarations = parse_declarations(source, &is_standard_type_name).unwrap();
+    let mut registry = Registry::new("test".to_string());
+    let messages = lower_declarations(registry.as_base_mut(), &declarations, &AnnotationTable::new());
+    assert_eq!(messages, ["variable 'counter' is not supported"]);
+
+    let dsl = write_registry(&registry);
+    for expected in [
+        "handle Instance;",
+        "opaque Opaque;",
+        "enum Color {\n    RED = 0,\n    GREEN = 4,\n    BLUE = 5,\n    ALIAS = GREEN,\n    NEXT = ALIAS + 1,\n}",
+        "    OCTAL = 010,\n    AFTER_OCTAL = 9,\n    HEX = 0x1F,\n    AFTER_HEX = 32,\n",
+        "    MAX = 0xFFFFFFFFFFFFFFFF,\n    AFTER_MAX = MAX + 1,\n",
+        "struct Point {\n    x: int,\n    @bits(4)\n    y: int,\n    next: *const Point,\n}",
+        "union Value {\n    f: float,\n    bits: [uint32_t; 2],\n}",
+        "alias PointAlias = Point;",
+        "@pointer\nfunctype Callback(\n    user: *void,\n    color: Color,\n) -> void;",
+        "functype Handler() -> int;",
+        "command draw(\n    instance: Instance,\n    points: *const Point,\n    count: size_t,\n) -> int;",
+        "command print(\n    format: *const char,\n    ...\n) -> int;",
+        "@pointer\nfunctype Logger(\n    level: int,\n    ...\n) -> void;",
+        "command copy(\n    dst: *restrict char,\n    src: *const *const char,\n    flag: *volatile _Atomic int,\n) -> void;",
+        "alias AtomicPointer = _Atomic *int;",
+        "alias ConstPointer = const *char;",
+    ] {
+        assert!(dsl.contains(expected), "missing `{}` in\n{}", expected, dsl);
+    }
+}
+
+#[test]
+fn lower_inline_prototypes() {
+    let source = r#"
+        typedef struct Visitor { void (*visit)(int depth, void (*leave)(void)); int (*counts[2])(void); } Visitor;
+        void extract_visit(void (*visitor)(const char *name), int (*(*lookup)(int))(char));
+        typedef void (**Hooks)(int);
+    "#;
+    let declarations = parse_declarations(source, &is_standard_type_name).unwrap();
+    let mut registry = Registry::new("test".to_string());
+    let messages = lower_declarations(registry.as_base_mut(), &declarations, &AnnotationTable::new());
+    assert!(messages.is_empty(), "{:?}", messages);
+    assert!(validate_registry(registry.as_base(), &[]).is_empty());
+
+    // Prototypes are named after where they are used, as rossetta names them
+    let mut names = registry.function_typedefs.keys().map(|name| name.original()).collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "Hooks_target", "Visitor_counts", "Visitor_visit", "Visitor_visit_leave", "extract_visit_lookup",
+            "extract_visit_lookup_result", "extract_visit_visitor",
+        ]
+    );
+    let dsl = write_registry(&registry);
+    for expected in [
+        "alias Hooks = *Hooks_target;",
+        "command extract_visit(\n    visitor: extract_visit_visitor,\n    lookup: extract_visit_lookup,\n) -> void;",
+        "@pointer\nfunctype extract_visit_lookup(\n    param0: int,\n) -> extract_visit_lookup_result;",
+        "struct Visitor {\n    visit: Visitor_visit,\n    counts: [Visitor_counts; 2],\n}",
+        "@pointer\nfunctype Visitor_visit(\n    depth: int,\n    leave: Visitor_visit_leave,\n) -> void;",
+    ] {
+        assert!(dsl.contains(expected), "missing `{}` in\n{}", expected, dsl);
+    }
+}
+
+#[test]
+fn lower_annotations() {
+    let mut annotations = AnnotationTable::sal();
+    annotations.extend(AnnotationTable::common());
+    annotations.extend(AnnotationTable::calling_conventions());
+    let annotations = annotations
+        .with("DEPRECATED", &[AnnotationEffect::Metadata("deprecated".to_string())])
+        .with("UNIQUE", &[AnnotationEffect::PointerToOne]);
+
+    let source = "typedef struct Buffer { NULLABLE const char *label; UNIQUE void *data; } Buffer;\n\
+                  DEPRECATED NULLABLE void *map(\n\
+                      _In_ const Buffer *buffer,\n\
+                      _Out_writes_(count) uint8_t *out,\n\
+                      size_t count,\n\
+                      void * NULLABLE user,\n\
+                      _In_opt_ int flags);\n\
+                  int __stdcall entry(void);\n\
+                  typedef void __stdcall (*PFN_cb)(int);\n\
+                  typedef void (__stdcall *PFN_x)(int);\n\
+                  typedef DEPRECATED void (*PFN_old)(void);";
+    let declarations = DeclarationReader::new(&is_standard_type_name)
+        .with_annotations(&annotations)
+        .read(source)
+        .unwrap();
+    let mut registry = Registry::new("test".to_string());
+    let messages = lower_declarations(registry.as_base_mut(), &declarations, &annotations);
+    assert_eq!(messages, ["command 'map' parameter 'flags': annotation '_In_opt_' applies to pointers only"]);
+    registry.sanitize();
+
+    let pointer = |ty: &Type| match ty {
+        Type::PointerType(ptr_type) => (ptr_type.nullable, ptr_type.pointer_to_one),
+        _ => panic!("not a pointer"),
+    };
+
+    let map = &registry.commands[&"map".interned()];
+    assert_eq!(pointer(&map.result), (true, false));
+    assert_eq!(map.try_get_metadata("deprecated"), Some(&Metadata::None));
+
+    let params = map.params.iter().map(|param| (param.name.original(), param.optional)).collect::<Vec<_>>();
+    assert_eq!(params, [("buffer", false), ("out", false), ("count", false), ("user", true), ("flags", true)]);
+    assert_eq!(pointer(&map.params[0].ty), (false, true));
+    assert_eq!(map.params[1].len.as_ref().unwrap().to_string(), "count");
+    let out = Metadata::String { value: "count".to_string() };
+    assert_eq!(map.params[1].try_get_metadata("out"), Some(&out));
+
+    let entry = &registry.commands[&"entry".interned()];
+    assert_eq!(entry.calling_convention, Some(CallingConvention::StdCall));
+    assert!(map.calling_convention.is_none());
+    for name in ["PFN_cb", "PFN_x"] {
+        let functype = &registry.function_typedefs[&name.interned()];
+        assert_eq!(functype.calling_convention, Some(CallingConvention::StdCall), "{}", name);
+    }
+    let old = &registry.function_typedefs[&"PFN_old".interned()];
+    assert!(old.calling_convention.is_none());
+    assert_eq!(old.try_get_metadata("deprecated"), Some(&Metadata::None));
+
+    let buffer = &registry.structs[&"Buffer".interned()];
+    assert!(buffer.members[0].optional);
+    assert_eq!(pointer(&buffer.members[0].ty), (true, false));
+    assert_eq!(pointer(&buffer.members[1].ty), (false, true));
+}
@@ -0,0 +1,349 @@
+use sennaar::registry::{
+    parse_registry, write_registry, CallingConvention, Doc, DocSpan, Metadata, Registry, Type, Typedef,
+};
+use sennaar::Internalize;
+
+#[test]
+fn parse_sample_registry() {
+    let source = std::fs::read_to_string("./tests/resources/sample.sennaar").unwrap();
+    let registry = parse_registry("sample.sennaar", &source).unwrap();
+    registry.sanitize();
+
+    assert_eq!(registry.name, "sample");
+    assert_eq!(registry.metadefs["vendor"], "club-doki7");
+    assert_eq!(registry.imports.len(), 2);
+    assert_eq!(registry.ext["generator"], "hand");
+
+    let result = &registry.enumerations[&"VkResult".interned()];
+    assert_eq!(result.doc.brief, [DocSpan::Text { value: "Result codes".to_string() }]);
+    assert!(result.doc.description.is_empty());
+    assert_eq!(result.metadata["khronos"], Metadata::None);
+    let variants = result.variants
+        .iter()
+        .map(|v| format!("{} = {}", v.name, v.value))
+        .collect::<Vec<_>>();
+    assert_eq!(variants, vec!["VK_SUCCESS = 0", "VK_NOT_READY = 1", "VK_ERROR_UNKNOWN = -13"]);
+
+    let flags = &registry.bitmasks[&"VkQueueFlags".interned()];
+    assert_eq!(format!("{}", flags.bitflags[2].value), "VK_QUEUE_GRAPHICS_BIT | VK_QUEUE_COMPUTE_BIT");
+
+    let whole_size = &registry.constants[&"VK_WHOLE_SIZE".interned()];
+    assert_eq!(format!("{}", whole_size.expr), "~0ULL");
+
+    assert_eq!("VkFlags".interned().value(), "Flags");
+
+    let surface = &registry.structs[&"VkWin32SurfaceCreateInfoKHR".interned()];
+    assert!(surface.platform.is_some());
+    assert_eq!(surface.members.len(), 5);
+    assert_eq!(surface.members[2].bits, Some(8));
+    assert!(surface.members[1].optional);
+    let Type::PointerType(items) = &surface.members[4].ty else {
+        panic!("expected pointer type");
+    };
+    assert!(items.pointer_to_one && items.is_const && !items.nullable);
+
+    let create = &registry.commands[&"vkCreateInstance".interned()];
+    assert_eq!(create.params.len(), 3);
+    assert_eq!(create.success_codes.len(), 2);
+    assert_eq!(create.alias_to, Some("vkCreateInstanceKHR".interned()));
+
+    let void_fn = &registry.function_typedefs[&"PFN_vkVoidFunction".interned()];
+    assert!(void_fn.is_pointer && void_fn.is_native_api);
+
+    let make_version = &registry.macros[&"VK_MAKE_API_VERSION".interned()];
+    let params = make_version.params.iter().map(|param| param.original()).collect::<Vec<_>>();
+    assert_eq!(params, ["variant", "major", "minor", "patch"]);
+    assert!(!make_version.variadic);
+    assert!(format!("{}", make_version.body).ends_with("| ((uint32_t) patch)"));
+}
+
+#[test]
+fn parse_structured_doc() {
+    let source = std::fs::read_to_string("./tests/resources/sample.sennaar").unwrap();
+    let registry = parse_registry("sample.sennaar", &source).unwrap();
+    let doc = &registry.commands[&"vkCreateInstance".interned()].doc;
+
+    let text = |value: &str| DocSpan::Text { value: value.to_string() };
+    assert_eq!(doc.brief, [text("Create a new Vulkan instance.")]);
+    assert_eq!(
+        doc.description,
+        [vec![text("Loads the layers and extensions listed in "), DocSpan::Code { value: "pCreateInfo".to_string() }, text(".")]]
+    );
+    assert_eq!(doc.params.len(), 2);
+    assert_eq!(doc.params[0].name, "pCreateInfo".interned());
+    assert_eq!(doc.params[1].text[1], DocSpan::Code { value: "VkInstance".to_string() });
+    assert_eq!(doc.returns[0], DocSpan::Link { target: "VK_SUCCESS".interned() });
+    assert_eq!(doc.deprecated.as_ref().unwrap()[1], DocSpan::Link { target: "vkCreateInstanceKHR".interned() });
+    assert_eq!(doc.see, ["vkDestroyInstance".interned()]);
+
+    let json = serde_json::to_value(doc).unwrap();
+    assert_eq!(json["returns"][0], serde_json::json!({ "$kind": "Link", "target": "VK_SUCCESS" }));
+
+    let doxygen = Doc::parse(&[
+        "\\brief Short.",
+        "",
+        "Longer, with \\c code and",
+        "two lines.",
+        "@param[in,out] x The x.",
+        "@returns Nothing.",
+        "@deprecated",
+    ]);
+    assert_eq!(doxygen.brief, [text("Short.")]);
+    assert_eq!(doxygen.description[0].len(), 3);
+    assert_eq!(doxygen.description[0][2], text(" and two lines."));
+    assert_eq!(doxygen.params[0].name, "x".interned());
+    assert_eq!(doxygen.returns, [text("Nothing.")]);
+    assert_eq!(doxygen.deprecated, Some(Vec::new()));
+    assert_eq!(Doc::parse(&doxygen.to_lines()), doxygen);
+
+    // Text and code that would otherwise read back as markup
+    let code = |value: &str| DocSpan::Code { value: value.to_string() };
+    let tricky = Doc {
+        brief: vec![text("@return a lone ` backtick, a literal @ref x and \\@c y")],
+        description: vec![vec![code("a b"), text(" and "), code("`x` \\ y"), text(" with a trailing \\"), code("z")]],
+        ..Doc::default()
+    };
+    assert_eq!(
+        tricky.to_lines(),
+        [
+            "\\@return a lone \\` backtick, a literal \\@ref x and \\\\@c y",
+            "",
+            "`a b` and `\\`x\\` \\\\ y` with a trailing \\\\`z`",
+        ]
+    );
+    assert_eq!(Doc::parse(&tricky.to_lines()), tricky);
+}
+
+#[test]
+fn read_doc_lines() {
+    // Registries written before the structured model store the comment lines
+    let typedef: Typedef = serde_json::from_value(serde_json::json!({
+        "name": "PFN_vkDestroyInstance",
+        "metadata": {},
+        "doc": ["Destroy an instance.", "", "@param instance The `VkInstance`."],
+        "platform": null,
+        "target": { "$kind": "IdentifierType", "ident": "void" },
+    }))
+    .unwrap();
+    assert_eq!(typedef.doc.brief, [DocSpan::Text { value: "Destroy an instance.".to_string() }]);
+    assert_eq!(typedef.doc.params[0].text[1], DocSpan::Code { value: "VkInstance".to_string() });
+}
+
+#[test]
+fn reject_misplaced_attribute() {
+    assert!(parse_registry("test.sennaar", "registry r; @bits(3) opaque Foo;").is_err());
+    assert!(parse_registry("test.sennaar", "registry r; opaque Foo; opaque Foo;").is_err());
+}
+
+#[test]
+fn round_trip_sample_registry() {
+    let source = std::fs::read_to_string("./tests/resources/sample.sennaar").unwrap();
+    let registry = parse_registry("sample.sennaar", &source).unwrap();
+
+    let written = write_registry(&registry);
+    let reparsed = parse_registry("written.sennaar", &written).unwrap_or_else(|e| panic!("{:?}\n{}", e, written));
+
+    assert_eq!(serde_json::to_value(&registry).unwrap(), serde_json::to_value(&reparsed).unwrap());
+    assert_eq!(written, write_registry(&reparsed));
+    assert!(written.contains("alias `VkFlags:Flags` = uint32_t;"));
+}
+
+#[test]
+fn round_trip_expressions() {
+    let source = r#"
+        registry exprs;
+        constant A: int = sizeof(int) + alignof x * -(-1);
+        constant B: int = a ? b, c : d ? e : f;
+        constant C: int = cast(`unsigned long`) p->q.r[3]++ - - --s;
+        constant D: int = (x = y += 1), f(g(), 'c', "str\n") && !~z;
+        constant E: int = *&v << 2 >> 1 != 0 == (1 < 2) | 3 ^ 4 & 5 || 0x1Fu >= 07;
+        macro LOG(level, ...) = log_message(level, __VA_ARGS__);
+        macro NOW() = clock();
+        constant F: int = sizeof_type(`struct Foo*`) * alignof_type(int) + offsetof(`struct Foo`, a.b[2]);
+        constant G: int = cast(*const [char; 4]) p == cast(?*restrict void) 0;
+    "#;
+    let registry = parse_registry("expressions.sennaar", source).unwrap();
+    let written = write_registry(&registry);
+    let reparsed = parse_registry("written.sennaar", &written).unwrap();
+
+    assert_eq!(serde_json::to_value(&registry).unwrap(), serde_json::to_value(&reparsed).unwrap());
+    assert!(written.contains("constant C: int = cast(`unsigned long`) p->q.r[3]++ - - --s;"));
+    assert!(written.contains("macro LOG(level, ...) = log_message(level, __VA_ARGS__);"));
+    assert!(written.contains("macro NOW() = clock();"));
+    assert!(written.contains(
+        "constant F: int = sizeof_type(`struct Foo*`) * alignof_type(int) + offsetof(`struct Foo`, a.b[2]);"
+    ));
+    assert!(written.contains("constant G: int = cast(*const [char; 4]) p == cast(?*restrict void) 0;"));
+}
+
+#[test]
+fn round_trip_platform_variants() {
+    let source = r#"
+        registry variants;
+        @platform("any_arch-any_endian-windows-any_libc-[any]")
+        alias Handle = *void;
+        @platform("any_arch-any_endian-other_os-any_libc-[any]")
+        alias Handle = int;
+    "#;
+    let registry = parse_registry("variants.sennaar", source).unwrap();
+    let written = write_registry(&registry);
+    let reparsed = parse_registry("written.sennaar", &written).unwrap();
+
+    assert_eq!(serde_json::to_value(&registry).unwrap(), serde_json::to_value(&reparsed).unwrap());
+    let handle = &registry.aliases[&"Handle".interned()];
+    assert_eq!(handle.target.to_string(), "void*");
+    assert_eq!(handle.platform_variants.len(), 1);
+    assert_eq!(handle.platform_variants[0].target.to_string(), "int");
+
+    // Only declarations for distinct platforms are variants
+    let windows = r#"@platform("any_arch-any_endian-windows-any_libc-[any]")"#;
+    let same = format!("registry r; {} handle Foo; {} handle Foo;", windows, windows);
+    assert!(parse_registry("same.sennaar", &same).is_err());
+    let unqualified = format!("registry r; {} opaque Foo; opaque Foo;", windows);
+    assert!(parse_registry("unqualified.sennaar", &unqualified).is_err());
+}
+
+#[test]
+fn round_trip_qualified_pointers() {
+    let source = r#"
+        registry qualifiers;
+        command copy(dst: *restrict char, src: *const *const char, flag: ?*volatile _Atomic int) -> void;
+        alias AtomicPointer = _Atomic *int;
+        alias ConstPointer = const ?*const char;
+    "#;
+    let registry = parse_registry("qualifiers.sennaar", source).unwrap();
+    let written = write_registry(&registry);
+    let reparsed = parse_registry("written.sennaar", &written).unwrap();
+
+    assert_eq!(serde_json::to_value(&registry).unwrap(), serde_json::to_value(&reparsed).unwrap());
+    let copy = &registry.commands[&"copy".interned()];
+    let Type::PointerType(src) = &copy.params[1].ty else { panic!("expected pointer type") };
+    let Type::PointerType(inner) = &src.pointee else { panic!("expected pointer type") };
+    // The qualifiers of the inner pointer are only held by the pointer to it
+    assert!(src.is_const && inner.is_const && !inner.is_pointer_const && !src.is_restrict && !src.is_pointer_const);
+    let Type::PointerType(flag) = &copy.params[2].ty else { panic!("expected pointer type") };
+    assert!(flag.nullable && flag.is_volatile && flag.is_atomic && !flag.is_const);
+    assert!(written.contains("dst: *restrict char,"));
+
+    // Qualifiers of the pointer itself, not of what it points to
+    let Type::PointerType(atomic) = &registry.aliases[&"AtomicPointer".interned()].target else { panic!() };
+    assert!(atomic.is_pointer_atomic && !atomic.is_atomic);
+    let Type::PointerType(constant) = &registry.aliases[&"ConstPointer".interned()].target else { panic!() };
+    assert!(constant.is_pointer_const && constant.is_const && constant.nullable);
+    assert!(written.contains("alias ConstPointer = const ?*const char;"));
+}
+
+#[test]
+fn round_trip_variadic_functions() {
+    let source = r#"
+        registry variadic;
+        @callconv(StdCall)
+        command printf(format: *const char, ...) -> int;
+        @pointer
+        functype PFN_log(...) -> void;
+    "#;
+    let registry = parse_registry("variadic.sennaar", source).unwrap();
+    let written = write_registry(&registry);
+    let reparsed = parse_registry("written.sennaar", &written).unwrap();
+
+    assert_eq!(serde_json::to_value(&registry).unwrap(), serde_json::to_value(&reparsed).unwrap());
+    let printf = &registry.commands[&"printf".interned()];
+    assert!(printf.variadic && printf.params.len() == 1);
+    assert_eq!(printf.calling_convention, Some(CallingConvention::StdCall));
+    let log = &registry.function_typedefs[&"PFN_log".interned()];
+    assert!(log.variadic && log.params.is_empty() && log.calling_convention.is_none());
+    assert!(written.contains("@callconv(StdCall)\ncommand printf(\n    format: *const char,\n    ...\n) -> int;"));
+    assert!(parse_registry("bad.sennaar", "registry r; @callconv(cdecl) command f() -> void;").is_err());
+}
+
+#[test]
+fn write_json_registry() {
+    let json = r#"{
+        "name": "from json",
+        "metadefs": {},
+        "imports": [],
+        "aliases": {},
+        "bitmasks": {},
+        "constants": {},
+        "commands": {
+            "sizeof": {
+                "name": "sizeof",
+                "metadata": {
+                    "nested": { "$kind": "KeyValues", "kvs": { "const": { "$kind": "String", "value": "a\"b" } } }
+                },
+                "doc": [],
+                "platform": null,
+                "params": [],
+                "result": { "$kind": "IdentifierType", "ident": "void" },
+                "successCodes": [],
+                "errorCodes": [],
+                "aliasTo": null
+            }
+        },
+        "enumerations": {},
+        "functionTypedefs": {},
+        "opaqueTypedefs": {},
+        "opaqueHandleTypedefs": {},
+        "structs": {},
+        "unions": {},
+        "ext": null
+    }"#;
+    let registry: Registry = serde_json::from_str(json).unwrap();
+    let written = write_registry(&registry);
+    assert_eq!(
+        written,
+        "registry \"from json\";\n\n@meta(nested = { \"const\" = \"a\\\"b\" })\ncommand `sizeof`() -> void;\n"
+    );
+
+    let reparsed = parse_registry("written.sennaar", &written).unwrap();
+    assert_eq!(serde_json::to_value(&registry).unwrap(), serde_json::to_value(&reparsed).unwrap());
+}
+
+#[test]
+fn round_trip_json_pointer_qualifiers() {
+    // Written before pointers had qualifiers of their own, with those of a pointee on its pointer
+    let json = r#"{
+        "name": "from json",
+        "metadefs": {},
+        "imports": [],
+        "aliases": {
+            "Names": {
+                "name": "Names",
+                "metadata": {},
+                "doc": [],
+                "platform": null,
+                "target": {
+                    "$kind": "PointerType",
+                    "pointee": {
+                        "$kind": "PointerType",
+                        "pointee": { "$kind": "IdentifierType", "ident": "char" },
+                        "isConst": true,
+                        "pointerToOne": false,
+                        "nullable": false
+                    },
+                    "isConst": true,
+                    "pointerToOne": false,
+                    "nullable": false
+                }
+            }
+        },
+        "bitmasks": {},
+        "constants": {},
+        "commands": {},
+        "enumerations": {},
+        "functionTypedefs": {},
+        "opaqueTypedefs": {},
+        "opaqueHandleTypedefs": {},
+        "structs": {},
+        "unions": {},
+        "ext": null
+    }"#;
+    let registry = Registry::from_json("names.json", json).unwrap();
+    let written = write_registry(&registry);
+    assert!(written.contains("alias Names = *const *const char;"), "{}", written);
+    assert_eq!(registry.aliases[&"Names".interned()].target.to_string(), "const char* const *");
+
+    let reparsed = parse_registry("written.sennaar", &written).unwrap();
+    assert_eq!(serde_json::to_value(&registry).unwrap(), serde_json::to_value(&reparsed).unwrap());
+    assert_eq!(written, write_registry(&reparsed));
+}
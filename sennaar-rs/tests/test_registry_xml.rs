@@ -0,0 +1,82 @@
+use sennaar::registry::{parse_registry, parse_registry_xml, write_registry_xml};
+
+#[test]
+fn round_trip_sample_registry() {
+    let source = std::fs::read_to_string("./tests/resources/sample.sennaar").unwrap();
+    let registry = parse_registry("sample.sennaar", &source).unwrap();
+
+    let written = write_registry_xml(&registry);
+    let reparsed = parse_registry_xml("sample.xml", &written).unwrap_or_else(|e| {
+        panic!("{}\n{}", e.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"), written)
+    });
+
+    assert_eq!(
+        serde_json::to_value(&registry).unwrap(),
+        serde_json::to_value(&reparsed).unwrap()
+    );
+    assert_eq!(written, write_registry_xml(&reparsed));
+}
+
+#[test]
+fn write_kinds_as_elements() {
+    let source = "registry r;\n\
+                  @meta(origin = { header = \"r.h\" })\n\
+                  alias `RFlags:Flags` = ?*const [char; N + 1];\n\
+                  ext \"{\\\"answer\\\": 42}\";\n";
+    let registry = parse_registry("test.sennaar", source).unwrap();
+    let written = write_registry_xml(&registry);
+
+    assert!(written.contains("<entry key=\"RFlags:Flags\" name=\"RFlags:Flags\">"), "{}", written);
+    assert!(written.contains(
+        "<PointerType isConst=\"true\" isVolatile=\"false\" isAtomic=\"false\" isRestrict=\"false\" \
+         isPointerConst=\"false\" isPointerVolatile=\"false\" isPointerAtomic=\"false\" \
+         pointerToOne=\"false\" nullable=\"true\">"
+    ));
+    assert!(written.contains("<Binary op=\"Add\">"));
+    assert!(written.contains("<IntLiteral value=\"1\" suffix=\"\"/>"));
+    assert!(written.contains("<KeyValues>"));
+    assert!(written.contains("<String value=\"r.h\"/>"));
+    assert!(written.contains("<ext>{&quot;answer&quot;:42}</ext>"));
+}
+
+#[test]
+fn collect_xml_errors() {
+    let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Registry name="r">
+  <imports>
+    <item name="base" depend="maybe"/>
+  </imports>
+  <metadefs/>
+  <aliases>
+    <entry key="A" name="A">
+      <metadata/>
+      <doc/>
+      <target>
+        <ArrayKind/>
+      </target>
+    </entry>
+  </aliases>
+  <bogus/>
+</Registry>
+"#;
+    let errors = parse_registry_xml("test.xml", source).unwrap_err();
+    let messages = errors.iter().map(|e| e.message.as_str()).collect::<Vec<_>>();
+
+    assert_eq!(
+        messages,
+        [
+            "expected boolean, found 'maybe'",
+            "unexpected element `<ArrayKind>`, expected one of `<IdentifierType>`, `<ArrayType>`, `<PointerType>`",
+            "unexpected element `<bogus>` in `<Registry>`",
+        ]
+    );
+    let lines = errors.iter().map(|e| e.location.as_ref().unwrap().line).collect::<Vec<_>>();
+    assert_eq!(lines, [4, 12, 16]);
+}
+
+#[test]
+fn reject_malformed_xml() {
+    let errors = parse_registry_xml("test.xml", "<Registry name=\"r\">\n  <imports>\n</Registry>\n").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].message.starts_with("malformed XML"), "{}", errors[0]);
+}
@@ -0,0 +1,32 @@
+use sennaar::registry::{parse_registry, validate_registry};
+
+#[test]
+fn report_unknown_references() {
+    let base = parse_registry("base.sennaar", "registry base; opaque Allocator;").unwrap();
+    let source = "registry r;\n\
+                  struct Info { next: *const void, size: size_t, kind: InfoKind }\n\
+                  @alias(createThingKHR)\n\
+                  command createThing(info: &const Info, @optional allocator: *Allocator) -> Result;\n";
+    let registry = parse_registry("r.sennaar", source).unwrap();
+
+    assert_eq!(
+        validate_registry(registry.as_base(), &[base.as_base()]),
+        [
+            "command 'createThing' parameter 'allocator': pointer nullability does not match its optional flag",
+            "command 'createThing' result: unknown type 'Result'",
+            "command 'createThing': alias of unknown command 'createThingKHR'",
+            "struct 'Info' member 'kind': unknown type 'InfoKind'",
+        ]
+    );
+
+    assert_eq!(
+        validate_registry(registry.as_base(), &[]),
+        [
+            "command 'createThing' parameter 'allocator': pointer nullability does not match its optional flag",
+            "command 'createThing' parameter 'allocator': unknown type 'Allocator'",
+            "command 'createThing' result: unknown type 'Result'",
+            "command 'createThing': alias of unknown command 'createThingKHR'",
+            "struct 'Info' member 'kind': unknown type 'InfoKind'",
+        ]
+    );
+}